@@ -0,0 +1,200 @@
+use std::{convert::TryInto, sync::Arc};
+
+use maybe_owned::MaybeOwned;
+use spartan_lib::core::{dispatcher::SimpleDispatcher, message::Message, payload::Identifiable};
+use warp::reply::{json, Json};
+
+use crate::{
+    actions::{QueueError, Result},
+    http::query::push::{PushRequest, PushResponse},
+    node::{dedup::DedupIndex, event::Event, queue::QueueCommit, Manager},
+};
+
+/// Split batch into IDs of all batch messages, and messages that are not duplicates
+///
+/// Messages are checked both against deduplication index and previous messages of the same batch.
+fn deduplicate(
+    dedup: &DedupIndex,
+    window: u32,
+    messages: Vec<Message>,
+) -> (Vec<PushResponse>, Vec<Message>) {
+    let mut ids = Vec::with_capacity(messages.len());
+    let mut unique: Vec<Message> = Vec::with_capacity(messages.len());
+
+    for message in messages {
+        let duplicate = message.dedup_id().and_then(|dedup_id| {
+            dedup.get(dedup_id, window).or_else(|| {
+                unique
+                    .iter()
+                    .find(|pushed| pushed.dedup_id() == Some(dedup_id))
+                    .map(Identifiable::id)
+            })
+        });
+
+        match duplicate {
+            Some(id) => ids.push(PushResponse::from(id)),
+            None => {
+                ids.push(PushResponse::from(message.id()));
+                unique.push(message);
+            }
+        }
+    }
+
+    (ids, unique)
+}
+
+/// Push batch of messages to queue.
+///
+/// Requires array of push requests, with the same fields as in single message push.
+///
+/// Whole batch is rejected if any of messages can't be composed.
+///
+/// Returns array of pushed message IDs. Duplicate messages are not enqueued, and IDs of the original messages are returned instead.
+pub async fn batch(
+    manager: Arc<Manager<'_>>,
+    name: String,
+    requests: Vec<PushRequest>,
+) -> Result<Json> {
+    let queue = manager.queue(&name)?;
+    let messages = requests
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<std::result::Result<Vec<Message>, _>>()
+        .map_err(QueueError::MessageCompose)?;
+
+    let mut database = queue.database().await;
+
+    let (ids, messages) = deduplicate(
+        &*queue.dedup().await,
+        manager.config().dedup_window,
+        messages,
+    );
+
+    let commit = if messages.is_empty() {
+        QueueCommit::done(queue)
+    } else {
+        queue
+            .log_event(
+                &name,
+                &manager,
+                Event::PushBatch(MaybeOwned::Borrowed(&messages)),
+            )
+            .await?
+    };
+
+    messages
+        .into_iter()
+        .for_each(|message| database.push(message));
+    drop(database);
+
+    commit.wait().await?;
+
+    Ok(json(&ids))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{
+        http::query::{
+            pop::test_response::TestPopResponse,
+            push::{PushRequest, PushResponse},
+            size::SizeResponse,
+        },
+        init_application, test_json_request, test_request,
+        utils::testing::CONFIG,
+    };
+
+    #[tokio::test]
+    async fn test_batch_push() {
+        let app = init_application!(&CONFIG);
+
+        test_request!(
+            app,
+            "POST",
+            "/test/batch",
+            &vec![
+                PushRequest {
+                    body: String::from("Hello").into_boxed_str(),
+                    ..Default::default()
+                },
+                PushRequest {
+                    body: String::from("world").into_boxed_str(),
+                    ..Default::default()
+                },
+            ]
+        )
+        .await;
+
+        let pop: Vec<TestPopResponse> = test_json_request!(app, "GET", "/test?count=3");
+
+        assert_eq!(pop.len(), 2);
+        assert_eq!(&*pop[0].body, "Hello");
+        assert_eq!(&*pop[1].body, "world");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_batch_push() {
+        let app = init_application!(&CONFIG);
+
+        let push = test_request!(
+            app,
+            "POST",
+            "/test/batch",
+            &vec![
+                PushRequest {
+                    body: String::from("Hello").into_boxed_str(),
+                    ..Default::default()
+                },
+                PushRequest {
+                    body: String::from("world").into_boxed_str(),
+                    offset: Some(86400),
+                    ..Default::default()
+                },
+            ]
+        )
+        .await;
+
+        assert_eq!(
+            *push.body(),
+            Bytes::from_static(b"Unable to compose message")
+        );
+
+        let size: SizeResponse = test_json_request!(app, "GET", "/test/size");
+        assert_eq!(size.size, 0);
+    }
+
+    #[tokio::test]
+    async fn test_dedup_batch_push() {
+        let app = init_application!(&CONFIG);
+
+        let request = |body: &str, dedup_id: &str| PushRequest {
+            body: String::from(body).into_boxed_str(),
+            dedup_id: Some(String::from(dedup_id).into_boxed_str()),
+            ..Default::default()
+        };
+
+        let first: Vec<PushResponse> = test_json_request!(
+            app,
+            "POST",
+            "/test/batch",
+            &vec![request("Hello", "first"), request("Hello", "first")]
+        );
+
+        assert_eq!(first[0].id, first[1].id);
+
+        let second: Vec<PushResponse> = test_json_request!(
+            app,
+            "POST",
+            "/test/batch",
+            &vec![request("Hello", "first"), request("world", "second")]
+        );
+
+        assert_eq!(second[0].id, first[0].id);
+        assert_ne!(second[1].id, first[0].id);
+
+        let size: SizeResponse = test_json_request!(app, "GET", "/test/size");
+        assert_eq!(size.size, 2);
+    }
+}
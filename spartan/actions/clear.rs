@@ -11,12 +11,22 @@ use crate::{
 /// Clear queue.
 ///
 /// Doesn't require any input, returns empty response.
+///
+/// Clearing empty queue is not logged.
 pub async fn clear(manager: Arc<Manager<'_>>, name: String) -> Result<Json> {
     let queue = manager.queue(&name)?;
 
-    queue.log_event(&name, &manager, Event::Clear).await?;
+    let mut database = queue.database().await;
+
+    if database.size() > 0 {
+        let commit = queue.log_event(&name, &manager, Event::Clear).await?;
+
+        database.clear();
+        drop(database);
+
+        commit.wait().await?;
+    }
 
-    queue.database().await.clear();
     Ok(json(&()))
 }
 
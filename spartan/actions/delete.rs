@@ -1,10 +1,10 @@
 use std::sync::Arc;
 
-use spartan_lib::core::dispatcher::PositionBasedDelete;
+use spartan_lib::core::{db::Database, dispatcher::PositionBasedDelete, payload::Status};
 use warp::reply::{json, Json};
 
 use crate::{
-    actions::{QueueError, Result},
+    actions::{check_receipt, QueueError, Result},
     http::query::delete::{DeleteRequest, DeleteResponse},
     node::{event::Event, Manager},
 };
@@ -12,22 +12,42 @@ use crate::{
 /// Delete message from queue.
 ///
 /// Requires ID of message being deleted, returns deleted message.
+///
+/// Receipt handle is required to delete reserved message. Messages, that are not reserved
+/// (for example, messages of dead letter queue), may be deleted without it.
+///
+/// Stale receipt handles (for example, from expired reservation) are rejected.
 pub async fn delete(
     manager: Arc<Manager<'_>>,
     name: String,
     request: DeleteRequest,
 ) -> Result<Json> {
     let queue = manager.queue(&name)?;
+    let mut database = queue.database().await;
+
+    match request.receipt {
+        Some(receipt) => check_receipt(&database, request.id, receipt)?,
+        None => {
+            let message = database
+                .get(request.id)
+                .ok_or(QueueError::MessageNotFound)?;
+
+            if message.requeueable() {
+                return Err(QueueError::MessageReserved.into());
+            }
+        }
+    }
 
-    queue
+    let commit = queue
         .log_event(&name, &manager, Event::Delete(request.id))
         .await?;
 
-    let message = queue
-        .database()
-        .await
+    let message = database
         .delete(request.id)
         .ok_or(QueueError::MessageNotFound)?;
+    drop(database);
+
+    commit.wait().await?;
 
     Ok(json(&DeleteResponse::from(message)))
 }
@@ -41,7 +61,7 @@ mod tests {
         http::query::{
             delete::{DeleteRequest, DeleteResponse},
             pop::test_response::TestPopResponse,
-            push::PushRequest,
+            push::{PushRequest, PushResponse},
             size::SizeResponse,
         },
         init_application, test_json_request, test_request,
@@ -55,7 +75,10 @@ mod tests {
             app,
             "DELETE",
             "/test",
-            &DeleteRequest { id: Uuid::new_v4() }
+            &DeleteRequest {
+                id: Uuid::new_v4(),
+                receipt: Some(Uuid::new_v4()),
+            }
         )
         .await;
         assert_eq!(*resp.body(), Bytes::from_static(b"Message not found"));
@@ -82,8 +105,15 @@ mod tests {
 
         assert_eq!(size.size, 1);
 
-        let delete: DeleteResponse =
-            test_json_request!(app, "DELETE", "/test", &DeleteRequest { id: pop.id });
+        let delete: DeleteResponse = test_json_request!(
+            app,
+            "DELETE",
+            "/test",
+            &DeleteRequest {
+                id: pop.id,
+                receipt: Some(pop.receipt),
+            }
+        );
 
         assert_eq!(delete.message.id(), pop.id);
 
@@ -91,4 +121,73 @@ mod tests {
 
         assert_eq!(size.size, 0);
     }
+
+    #[tokio::test]
+    async fn test_delete_without_receipt() {
+        let app = init_application!(&CONFIG);
+
+        let push: PushResponse = test_json_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                ..Default::default()
+            }
+        );
+
+        let delete: DeleteResponse = test_json_request!(
+            app,
+            "DELETE",
+            "/test",
+            &DeleteRequest {
+                id: push.id,
+                receipt: None,
+            }
+        );
+
+        assert_eq!(delete.message.id(), push.id);
+
+        let size: SizeResponse = test_json_request!(app, "GET", "/test/size");
+
+        assert_eq!(size.size, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reserved_delete_without_receipt() {
+        let app = init_application!(&CONFIG);
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        let resp = test_request!(
+            app,
+            "DELETE",
+            "/test",
+            &DeleteRequest {
+                id: pop.id,
+                receipt: None,
+            }
+        )
+        .await;
+
+        assert_eq!(
+            *resp.body(),
+            Bytes::from_static(b"Message is reserved, receipt handle is required")
+        );
+
+        let size: SizeResponse = test_json_request!(app, "GET", "/test/size");
+
+        assert_eq!(size.size, 1);
+    }
 }
@@ -1,6 +1,13 @@
 use std::{fmt::Display, result::Result as StdResult};
 
-use spartan_lib::core::message::builder::BuilderError;
+use spartan_lib::{
+    core::{
+        db::{Database, TreeDatabase},
+        message::{builder::BuilderError, Message},
+        payload::Identifiable,
+    },
+    uuid::Uuid,
+};
 use thiserror::Error as ThisError;
 use warp::{
     http::response::Builder,
@@ -9,6 +16,9 @@ use warp::{
     Reply,
 };
 
+/// Push batch of messages to queue
+pub mod batch;
+
 /// Clear queue
 pub mod clear;
 
@@ -21,12 +31,18 @@ pub mod pop;
 /// Push message to queue
 pub mod push;
 
+/// Redrive messages from dead letter queue
+pub mod redrive;
+
 /// Requeue message back
 pub mod requeue;
 
 /// Get queue size
 pub mod size;
 
+/// Extend reservation of message
+pub mod touch;
+
 pub type Result<T> = StdResult<T, ResponseError>;
 
 pub struct ResponseError {
@@ -67,8 +83,16 @@ pub enum QueueError {
     NoMessageAvailable,
     #[error("Message not found")]
     MessageNotFound,
+    #[error("Message is not reserved")]
+    MessageNotReserved,
+    #[error("Receipt handle doesn't match current message reservation")]
+    ReceiptMismatch,
+    #[error("Message is reserved, receipt handle is required")]
+    MessageReserved,
     #[error("Unable to compose message")]
     MessageCompose(#[from] BuilderError),
+    #[error("Dead letter queue is not configured")]
+    DeadLetterNotConfigured,
 }
 
 impl RespondableError for QueueError {
@@ -76,3 +100,18 @@ impl RespondableError for QueueError {
         StatusCode::NOT_FOUND
     }
 }
+
+/// Check if message is present in database, and is reserved with provided receipt handle
+fn check_receipt(
+    database: &TreeDatabase<Message>,
+    id: <Message as Identifiable>::Id,
+    receipt: Uuid,
+) -> StdResult<(), QueueError> {
+    let message = database.get(id).ok_or(QueueError::MessageNotFound)?;
+
+    if *message.state().receipt() == Some(receipt) {
+        Ok(())
+    } else {
+        Err(QueueError::ReceiptMismatch)
+    }
+}
@@ -1,35 +1,225 @@
-use std::sync::Arc;
+use std::{result::Result as StdResult, sync::Arc, time::Duration};
 
-use spartan_lib::core::dispatcher::StatusAwareDispatcher;
-use warp::reply::{json, Json};
+use spartan_lib::core::{
+    clock::Clock,
+    db::{Database, StatusAwareDatabase, TreeDatabase},
+    dispatcher::SimpleDispatcher,
+    message::Message,
+    payload::{Dispatchable, Identifiable, Status},
+};
+use tokio::{
+    sync::MutexGuard,
+    time::{timeout_at, Instant},
+};
+use warp::{
+    http::{header::CONTENT_TYPE, response::Builder},
+    hyper::Body,
+    reply::{json, Json, Response},
+};
 
 use crate::{
     actions::{QueueError, Result},
-    http::query::pop::PopResponse,
-    node::{event::Event, Manager},
+    http::query::pop::{PopRequest, PopResponse, RawPopRequest},
+    node::{
+        event::{Event, Reservation},
+        queue::QueueCommit,
+        Manager, DB,
+    },
 };
 
+/// Max amount of seconds, that pop request may wait for message
+const MAX_WAIT: u64 = 60;
+
+/// Header, that contains ID of message returned by raw pop
+const MESSAGE_ID_HEADER: &str = "X-Message-Id";
+
+/// Header, that contains receipt handle of message returned by raw pop
+const MESSAGE_RECEIPT_HEADER: &str = "X-Message-Receipt";
+
+/// Get time left until delay of first message in queue expires
+fn next_delay(database: &TreeDatabase<Message>) -> Option<Duration> {
+    let message = database.get(database.position(|_| true)?)?;
+
+    message
+        .time()
+        .delay()
+        .as_ref()?
+        .signed_duration_since(database.clock().now())
+        .to_std()
+        .ok()
+}
+
+/// Make reservations of up to `count` messages, that are available now
+fn reservations(database: &TreeDatabase<Message>, count: usize) -> Vec<Reservation> {
+    let now = database.clock().now();
+
+    database
+        .reservable_positions(
+            |message| message.reservable() && message.obtainable(&now),
+            count,
+        )
+        .into_iter()
+        .map(|id| Reservation::new(id, now))
+        .collect()
+}
+
+/// Reserve single available message
+async fn reserve<'a, 'q>(
+    manager: &Manager<'_>,
+    queue: &'q DB,
+    name: &str,
+    database: &'a mut TreeDatabase<Message>,
+) -> Result<(&'a Message, QueueCommit<'q, TreeDatabase<Message>>)> {
+    let reservation = reservations(database, 1)
+        .pop()
+        .ok_or(QueueError::NoMessageAvailable)?;
+
+    let commit = queue
+        .log_event(name, manager, Event::Pop(reservation))
+        .await?;
+
+    Event::Pop(reservation).apply(database);
+
+    let message = database
+        .get(reservation.id())
+        .ok_or(QueueError::NoMessageAvailable)?;
+
+    Ok((message, commit))
+}
+
+/// Lock queue database, waiting up to provided amount of seconds for message to become available.
+///
+/// Database stays locked while returned guard is alive.
+async fn wait_available(
+    queue: &DB,
+    wait: Option<u64>,
+) -> StdResult<MutexGuard<'_, TreeDatabase<Message>>, QueueError> {
+    let deadline = Instant::now() + Duration::from_secs(wait.unwrap_or(0).min(MAX_WAIT));
+
+    loop {
+        let database = queue.database().await;
+
+        if database.peek().is_some() {
+            return Ok(database);
+        }
+
+        let now = Instant::now();
+
+        if now >= deadline {
+            return Err(QueueError::NoMessageAvailable);
+        }
+
+        let wake_at = next_delay(&database)
+            .map(|delay| now + delay)
+            .filter(|wake_at| *wake_at < deadline)
+            .unwrap_or(deadline);
+
+        drop(database);
+
+        // Timeout is not an error here, as message availability is checked on next iteration
+        let _ = timeout_at(wake_at, queue.notified()).await;
+    }
+}
+
 /// Pop message from queue.
 ///
-/// Doesn't require any input, returns reserved message.
+/// Wait time in seconds is optional (max 60 seconds), returns reserved message.
+///
+/// If message count is provided, then up to count messages are reserved at once,
+/// and array of reserved messages is returned.
+///
+/// If wait time is provided, and there are no available messages in queue,
+/// then request waits for message to be pushed, requeued, or for message delay to expire.
+///
+/// Text bodies are returned as is, binary bodies are encoded with base64.
 ///
 /// After reserving message, you either need to return it to queue, or delete it.
 ///
 /// Messages that are not returned after timeout are deleted by GC.
-pub async fn pop(manager: Arc<Manager<'_>>, name: String) -> Result<Json> {
+pub async fn pop(manager: Arc<Manager<'_>>, name: String, request: PopRequest) -> Result<Json> {
     let queue = manager.queue(&name)?;
+    let mut database = wait_available(queue, request.wait).await?;
 
-    queue.log_event(&name, &manager, Event::Pop).await?;
+    let (response, commit) = match request.count {
+        Some(count) => {
+            let reservations = reservations(&database, count);
 
-    let mut database = queue.database().await;
-    let message = database.pop().ok_or(QueueError::NoMessageAvailable)?;
+            let commit = if reservations.is_empty() {
+                QueueCommit::done(queue)
+            } else {
+                let event = Event::PopBatch(reservations.clone());
+                let commit = queue.log_event(&name, &manager, event.clone()).await?;
+
+                event.apply(&mut *database);
+
+                commit
+            };
+
+            let response = json(
+                &reservations
+                    .iter()
+                    .filter_map(|reservation| database.get(reservation.id()))
+                    .map(PopResponse::from)
+                    .collect::<Vec<_>>(),
+            );
+
+            (response, commit)
+        }
+        None => {
+            let (message, commit) = reserve(&manager, queue, &name, &mut database).await?;
+
+            (json(&PopResponse::from(message)), commit)
+        }
+    };
 
-    Ok(json(&PopResponse::from(message)))
+    drop(database);
+
+    commit.wait().await?;
+
+    Ok(response)
+}
+
+/// Pop message from queue, returning its raw body.
+///
+/// Wait time in seconds is optional (max 60 seconds).
+///
+/// Response body is the message body, with `Content-Type` header set to message content type.
+/// Message ID and receipt handle are returned in `X-Message-Id` and `X-Message-Receipt` headers.
+pub async fn pop_raw(
+    manager: Arc<Manager<'_>>,
+    name: String,
+    request: RawPopRequest,
+) -> Result<Response> {
+    let queue = manager.queue(&name)?;
+    let mut database = wait_available(queue, request.wait).await?;
+
+    let (message, commit) = reserve(&manager, queue, &name, &mut database).await?;
+
+    let mut builder = Builder::default()
+        .header(CONTENT_TYPE, message.content_type())
+        .header(MESSAGE_ID_HEADER, message.id().to_string());
+
+    if let Some(receipt) = message.state().receipt() {
+        builder = builder.header(MESSAGE_RECEIPT_HEADER, receipt.to_string());
+    }
+
+    let response = builder
+        .body(Body::from(message.body().to_vec()))
+        .expect("Message content type is validated on compose");
+
+    drop(database);
+
+    commit.wait().await?;
+
+    Ok(response)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use bytes::Bytes;
+    use tokio::time::delay_for;
 
     use crate::{
         http::query::{pop::test_response::TestPopResponse, push::PushRequest},
@@ -62,4 +252,109 @@ mod tests {
         let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
         assert_eq!(&*pop.body, "Hello, world");
     }
+
+    #[tokio::test]
+    async fn test_wait_pop_timeout() {
+        let app = init_application!(&CONFIG);
+        let pop = test_request!(app, "GET", "/test?wait=1").await;
+        assert_eq!(*pop.body(), Bytes::from_static(b"No message available"));
+    }
+
+    #[tokio::test]
+    async fn test_wait_pop_push() {
+        let app = init_application!(&CONFIG);
+
+        let (pop, _) = tokio::join!(test_request!(app, "GET", "/test?wait=5"), async {
+            delay_for(Duration::from_millis(100)).await;
+
+            test_request!(
+                app,
+                "POST",
+                "/test",
+                &PushRequest {
+                    body: String::from("Hello, world").into_boxed_str(),
+                    ..Default::default()
+                }
+            )
+            .await
+        });
+
+        let pop: TestPopResponse = serde_json::from_slice(pop.body()).unwrap();
+        assert_eq!(&*pop.body, "Hello, world");
+    }
+
+    #[tokio::test]
+    async fn test_wait_pop_delay() {
+        let app = init_application!(&CONFIG);
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                delay: Some(1),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test?wait=5");
+        assert_eq!(&*pop.body, "Hello, world");
+    }
+
+    #[tokio::test]
+    async fn test_raw_pop() {
+        let app = init_application!(&CONFIG);
+
+        warp::test::request()
+            .method("POST")
+            .path("/test/raw")
+            .header("content-type", "image/png")
+            .body(vec![137, 80, 78, 71])
+            .reply(&app)
+            .await;
+
+        let pop = test_request!(app, "GET", "/test/raw").await;
+
+        assert_eq!(*pop.body(), Bytes::from_static(&[137, 80, 78, 71]));
+        assert_eq!(pop.headers()["content-type"], "image/png");
+        assert!(pop.headers().contains_key("x-message-id"));
+        assert!(pop.headers().contains_key("x-message-receipt"));
+
+        let pop = test_request!(app, "GET", "/test/raw").await;
+        assert_eq!(*pop.body(), Bytes::from_static(b"No message available"));
+    }
+
+    #[tokio::test]
+    async fn test_group_pop() {
+        let app = init_application!(&CONFIG);
+
+        for (body, group) in [
+            ("first", "customer"),
+            ("second", "customer"),
+            ("other", "other"),
+        ]
+        .iter()
+        {
+            test_request!(
+                app,
+                "POST",
+                "/test",
+                &PushRequest {
+                    body: String::from(*body).into_boxed_str(),
+                    group: Some(String::from(*group).into_boxed_str()),
+                    ..Default::default()
+                }
+            )
+            .await;
+        }
+
+        let pop: Vec<TestPopResponse> = test_json_request!(app, "GET", "/test?count=3");
+
+        assert_eq!(pop.len(), 2);
+        assert_eq!(&*pop[0].body, "first");
+        assert_eq!(pop[0].group.as_deref(), Some("customer"));
+        assert_eq!(&*pop[1].body, "other");
+    }
 }
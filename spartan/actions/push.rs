@@ -1,39 +1,94 @@
 use std::{convert::TryInto, sync::Arc};
 
+use bytes::Bytes;
 use maybe_owned::MaybeOwned;
-use spartan_lib::core::{dispatcher::SimpleDispatcher, message::Message};
+use spartan_lib::core::{dispatcher::SimpleDispatcher, message::Message, payload::Identifiable};
 use warp::reply::{json, Json};
 
 use crate::{
     actions::{QueueError, Result},
-    http::query::push::PushRequest,
+    http::query::push::{PushRequest, PushResponse, RawPushRequest},
     node::{event::Event, Manager},
 };
 
 /// Push message to queue.
 ///
-/// Requires message body. Offset, max tries, timeout, delay are optional.
+/// Requires message body. Content type, deduplication ID, group, offset, timezone, max tries, timeout, delay, delivery time, priority, attributes are optional.
 ///
-/// Returns empty response.
+/// Returns ID of pushed message.
+///
+/// If message with the same deduplication ID was pushed inside of deduplication window,
+/// then message is not enqueued, and ID of the original message is returned.
+///
+/// With log persistence, response is sent only after message reaches durability point of fsync policy.
 pub async fn push(manager: Arc<Manager<'_>>, name: String, request: PushRequest) -> Result<Json> {
-    let queue = manager.queue(&name)?;
     let message: Message = request.try_into().map_err(QueueError::MessageCompose)?;
 
-    queue
-        .log_event(&name, &manager, Event::Push(MaybeOwned::Borrowed(&message)))
+    push_message(&manager, &name, message).await
+}
+
+/// Push message with raw body to queue.
+///
+/// Request body is used as message body as is, and `Content-Type` header is used as message content type
+/// (`application/octet-stream` by default). Deduplication ID, group, offset, timezone, max tries, timeout, delay, delivery time,
+/// priority are optional query parameters.
+///
+/// Returns ID of pushed message, deduplicated the same way as in [`push`].
+pub async fn push_raw(
+    manager: Arc<Manager<'_>>,
+    name: String,
+    content_type: Option<String>,
+    request: RawPushRequest,
+    body: Bytes,
+) -> Result<Json> {
+    let message = request
+        .compose(content_type, body)
+        .map_err(QueueError::MessageCompose)?;
+
+    push_message(&manager, &name, message).await
+}
+
+async fn push_message(manager: &Manager<'_>, name: &str, message: Message) -> Result<Json> {
+    let queue = manager.queue(name)?;
+
+    let mut database = queue.database().await;
+
+    if let Some(dedup_id) = message.dedup_id() {
+        if let Some(id) = queue
+            .dedup()
+            .await
+            .get(dedup_id, manager.config().dedup_window)
+        {
+            return Ok(json(&PushResponse::from(id)));
+        }
+    }
+
+    let commit = queue
+        .log_event(name, manager, Event::Push(MaybeOwned::Borrowed(&message)))
         .await?;
 
-    queue.database().await.push(message);
+    let id = message.id();
+
+    database.push(message);
+    drop(database);
 
-    Ok(json(&()))
+    commit.wait().await?;
+
+    Ok(json(&PushResponse::from(id)))
 }
 
 #[cfg(test)]
 mod tests {
     use bytes::Bytes;
+    use chrono::{Duration, FixedOffset, Utc};
+    use spartan_lib::core::message::{Attributes, Timezone};
 
     use crate::{
-        http::query::{pop::test_response::TestPopResponse, push::PushRequest},
+        http::query::{
+            pop::{test_response::TestPopResponse, BodyEncoding},
+            push::{PushRequest, PushResponse},
+            size::SizeResponse,
+        },
         init_application, test_json_request, test_request,
         utils::testing::CONFIG,
     };
@@ -77,4 +132,230 @@ mod tests {
         let pop = test_request!(app, "GET", "/test").await;
         assert_eq!(*pop.body(), Bytes::from_static(b"No message available"));
     }
+
+    #[tokio::test]
+    async fn test_prioritized_push() {
+        let app = init_application!(&CONFIG);
+
+        for (body, priority) in [("Bulk", None), ("Urgent", Some(10))].iter() {
+            test_request!(
+                app,
+                "POST",
+                "/test",
+                &PushRequest {
+                    body: String::from(*body).into_boxed_str(),
+                    priority: *priority,
+                    ..Default::default()
+                }
+            )
+            .await;
+        }
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+        assert_eq!(&*pop.body, "Urgent");
+    }
+
+    #[tokio::test]
+    async fn test_push_attributes() {
+        let app = init_application!(&CONFIG);
+
+        let mut attributes = Attributes::new();
+        attributes.insert("trace".into(), "abc".into());
+        attributes.insert("retries".into(), 3.into());
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                attributes: Some(attributes.clone()),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        assert_eq!(pop.attributes, attributes);
+    }
+
+    #[tokio::test]
+    async fn test_push_content_type() {
+        let app = init_application!(&CONFIG);
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("{}").into_boxed_str(),
+                content_type: Some(String::from("application/json").into_boxed_str()),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        assert_eq!(&*pop.content_type, "application/json");
+        assert_eq!(pop.encoding, BodyEncoding::Utf8);
+    }
+
+    #[tokio::test]
+    async fn test_push_raw() {
+        let app = init_application!(&CONFIG);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .path("/test/raw?priority=1")
+            .header("content-type", "application/x-protobuf")
+            .body(vec![0, 159, 146, 150])
+            .reply(&app)
+            .await;
+
+        let push: PushResponse = serde_json::from_slice(resp.body()).unwrap();
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        assert_eq!(pop.id, push.id);
+        assert_eq!(&*pop.content_type, "application/x-protobuf");
+        assert_eq!(pop.encoding, BodyEncoding::Base64);
+        assert_eq!(base64::decode(&*pop.body).unwrap(), [0, 159, 146, 150]);
+    }
+
+    #[tokio::test]
+    async fn test_push_raw_default_content_type() {
+        let app = init_application!(&CONFIG);
+
+        warp::test::request()
+            .method("POST")
+            .path("/test/raw")
+            .body("Hello, world")
+            .reply(&app)
+            .await;
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        assert_eq!(&*pop.content_type, "application/octet-stream");
+        assert_eq!(&*pop.body, "Hello, world");
+    }
+
+    #[tokio::test]
+    async fn test_dedup_push() {
+        let app = init_application!(&CONFIG);
+
+        let request = PushRequest {
+            body: String::from("Hello, world").into_boxed_str(),
+            dedup_id: Some(String::from("order").into_boxed_str()),
+            ..Default::default()
+        };
+
+        let first: PushResponse = test_json_request!(app, "POST", "/test", &request);
+        let second: PushResponse = test_json_request!(app, "POST", "/test", &request);
+
+        assert_eq!(first.id, second.id);
+
+        let size: SizeResponse = test_json_request!(app, "GET", "/test/size");
+        assert_eq!(size.size, 1);
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+        assert_eq!(pop.id, first.id);
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_push() {
+        let app = init_application!(&CONFIG);
+
+        let deliver_at =
+            (Utc::now() + Duration::seconds(1)).with_timezone(&FixedOffset::east(7200));
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                deliver_at: Some(deliver_at),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let pop = test_request!(app, "GET", "/test").await;
+        assert_eq!(*pop.body(), Bytes::from_static(b"No message available"));
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test?wait=5");
+        assert_eq!(
+            pop.time.deliver_at.unwrap().timestamp(),
+            deliver_at.timestamp()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timezone_push() {
+        let app = init_application!(&CONFIG);
+
+        // Timezone is loaded from host timezone database, that may be not available
+        if let Some(timezone) = Timezone::new("Europe/Kyiv") {
+            test_request!(
+                app,
+                "POST",
+                "/test",
+                &PushRequest {
+                    body: String::from("Hello, world").into_boxed_str(),
+                    timezone: Some(String::from("Europe/Kyiv").into_boxed_str()),
+                    ..Default::default()
+                }
+            )
+            .await;
+
+            let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+            assert_eq!(pop.time.timezone.as_deref(), Some("Europe/Kyiv"));
+            assert_eq!(
+                pop.time.dispatched_at.offset(),
+                &timezone.offset_at(&pop.time.dispatched_at)
+            );
+        }
+
+        let resp = test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                timezone: Some(String::from("Europe/Atlantis").into_boxed_str()),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        assert_eq!(
+            *resp.body(),
+            Bytes::from_static(b"Unable to compose message")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_past_scheduled_push() {
+        let app = init_application!(&CONFIG);
+
+        let resp = test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                deliver_at: Some((Utc::now() - Duration::hours(1)).into()),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        assert_eq!(
+            *resp.body(),
+            Bytes::from_static(b"Unable to compose message")
+        );
+    }
 }
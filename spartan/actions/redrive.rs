@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use maybe_owned::MaybeOwned;
+use spartan_lib::core::{
+    db::Database,
+    dispatcher::{PositionBasedDelete, SimpleDispatcher},
+};
+use warp::reply::{json, Json};
+
+use crate::{
+    actions::{QueueError, Result},
+    http::query::redrive::RedriveResponse,
+    node::{event::Event, queue::QueueCommit, Manager},
+};
+
+/// Redrive messages from dead letter queue.
+///
+/// Doesn't require any input, moves all messages from dead letter queue
+/// back to queue with reset state, and returns amount of redriven messages.
+///
+/// Each message is deleted from dead letter queue only after its push is committed,
+/// so interrupted redrive may leave a copy of message in dead letter queue, but never loses it.
+pub async fn redrive(manager: Arc<Manager<'_>>, name: String) -> Result<Json> {
+    let queue = manager.queue(&name)?;
+
+    let dead_letter = manager
+        .config()
+        .dead_letter(&name)
+        .ok_or(QueueError::DeadLetterNotConfigured)?;
+    let dead_letter_queue = manager.queue(dead_letter)?;
+
+    let ids = dead_letter_queue.database().await.positions(|_| true);
+    let mut redriven = 0;
+
+    for id in ids {
+        let mut message = match dead_letter_queue.database().await.get(id) {
+            Some(message) => message.clone(),
+            None => continue,
+        };
+
+        message.reset();
+
+        let commit = {
+            let mut database = queue.database().await;
+
+            let commit = queue
+                .log_event(&name, &manager, Event::Push(MaybeOwned::Borrowed(&message)))
+                .await?;
+
+            database.push(message);
+
+            commit
+        };
+
+        commit.wait().await?;
+
+        let commit = {
+            let mut database = dead_letter_queue.database().await;
+
+            if database.get(id).is_some() {
+                let commit = dead_letter_queue
+                    .log_event(dead_letter, &manager, Event::Delete(id))
+                    .await?;
+
+                database.delete(id);
+
+                commit
+            } else {
+                QueueCommit::done(dead_letter_queue)
+            }
+        };
+
+        commit.wait().await?;
+
+        redriven += 1;
+    }
+
+    Ok(json(&RedriveResponse::from(redriven)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use once_cell::sync::Lazy;
+    use spartan_lib::core::{
+        clock::SystemClock, dispatcher::SimpleDispatcher, message::builder::MessageBuilder,
+        payload::Status,
+    };
+
+    use crate::{
+        config::Config,
+        http::query::{pop::test_response::TestPopResponse, redrive::RedriveResponse},
+        init_application, init_application_from_data,
+        node::Manager,
+        test_json_request, test_request,
+        utils::testing::CONFIG,
+    };
+
+    static DEAD_LETTER_CONFIG: Lazy<Config> = Lazy::new(|| Config {
+        dead_letter: Some(
+            [(
+                String::from("test").into_boxed_str(),
+                String::from("test_2").into_boxed_str(),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        ),
+        ..Default::default()
+    });
+
+    #[tokio::test]
+    async fn test_redrive_not_configured() {
+        let app = init_application!(&CONFIG);
+        let resp = test_request!(app, "POST", "/test/redrive").await;
+        assert_eq!(
+            *resp.body(),
+            Bytes::from_static(b"Dead letter queue is not configured")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redrive() {
+        let manager = Arc::new(Manager::new(&DEAD_LETTER_CONFIG));
+        let app = init_application_from_data!(manager.clone());
+
+        let mut message = MessageBuilder::default()
+            .body("Hello, world")
+            .compose()
+            .unwrap();
+
+        message.reserve(&SystemClock);
+        message.requeue();
+
+        manager
+            .queue("test_2")
+            .unwrap()
+            .database()
+            .await
+            .push(message);
+
+        let redrive: RedriveResponse = test_json_request!(app, "POST", "/test/redrive");
+        assert_eq!(redrive.redriven, 1);
+
+        assert_eq!(manager.queue("test_2").unwrap().database().await.size(), 0);
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+        assert_eq!(&*pop.body, "Hello, world");
+    }
+}
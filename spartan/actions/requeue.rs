@@ -1,17 +1,25 @@
 use std::sync::Arc;
 
-use spartan_lib::core::dispatcher::StatusAwareDispatcher;
+use spartan_lib::core::{
+    clock::Clock,
+    db::Database,
+    payload::{Dispatchable, Status},
+};
 use warp::reply::{json, Json};
 
 use crate::{
-    actions::{QueueError, Result},
+    actions::{check_receipt, QueueError, Result},
     http::query::requeue::RequeueRequest,
     node::{event::Event, Manager},
 };
 
 /// Requeues message back to queue.
 ///
-/// Requires ID of message being requeued, returns empty response.
+/// Requires ID and receipt handle of message being requeued, returns empty response.
+///
+/// Stale receipt handles (for example, from expired reservation) are rejected.
+///
+/// Delay in seconds is optional. If delay is not provided, then queue backoff policy is used (if configured).
 ///
 /// Message try counter is incremented.
 pub async fn requeue(
@@ -21,32 +29,83 @@ pub async fn requeue(
 ) -> Result<Json> {
     let queue = manager.queue(&name)?;
 
-    queue
-        .log_event(&name, &manager, Event::Requeue(request.id))
-        .await?;
+    let mut database = queue.database().await;
+
+    check_receipt(&database, request.id, request.receipt)?;
+
+    let delay = request.delay.or_else(|| {
+        let tries = *database.get(request.id)?.state().tries();
 
-    queue
-        .database()
-        .await
-        .requeue(request.id)
+        manager
+            .config()
+            .backoff(&name)
+            .map(|backoff| backoff.delay(tries))
+    });
+
+    let now = database.clock().now();
+
+    database
+        .get(request.id)
+        .filter(|message| message.requeueable() && message.obtainable(&now))
         .ok_or(QueueError::MessageNotFound)?;
 
+    let event = if let Some(delay) = delay {
+        Event::RequeueDelayed(request.id, delay, now)
+    } else {
+        Event::Requeue(request.id)
+    };
+
+    let commit = queue.log_event(&name, &manager, event.clone()).await?;
+
+    event.apply(&mut *database);
+    drop(database);
+
+    commit.wait().await?;
+
     Ok(json(&()))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use bytes::Bytes;
+    use once_cell::sync::Lazy;
+    use spartan_lib::core::{db::Database, payload::Dispatchable};
     use uuid::Uuid;
 
     use crate::{
+        config::{
+            backoff::{Backoff, BackoffPolicy},
+            Config,
+        },
         http::query::{
             pop::test_response::TestPopResponse, push::PushRequest, requeue::RequeueRequest,
         },
-        init_application, test_json_request, test_request,
+        init_application, init_application_from_data,
+        node::Manager,
+        test_json_request, test_request,
         utils::testing::CONFIG,
     };
 
+    static BACKOFF_CONFIG: Lazy<Config> = Lazy::new(|| Config {
+        backoff: Some(
+            [(
+                String::from("test").into_boxed_str(),
+                Backoff {
+                    policy: BackoffPolicy::Fixed,
+                    delay: 900,
+                    cap: None,
+                    jitter: false,
+                },
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        ),
+        ..Default::default()
+    });
+
     #[tokio::test]
     async fn test_empty_requeue() {
         let app = init_application!(&CONFIG);
@@ -54,7 +113,11 @@ mod tests {
             app,
             "POST",
             "/test/requeue",
-            &RequeueRequest { id: Uuid::new_v4() }
+            &RequeueRequest {
+                id: Uuid::new_v4(),
+                receipt: Uuid::new_v4(),
+                ..Default::default()
+            }
         )
         .await;
 
@@ -83,7 +146,11 @@ mod tests {
             app,
             "POST",
             "/test/requeue",
-            &RequeueRequest { id: first_pop.id }
+            &RequeueRequest {
+                id: first_pop.id,
+                receipt: first_pop.receipt,
+                ..Default::default()
+            }
         )
         .await;
 
@@ -95,7 +162,11 @@ mod tests {
             app,
             "POST",
             "/test/requeue",
-            &RequeueRequest { id: second_pop.id }
+            &RequeueRequest {
+                id: second_pop.id,
+                receipt: second_pop.receipt,
+                ..Default::default()
+            }
         )
         .await;
 
@@ -106,4 +177,179 @@ mod tests {
             Bytes::from_static(b"No message available")
         );
     }
+
+    #[tokio::test]
+    async fn test_stale_receipt_requeue() {
+        let app = init_application!(&CONFIG);
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                max_tries: Some(2),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let first_pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        test_request!(
+            app,
+            "POST",
+            "/test/requeue",
+            &RequeueRequest {
+                id: first_pop.id,
+                receipt: first_pop.receipt,
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let second_pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        let resp = test_request!(
+            app,
+            "POST",
+            "/test/requeue",
+            &RequeueRequest {
+                id: first_pop.id,
+                receipt: first_pop.receipt,
+                ..Default::default()
+            }
+        )
+        .await;
+
+        assert_eq!(
+            *resp.body(),
+            Bytes::from_static(b"Receipt handle doesn't match current message reservation")
+        );
+
+        let resp = test_request!(
+            app,
+            "POST",
+            "/test/requeue",
+            &RequeueRequest {
+                id: second_pop.id,
+                receipt: second_pop.receipt,
+                ..Default::default()
+            }
+        )
+        .await;
+
+        assert_eq!(*resp.body(), Bytes::from_static(b"null"));
+    }
+
+    #[tokio::test]
+    async fn test_delayed_requeue() {
+        let app = init_application!(&CONFIG);
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                max_tries: Some(2),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        test_request!(
+            app,
+            "POST",
+            "/test/requeue",
+            &RequeueRequest {
+                id: pop.id,
+                receipt: pop.receipt,
+                delay: Some(900),
+            }
+        )
+        .await;
+
+        let pop = test_request!(app, "GET", "/test").await;
+
+        assert_eq!(*pop.body(), Bytes::from_static(b"No message available"));
+    }
+
+    #[tokio::test]
+    async fn test_backoff_requeue() {
+        let manager = Arc::new(Manager::new(&BACKOFF_CONFIG));
+        let app = init_application_from_data!(manager.clone());
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                max_tries: Some(2),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        test_request!(
+            app,
+            "POST",
+            "/test/requeue",
+            &RequeueRequest {
+                id: pop.id,
+                receipt: pop.receipt,
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let database = manager.queue("test").unwrap().database().await;
+        let message = database.get(pop.id).unwrap();
+
+        assert!(!message.obtainable(database.clock()));
+    }
+
+    #[cfg(feature = "replication")]
+    #[tokio::test]
+    async fn test_failed_requeue_not_logged() {
+        use crate::node::replication::{
+            primary::storage::PrimaryStorage, storage::ReplicationStorage,
+        };
+
+        let manager = Arc::new(Manager::new(&CONFIG));
+
+        manager
+            .node()
+            .prepare_replication(
+                |_| false,
+                || ReplicationStorage::Primary(PrimaryStorage::default()),
+            )
+            .await;
+
+        let app = init_application_from_data!(manager.clone());
+
+        test_request!(
+            app,
+            "POST",
+            "/test/requeue",
+            &RequeueRequest {
+                id: Uuid::new_v4(),
+                receipt: Uuid::new_v4(),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        test_request!(app, "GET", "/test?count=2").await;
+
+        let mut storage = manager.queue("test").unwrap().replication_storage().await;
+        let log = storage.as_mut().unwrap().get_primary().slice(1).unwrap();
+
+        assert!(log.is_empty());
+    }
 }
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use spartan_lib::core::{
+    clock::Clock,
+    db::Database,
+    payload::{Dispatchable, Status},
+};
+use warp::reply::{json, Json};
+
+use crate::{
+    actions::{check_receipt, QueueError, Result},
+    http::query::touch::TouchRequest,
+    node::{event::Event, Manager},
+};
+
+/// Extend reservation of message.
+///
+/// Requires ID and receipt handle of reserved message and new timeout in seconds, returns empty response.
+///
+/// Message timeout is restarted from current time, using new timeout value.
+pub async fn touch(manager: Arc<Manager<'_>>, name: String, request: TouchRequest) -> Result<Json> {
+    let queue = manager.queue(&name)?;
+    let mut database = queue.database().await;
+
+    let now = database.clock().now();
+
+    let message = database
+        .get(request.id)
+        .ok_or(QueueError::MessageNotFound)?;
+
+    if !(message.requeueable() && message.obtainable(&now)) {
+        return Err(QueueError::MessageNotReserved.into());
+    }
+
+    check_receipt(&database, request.id, request.receipt)?;
+
+    let event = Event::Touch(request.id, request.timeout, now);
+
+    let commit = queue.log_event(&name, &manager, event.clone()).await?;
+
+    event.apply(&mut *database);
+    drop(database);
+
+    commit.wait().await?;
+
+    Ok(json(&()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use chrono::{Duration, Utc};
+    use spartan_lib::core::{
+        clock::MockClock,
+        db::Database,
+        dispatcher::SimpleDispatcher,
+        message::builder::MessageBuilder,
+        payload::{Dispatchable, Identifiable, Status},
+    };
+    use uuid::Uuid;
+
+    use crate::{
+        http::query::{
+            pop::test_response::TestPopResponse, push::PushRequest, requeue::RequeueRequest,
+            touch::TouchRequest,
+        },
+        init_application, init_application_from_data,
+        node::Manager,
+        test_json_request, test_request,
+        utils::testing::CONFIG,
+    };
+
+    #[tokio::test]
+    async fn test_touch_nonexistent() {
+        let app = init_application!(&CONFIG);
+
+        let touch = test_request!(
+            app,
+            "POST",
+            "/test/touch",
+            &TouchRequest {
+                id: Uuid::new_v4(),
+                receipt: Uuid::new_v4(),
+                timeout: 60,
+            }
+        )
+        .await;
+
+        assert_eq!(*touch.body(), Bytes::from_static(b"Message not found"));
+    }
+
+    #[tokio::test]
+    async fn test_touch() {
+        let manager = Arc::new(Manager::new(&CONFIG));
+        let app = init_application_from_data!(manager.clone());
+
+        // Message was reserved 20 seconds ago, so its reservation expires in 10 seconds
+        let clock = MockClock::new(Utc::now() - Duration::seconds(20));
+        let receipt = Uuid::new_v4();
+
+        let mut message = MessageBuilder::default()
+            .body("Hello, world")
+            .timeout(30)
+            .compose_with_clock(&clock)
+            .unwrap();
+
+        message.reserve_with_receipt(receipt, &clock);
+
+        let id = message.id();
+        let obtained_at = *message.time().timeout().obtained_at();
+
+        manager
+            .queue("test")
+            .unwrap()
+            .database()
+            .await
+            .push(message);
+
+        let touch = test_request!(
+            app,
+            "POST",
+            "/test/touch",
+            &TouchRequest {
+                id,
+                receipt,
+                timeout: 60,
+            }
+        )
+        .await;
+
+        assert_eq!(*touch.body(), Bytes::from_static(b"null"));
+
+        let database = manager.queue("test").unwrap().database().await;
+        let message = database.get(id).unwrap();
+
+        assert_eq!(*message.time().timeout().max(), 60);
+        assert!(*message.time().timeout().obtained_at() > obtained_at);
+
+        // Message survives past original expiry
+        let original_expiry = Utc::now() + Duration::seconds(15);
+        assert!(!message.expired(&original_expiry));
+        assert!(message.obtainable(&original_expiry));
+    }
+
+    #[tokio::test]
+    async fn test_touch_not_reserved() {
+        let app = init_application!(&CONFIG);
+
+        test_request!(
+            app,
+            "POST",
+            "/test",
+            &PushRequest {
+                body: String::from("Hello, world").into_boxed_str(),
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let pop: TestPopResponse = test_json_request!(app, "GET", "/test");
+
+        test_request!(
+            app,
+            "POST",
+            "/test/requeue",
+            &RequeueRequest {
+                id: pop.id,
+                receipt: pop.receipt,
+                ..Default::default()
+            }
+        )
+        .await;
+
+        let touch = test_request!(
+            app,
+            "POST",
+            "/test/touch",
+            &TouchRequest {
+                id: pop.id,
+                receipt: pop.receipt,
+                timeout: 60,
+            }
+        )
+        .await;
+
+        assert_eq!(
+            *touch.body(),
+            Bytes::from_static(b"Message is not reserved")
+        );
+    }
+}
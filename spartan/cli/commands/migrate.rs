@@ -0,0 +1,41 @@
+use structopt::StructOpt;
+use thiserror::Error;
+
+use crate::{
+    cli::Server,
+    node::persistence::{migration::migrate, PersistenceError},
+};
+
+#[derive(Error, Debug)]
+pub enum MigrateCommandError {
+    #[error("Unable to load configuration file")]
+    ConfigFileError,
+    #[error("Persistence error: {0}")]
+    PersistenceError(PersistenceError),
+}
+
+#[derive(StructOpt)]
+pub struct MigrateCommand {}
+
+impl MigrateCommand {
+    pub async fn dispatch(&self, server: &Server) -> Result<(), MigrateCommandError> {
+        let config = server
+            .config()
+            .ok_or(MigrateCommandError::ConfigFileError)?;
+
+        if config.persistence.is_none() {
+            info!("Persistence is disabled, nothing to migrate.");
+            return Ok(());
+        }
+
+        info!("Migrating database to current format.");
+
+        let migrated = migrate(config)
+            .await
+            .map_err(MigrateCommandError::PersistenceError)?;
+
+        info!("Migration finished, {} files upgraded.", migrated);
+
+        Ok(())
+    }
+}
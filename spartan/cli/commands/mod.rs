@@ -1,6 +1,9 @@
 /// `start` command
 pub mod start;
 
+/// `migrate` command
+pub mod migrate;
+
 #[cfg(feature = "init")]
 /// `init` command
 pub mod init;
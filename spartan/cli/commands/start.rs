@@ -9,7 +9,10 @@ use crate::{
     cli::Server,
     dispatch_jobs,
     http::server::{start_http_server, ServerError},
-    jobs::{gc::spawn_gc, persistence::spawn_persistence},
+    jobs::{
+        compaction::spawn_compaction, gc::spawn_gc, persistence::spawn_persistence,
+        schedule::spawn_schedule,
+    },
     node::{persistence::PersistenceError, Manager},
 };
 
@@ -53,7 +56,13 @@ impl StartCommand {
 
         let manager = Arc::new(manager);
 
-        dispatch_jobs!(manager, spawn_gc, spawn_persistence);
+        dispatch_jobs!(
+            manager,
+            spawn_gc,
+            spawn_persistence,
+            spawn_schedule,
+            spawn_compaction
+        );
 
         #[cfg(feature = "replication")]
         dispatch_jobs!(manager, spawn_replication);
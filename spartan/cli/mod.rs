@@ -9,7 +9,7 @@ use std::{io::Error, path::PathBuf};
 use commands::init::InitCommand;
 #[cfg(feature = "replication")]
 use commands::replica::ReplicaCommand;
-use commands::start::StartCommand;
+use commands::{migrate::MigrateCommand, start::StartCommand};
 use structopt::StructOpt;
 use tokio::fs::read;
 use toml::from_slice;
@@ -27,6 +27,8 @@ pub enum Command {
     #[cfg(feature = "replication")]
     #[structopt(about = "Start replication server")]
     Replica(ReplicaCommand),
+    #[structopt(about = "Upgrade database to current on-disk format")]
+    Migrate(MigrateCommand),
 }
 
 /// Server with config and selected command
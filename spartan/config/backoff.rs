@@ -0,0 +1,103 @@
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum BackoffPolicy {
+    /// Same delay for each requeue
+    Fixed,
+
+    /// Delay grows linearly with message tries
+    Linear,
+
+    /// Delay doubles with each message try
+    Exponential,
+}
+
+/// Requeue backoff config
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Backoff {
+    /// Backoff policy
+    pub policy: BackoffPolicy,
+
+    /// Base delay in seconds
+    pub delay: u32,
+
+    /// Max delay in seconds
+    pub cap: Option<u32>,
+
+    /// Randomize delay in range of half of delay and full delay
+    #[serde(default)]
+    pub jitter: bool,
+}
+
+impl Backoff {
+    /// Get requeue delay in seconds for message with provided amount of tries
+    pub fn delay(&self, tries: u32) -> u32 {
+        let delay = match self.policy {
+            BackoffPolicy::Fixed => self.delay,
+            BackoffPolicy::Linear => self.delay.saturating_mul(tries),
+            BackoffPolicy::Exponential => 2u32
+                .checked_pow(tries.saturating_sub(1))
+                .map_or(u32::MAX, |multiplier| self.delay.saturating_mul(multiplier)),
+        };
+
+        let delay = self.cap.map_or(delay, |cap| delay.min(cap));
+
+        if self.jitter {
+            let half = delay / 2;
+            half + thread_rng().gen_range(0, delay - half + 1)
+        } else {
+            delay
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backoff, BackoffPolicy};
+
+    fn backoff(policy: BackoffPolicy, cap: Option<u32>, jitter: bool) -> Backoff {
+        Backoff {
+            policy,
+            delay: 10,
+            cap,
+            jitter,
+        }
+    }
+
+    #[test]
+    fn test_fixed() {
+        let backoff = backoff(BackoffPolicy::Fixed, None, false);
+        assert_eq!(backoff.delay(1), 10);
+        assert_eq!(backoff.delay(5), 10);
+    }
+
+    #[test]
+    fn test_linear() {
+        let backoff = backoff(BackoffPolicy::Linear, Some(35), false);
+        assert_eq!(backoff.delay(1), 10);
+        assert_eq!(backoff.delay(3), 30);
+        assert_eq!(backoff.delay(4), 35);
+    }
+
+    #[test]
+    fn test_exponential() {
+        let backoff = backoff(BackoffPolicy::Exponential, Some(100), false);
+        assert_eq!(backoff.delay(1), 10);
+        assert_eq!(backoff.delay(2), 20);
+        assert_eq!(backoff.delay(4), 80);
+        assert_eq!(backoff.delay(5), 100);
+        assert_eq!(backoff.delay(64), 100);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let backoff = backoff(BackoffPolicy::Exponential, None, true);
+
+        for _ in 0..100 {
+            let delay = backoff.delay(3);
+            assert!((20..=40).contains(&delay));
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+/// Max amount of days, that are searched for the next tick
+///
+/// Covers full leap year cycle, so expressions like `0 0 29 2 1` still find their tick.
+const MAX_SEARCH_DAYS: u32 = 366 * 28;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CronError {
+    #[error("Cron expression must have 5 fields, found {0}")]
+    InvalidFieldCount(usize),
+    #[error("Invalid cron field \"{0}\"")]
+    InvalidField(Box<str>),
+    #[error("Cron value {0} is out of range {1}-{2}")]
+    ValueOutOfRange(u32, u32, u32),
+}
+
+/// Standard 5-field cron expression
+///
+/// Fields are minute, hour, day of month, month and day of week (0 or 7 is Sunday).
+/// Each field supports wildcards (`*`), values, ranges (`1-5`), steps (`*/15`, `0-30/10`) and lists (`1,15`).
+///
+/// As in most cron implementations, if both day of month and day of week are restricted,
+/// then tick matches any of them.
+#[derive(Clone, Debug)]
+pub struct Cron {
+    /// Source expression
+    expression: Box<str>,
+
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+
+    /// Day of month field is a wildcard
+    any_day: bool,
+
+    /// Day of week field is a wildcard
+    any_weekday: bool,
+}
+
+/// Parse cron field into bit set of matching values
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, CronError> {
+    let invalid = || CronError::InvalidField(field.into());
+    let parse = |value: &str| value.parse::<u32>().map_err(|_| invalid());
+
+    let mut set = 0;
+
+    for part in field.split(',') {
+        let mut split = part.splitn(2, '/');
+        let range = split.next().ok_or_else(invalid)?;
+        let step = split.next().map(parse).transpose()?;
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else {
+            let mut bounds = range.splitn(2, '-');
+            let start = parse(bounds.next().ok_or_else(invalid)?)?;
+
+            match bounds.next() {
+                Some(end) => (start, parse(end)?),
+                // Single value with step (like `5/15`) means range up to field max
+                None if step.is_some() => (start, max),
+                None => (start, start),
+            }
+        };
+
+        for value in &[start, end] {
+            if *value < min || *value > max {
+                return Err(CronError::ValueOutOfRange(*value, min, max));
+            }
+        }
+
+        if start > end {
+            return Err(invalid());
+        }
+
+        match step {
+            Some(0) => return Err(invalid()),
+            Some(step) => (start..=end)
+                .step_by(step as usize)
+                .for_each(|value| set |= 1 << value),
+            None => (start..=end).for_each(|value| set |= 1 << value),
+        }
+    }
+
+    Ok(set)
+}
+
+impl Cron {
+    fn matches(set: u64, value: u32) -> bool {
+        set & (1 << value) != 0
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        let day = Self::matches(self.days, date.day());
+        let weekday = Self::matches(self.weekdays, date.weekday().num_days_from_sunday());
+
+        Self::matches(self.months, date.month())
+            && if self.any_day || self.any_weekday {
+                day && weekday
+            } else {
+                day || weekday
+            }
+    }
+
+    /// Get the first tick strictly after provided local time
+    ///
+    /// Returns `None` if expression has no ticks (like `0 0 31 2 *`).
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut date = start.date();
+
+        for _ in 0..MAX_SEARCH_DAYS {
+            if self.matches_date(date) {
+                let (from_hour, from_minute) = if date == start.date() {
+                    (start.hour(), start.minute())
+                } else {
+                    (0, 0)
+                };
+
+                for hour in (from_hour..24).filter(|hour| Self::matches(self.hours, *hour)) {
+                    let from_minute = if hour == from_hour { from_minute } else { 0 };
+
+                    if let Some(minute) =
+                        (from_minute..60).find(|minute| Self::matches(self.minutes, *minute))
+                    {
+                        return Some(date.and_hms(hour, minute, 0));
+                    }
+                }
+            }
+
+            date = date.succ_opt()?;
+        }
+
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = CronError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+
+        if let [minutes, hours, days, months, weekdays] = *fields {
+            let weekdays = parse_field(weekdays, 0, 7)?;
+
+            Ok(Cron {
+                expression: fields.join(" ").into_boxed_str(),
+                minutes: parse_field(minutes, 0, 59)?,
+                hours: parse_field(hours, 0, 23)?,
+                days: parse_field(days, 1, 31)?,
+                months: parse_field(months, 1, 12)?,
+                // Both 0 and 7 are Sunday
+                weekdays: (weekdays | weekdays >> 7) & 0x7f,
+                any_day: days.starts_with('*'),
+                any_weekday: fields[4].starts_with('*'),
+            })
+        } else {
+            Err(CronError::InvalidFieldCount(fields.len()))
+        }
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl Serialize for Cron {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.expression)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cron {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, NaiveDate, Timelike};
+
+    use super::{Cron, CronError};
+
+    fn next(
+        expression: &str,
+        after: (i32, u32, u32, u32, u32),
+    ) -> Option<(i32, u32, u32, u32, u32)> {
+        let (year, month, day, hour, minute) = after;
+
+        expression
+            .parse::<Cron>()
+            .unwrap()
+            .next_after(NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 30))
+            .map(|tick| {
+                (
+                    tick.year(),
+                    tick.month(),
+                    tick.day(),
+                    tick.hour(),
+                    tick.minute(),
+                )
+            })
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!("* * * * *".parse::<Cron>().is_ok());
+        assert!("*/15 9-17 1,15 */2 1-5".parse::<Cron>().is_ok());
+        assert!("5/10 0 * * 7".parse::<Cron>().is_ok());
+
+        assert_eq!(
+            "* * * *".parse::<Cron>().unwrap_err(),
+            CronError::InvalidFieldCount(4)
+        );
+        assert_eq!(
+            "60 * * * *".parse::<Cron>().unwrap_err(),
+            CronError::ValueOutOfRange(60, 0, 59)
+        );
+        assert_eq!(
+            "* * 0 * *".parse::<Cron>().unwrap_err(),
+            CronError::ValueOutOfRange(0, 1, 31)
+        );
+        assert!(matches!(
+            "*/0 * * * *".parse::<Cron>(),
+            Err(CronError::InvalidField(_))
+        ));
+        assert!(matches!(
+            "5-1 * * * *".parse::<Cron>(),
+            Err(CronError::InvalidField(_))
+        ));
+        assert!(matches!(
+            "a * * * *".parse::<Cron>(),
+            Err(CronError::InvalidField(_))
+        ));
+    }
+
+    #[test]
+    fn test_next_after() {
+        // 2021-03-01 is Monday
+        assert_eq!(
+            next("* * * * *", (2021, 3, 1, 10, 0)),
+            Some((2021, 3, 1, 10, 1))
+        );
+        assert_eq!(
+            next("*/15 * * * *", (2021, 3, 1, 10, 15)),
+            Some((2021, 3, 1, 10, 30))
+        );
+        assert_eq!(
+            next("0 3 * * *", (2021, 3, 1, 3, 0)),
+            Some((2021, 3, 2, 3, 0))
+        );
+        assert_eq!(
+            next("30 23 31 12 *", (2021, 3, 1, 0, 0)),
+            Some((2021, 12, 31, 23, 30))
+        );
+        assert_eq!(
+            next("0 0 29 2 *", (2021, 3, 1, 0, 0)),
+            Some((2024, 2, 29, 0, 0))
+        );
+        assert_eq!(next("0 0 31 2 *", (2021, 3, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_next_after_weekdays() {
+        // Sunday may be set as both 0 and 7
+        assert_eq!(
+            next("0 12 * * 0", (2021, 3, 1, 0, 0)),
+            Some((2021, 3, 7, 12, 0))
+        );
+        assert_eq!(
+            next("0 12 * * 7", (2021, 3, 1, 0, 0)),
+            Some((2021, 3, 7, 12, 0))
+        );
+        assert_eq!(
+            next("0 9 * * 1-5", (2021, 3, 5, 10, 0)),
+            Some((2021, 3, 8, 9, 0))
+        );
+
+        // Either day of month or day of week matches, when both are restricted
+        assert_eq!(
+            next("0 0 15 * 3", (2021, 3, 1, 0, 0)),
+            Some((2021, 3, 3, 0, 0))
+        );
+        assert_eq!(
+            next("0 0 15 * 3", (2021, 3, 11, 0, 0)),
+            Some((2021, 3, 15, 0, 0))
+        );
+    }
+}
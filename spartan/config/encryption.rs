@@ -0,0 +1,74 @@
+use std::convert::TryInto;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Encryption key length in bytes
+const KEY_LENGTH: usize = 32;
+
+/// Persistence encryption key
+///
+/// Stored in config as base64-encoded string of 32 random bytes.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; KEY_LENGTH]);
+
+impl EncryptionKey {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; KEY_LENGTH]> for EncryptionKey {
+    fn from(key: [u8; KEY_LENGTH]) -> Self {
+        EncryptionKey(key)
+    }
+}
+
+impl Serialize for EncryptionKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptionKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let key = String::deserialize(deserializer)?;
+
+        base64::decode(key)
+            .ok()
+            .and_then(|key| key.as_slice().try_into().ok())
+            .map(EncryptionKey)
+            .ok_or_else(|| {
+                D::Error::custom(format!(
+                    "encryption key must be base64-encoded string of {} bytes",
+                    KEY_LENGTH
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EncryptionKey;
+
+    #[test]
+    fn test_serialize_key() {
+        let key = EncryptionKey::from([7; 32]);
+
+        let serialized = serde_json::to_string(&key).unwrap();
+        let deserialized: EncryptionKey = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(deserialized.as_bytes(), &[7; 32]);
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        assert!(serde_json::from_str::<EncryptionKey>("\"not a key\"").is_err());
+        assert!(serde_json::from_str::<EncryptionKey>("\"AAAA\"").is_err());
+    }
+}
@@ -1,17 +1,32 @@
+/// Requeue backoff config
+pub mod backoff;
+
+/// Cron expression
+pub mod cron;
+
 /// Queue access key
 pub mod key;
 
+/// Persistence encryption key
+pub mod encryption;
+
 /// Replication config
 pub mod replication;
 
 /// Persistence config
 pub mod persistence;
 
-use std::collections::HashSet;
+/// Recurring message schedule config
+pub mod schedule;
+
+use std::collections::{HashMap, HashSet};
 
+use backoff::Backoff;
+use encryption::EncryptionKey;
 use key::Key;
 use persistence::PersistenceConfig;
 use replication::ReplicationConfig;
+use schedule::Schedules;
 use serde::{Deserialize, Serialize, Serializer};
 
 /// Default amount of seconds between GC jobs
@@ -19,6 +34,11 @@ const fn default_gc_timer() -> u64 {
     300
 }
 
+/// Default amount of seconds, during which pushes with the same deduplication ID are ignored
+const fn default_dedup_window() -> u32 {
+    300
+}
+
 fn default_persistence() -> PersistenceConfig<'static> {
     PersistenceConfig::default()
 }
@@ -50,15 +70,49 @@ pub struct Config<'a> {
     #[serde(skip_serializing)]
     pub gc_timer: u64,
 
+    /// Requeue messages with expired reservation during GC, instead of deleting them
+    ///
+    /// Messages are deleted only after all of their tries were used
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    pub requeue_expired: bool,
+
+    /// Amount of seconds, during which pushes with the same deduplication ID are ignored
+    #[serde(default = "default_dedup_window")]
+    #[serde(skip_serializing)]
+    pub dedup_window: u32,
+
     /// Array of queues
     pub queues: Box<[Box<str>]>,
 
     /// Persistence encryption key
-    pub encryption_key: Option<Box<str>>,
+    ///
+    /// Snapshots and log entries are encrypted if key is present
+    pub encryption_key: Option<EncryptionKey>,
+
+    /// Previous persistence encryption keys
+    ///
+    /// Used only to decrypt data, that was persisted before key rotation
+    pub previous_encryption_keys: Option<Box<[EncryptionKey]>>,
 
     /// Queue access keys
     pub access_keys: Option<HashSet<Key>>,
 
+    /// Dead letter queues
+    ///
+    /// Maps queue name to name of queue, that receives its GC-collected messages
+    pub dead_letter: Option<HashMap<Box<str>, Box<str>>>,
+
+    /// Requeue backoff policies
+    ///
+    /// Maps queue name to backoff, that is applied to messages requeued without explicit delay
+    pub backoff: Option<HashMap<Box<str>, Backoff>>,
+
+    /// Recurring message schedules
+    ///
+    /// Maps queue name to named schedules, that push messages into it
+    pub schedule: Option<HashMap<Box<str>, Schedules>>,
+
     /// Replication config
     pub replication: Option<ReplicationConfig>,
 
@@ -67,15 +121,41 @@ pub struct Config<'a> {
     pub persistence: Option<PersistenceConfig<'a>>,
 }
 
+impl Config<'_> {
+    /// Get name of dead letter queue, that is attached to provided queue
+    pub fn dead_letter(&self, queue: &str) -> Option<&str> {
+        self.dead_letter.as_ref()?.get(queue).map(|name| &**name)
+    }
+
+    /// Get requeue backoff of provided queue
+    pub fn backoff(&self, queue: &str) -> Option<&Backoff> {
+        self.backoff.as_ref()?.get(queue)
+    }
+
+    /// Check if provided queue is used as dead letter queue
+    pub fn is_dead_letter(&self, queue: &str) -> bool {
+        self.dead_letter
+            .iter()
+            .flat_map(HashMap::values)
+            .any(|name| &**name == queue)
+    }
+}
+
 #[cfg(not(test))]
 impl Default for Config<'_> {
     fn default() -> Self {
         Config {
             body_size: None,
             gc_timer: default_gc_timer(),
+            requeue_expired: false,
+            dedup_window: default_dedup_window(),
             queues: Box::new([]),
             encryption_key: None,
+            previous_encryption_keys: None,
             access_keys: None,
+            dead_letter: None,
+            backoff: None,
+            schedule: None,
             replication: None,
             persistence: Some(default_persistence()),
         }
@@ -88,12 +168,18 @@ impl Default for Config<'_> {
         Config {
             body_size: None,
             gc_timer: 10,
+            requeue_expired: false,
+            dedup_window: default_dedup_window(),
             queues: Box::new([
                 String::from("test").into_boxed_str(),
                 String::from("test_2").into_boxed_str(),
             ]),
             encryption_key: None,
+            previous_encryption_keys: None,
             access_keys: None,
+            dead_letter: None,
+            backoff: None,
+            schedule: None,
             replication: None,
             persistence: Some(default_persistence()),
         }
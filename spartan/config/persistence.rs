@@ -20,6 +20,35 @@ const fn default_compaction() -> bool {
     true
 }
 
+const fn default_fsync() -> Fsync {
+    Fsync::Always
+}
+
+/// Default amount of milliseconds between log fsyncs with [`Fsync::Interval`] policy
+const fn default_fsync_interval() -> u64 {
+    1000
+}
+
+/// Default log segment size in bytes (64 MiB)
+const fn default_segment_size() -> u64 {
+    64 * 1024 * 1024
+}
+
+/// Default amount of seconds before log segment rotation
+const fn default_segment_age() -> u64 {
+    3600
+}
+
+/// Default amount of previous snapshot generations to keep
+const fn default_snapshot_generations() -> usize {
+    2
+}
+
+/// Default amount of seconds between background log compactions
+const fn default_compaction_timer() -> u64 {
+    60
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Persistence {
@@ -27,6 +56,22 @@ pub enum Persistence {
     Snapshot,
 }
 
+/// Log fsync policy
+///
+/// Log entries are acknowledged only after reaching durability point of policy
+#[derive(Serialize, Deserialize, Copy, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum Fsync {
+    /// Fsync each group commit, acknowledge entries after fsync
+    Always,
+
+    /// Fsync periodically, acknowledge entries after they were written to OS
+    Interval,
+
+    /// Never fsync, acknowledge entries after they were written to OS
+    Never,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct PersistenceConfig<'a> {
     /// Persistence mode
@@ -43,10 +88,43 @@ pub struct PersistenceConfig<'a> {
     #[serde(default = "default_snapshot_timer")]
     pub timer: u64,
 
-    /// Log compaction on queue restoring from FS
+    /// Amount of previous snapshot generations to keep
+    ///
+    /// If latest snapshot is unreadable, queue is restored from the newest valid previous generation
+    #[serde(default = "default_snapshot_generations")]
+    pub snapshot_generations: usize,
+
+    /// Log compaction on queue restoring from FS and in background
     #[serde(default = "default_compaction")]
     #[serde(skip_serializing)]
     pub compaction: bool,
+
+    /// Refuse to load log with corrupted entries in the middle of file
+    ///
+    /// Torn tail, that is left by interrupted append, is truncated regardless of this option
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    pub strict: bool,
+
+    /// Log fsync policy
+    #[serde(default = "default_fsync")]
+    pub fsync: Fsync,
+
+    /// Amount of milliseconds between log fsyncs with [`Fsync::Interval`] policy
+    #[serde(default = "default_fsync_interval")]
+    pub fsync_interval: u64,
+
+    /// Log segment size in bytes, after which it's rotated
+    #[serde(default = "default_segment_size")]
+    pub segment_size: u64,
+
+    /// Amount of seconds, after which log segment is rotated
+    #[serde(default = "default_segment_age")]
+    pub segment_age: u64,
+
+    /// Amount of seconds between background compactions of rotated log segments
+    #[serde(default = "default_compaction_timer")]
+    pub compaction_timer: u64,
 }
 
 impl Default for PersistenceConfig<'_> {
@@ -55,7 +133,14 @@ impl Default for PersistenceConfig<'_> {
             mode: default_persistence(),
             path: default_path(),
             timer: default_snapshot_timer(),
+            snapshot_generations: default_snapshot_generations(),
             compaction: default_compaction(),
+            strict: false,
+            fsync: default_fsync(),
+            fsync_interval: default_fsync_interval(),
+            segment_size: default_segment_size(),
+            segment_age: default_segment_age(),
+            compaction_timer: default_compaction_timer(),
         }
     }
 }
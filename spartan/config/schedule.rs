@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize};
+use spartan_lib::core::message::{
+    builder::{BuilderError, MessageBuilder},
+    Attributes, Message, Timezone,
+};
+
+use super::cron::Cron;
+
+/// Named schedules of queue
+pub type Schedules = HashMap<Box<str>, Schedule>;
+
+const fn default_catch_up() -> CatchUp {
+    CatchUp::Skip
+}
+
+/// Deserialize timezone offset, making sure that it's in bounds of [`FixedOffset`]
+fn deserialize_offset<'de, D>(deserializer: D) -> Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let offset = i32::deserialize(deserializer)?;
+
+    FixedOffset::east_opt(offset)
+        .map(|_| offset)
+        .ok_or_else(|| D::Error::custom(format!("Timezone offset {} is out of bounds", offset)))
+}
+
+/// Handling of ticks, that were missed while node was down
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CatchUp {
+    /// Missed ticks are skipped
+    Skip,
+
+    /// Only the latest missed tick is materialized
+    Latest,
+
+    /// All missed ticks are materialized (up to [`MAX_CATCH_UP_TICKS`])
+    ///
+    /// [`MAX_CATCH_UP_TICKS`]: crate::node::schedule::MAX_CATCH_UP_TICKS
+    All,
+}
+
+/// Template of scheduled messages
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MessageTemplate {
+    pub body: Box<str>,
+    pub content_type: Option<Box<str>>,
+    pub group: Option<Box<str>>,
+    pub max_tries: Option<u32>,
+    pub timeout: Option<u32>,
+    pub priority: Option<u32>,
+    pub attributes: Option<Attributes>,
+}
+
+/// Recurring message schedule
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Schedule {
+    /// Cron expression
+    pub cron: Cron,
+
+    /// Timezone offset in seconds, that cron expression is evaluated in
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_offset")]
+    pub offset: i32,
+
+    /// IANA timezone, that cron expression is evaluated in. Takes precedence over offset.
+    pub timezone: Option<Timezone>,
+
+    /// Catch-up policy of missed ticks
+    #[serde(default = "default_catch_up")]
+    pub catch_up: CatchUp,
+
+    /// Template of materialized messages
+    pub message: MessageTemplate,
+}
+
+impl Schedule {
+    /// Get the first tick strictly after provided time
+    ///
+    /// With IANA timezone, ticks that are skipped by DST transition are shifted forward,
+    /// and ticks that are repeated by DST transition are materialized only once.
+    pub fn next_tick(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.timezone.as_ref() {
+            Some(timezone) => {
+                let mut local = timezone.localize(&after).naive_local();
+
+                loop {
+                    local = self.cron.next_after(local)?;
+
+                    let tick = timezone.from_local(&local).with_timezone(&Utc);
+
+                    if tick > after {
+                        return Some(tick);
+                    }
+                }
+            }
+            None => {
+                let timezone = FixedOffset::east_opt(self.offset)?;
+
+                self.cron
+                    .next_after(after.with_timezone(&timezone).naive_local())
+                    .and_then(|tick| timezone.from_local_datetime(&tick).single())
+                    .map(|tick| tick.with_timezone(&Utc))
+            }
+        }
+    }
+
+    /// Compose message of schedule tick
+    ///
+    /// Message is deduplicated with provided ID, so that tick is never materialized twice.
+    pub fn compose(&self, dedup_id: String) -> Result<Message, BuilderError> {
+        let template = &self.message;
+
+        let mut builder = MessageBuilder::default()
+            .body(template.body.as_bytes())
+            .dedup_id(dedup_id)
+            .offset(self.offset);
+
+        if let Some(timezone) = self.timezone.as_ref() {
+            builder = builder.timezone(timezone.name());
+        };
+
+        if let Some(content_type) = template.content_type.as_ref() {
+            builder = builder.content_type(&**content_type);
+        };
+
+        if let Some(group) = template.group.as_ref() {
+            builder = builder.group(&**group);
+        };
+
+        if let Some(max_tries) = template.max_tries {
+            builder = builder.max_tries(max_tries);
+        };
+
+        if let Some(timeout) = template.timeout {
+            builder = builder.timeout(timeout);
+        };
+
+        if let Some(priority) = template.priority {
+            builder = builder.priority(priority);
+        };
+
+        if let Some(attributes) = template.attributes.as_ref() {
+            for (key, value) in attributes {
+                builder = builder.attribute(&**key, value.clone());
+            }
+        };
+
+        builder.compose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use spartan_lib::core::message::Timezone;
+
+    use super::{CatchUp, Schedule};
+
+    fn parse(source: &str) -> Result<Schedule, toml::de::Error> {
+        toml::from_str(source)
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let schedule = parse(
+            r#"
+            cron = "0 3 * * *"
+            offset = 7200
+            catch_up = "latest"
+
+            [message]
+            body = "Hello, world"
+            priority = 2
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(schedule.cron.to_string(), "0 3 * * *");
+        assert_eq!(schedule.offset, 7200);
+        assert_eq!(schedule.catch_up, CatchUp::Latest);
+        assert_eq!(schedule.message.priority, Some(2));
+
+        assert!(parse(
+            r#"
+            cron = "0 3 * *"
+
+            [message]
+            body = "Hello, world"
+            "#
+        )
+        .is_err());
+
+        assert!(parse(
+            r#"
+            cron = "0 3 * * *"
+            offset = 86400
+
+            [message]
+            body = "Hello, world"
+            "#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_timezone_next_tick() {
+        // Timezone is loaded from host timezone database, that may be not available
+        if Timezone::new("Europe/Kyiv").is_some() {
+            let schedule = parse(
+                r#"
+                cron = "30 3 * * *"
+                offset = 7200
+                timezone = "Europe/Kyiv"
+
+                [message]
+                body = "Hello, world"
+                "#,
+            )
+            .unwrap();
+
+            // Timezone takes precedence over offset
+            assert_eq!(
+                schedule.next_tick(Utc.ymd(2021, 7, 1).and_hms(0, 0, 0)),
+                Some(Utc.ymd(2021, 7, 1).and_hms(0, 30, 0))
+            );
+
+            // Tick in skipped hour is shifted forward
+            assert_eq!(
+                schedule.next_tick(Utc.ymd(2021, 3, 27).and_hms(12, 0, 0)),
+                Some(Utc.ymd(2021, 3, 28).and_hms(1, 30, 0))
+            );
+
+            // Tick in repeated hour is materialized once
+            assert_eq!(
+                schedule.next_tick(Utc.ymd(2021, 10, 30).and_hms(12, 0, 0)),
+                Some(Utc.ymd(2021, 10, 31).and_hms(0, 30, 0))
+            );
+            assert_eq!(
+                schedule.next_tick(Utc.ymd(2021, 10, 31).and_hms(0, 30, 0)),
+                Some(Utc.ymd(2021, 11, 1).and_hms(1, 30, 0))
+            );
+        }
+
+        assert!(parse(
+            r#"
+            cron = "30 3 * * *"
+            timezone = "Europe/Atlantis"
+
+            [message]
+            body = "Hello, world"
+            "#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_next_tick() {
+        let schedule = parse(
+            r#"
+            cron = "0 3 * * *"
+            offset = 7200
+
+            [message]
+            body = "Hello, world"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            schedule.next_tick(Utc.ymd(2021, 3, 1).and_hms(0, 0, 0)),
+            Some(Utc.ymd(2021, 3, 1).and_hms(1, 0, 0))
+        );
+        assert_eq!(
+            schedule.next_tick(Utc.ymd(2021, 3, 1).and_hms(1, 0, 0)),
+            Some(Utc.ymd(2021, 3, 2).and_hms(1, 0, 0))
+        );
+    }
+}
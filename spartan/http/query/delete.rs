@@ -5,6 +5,8 @@ use spartan_lib::{core::message::Message, uuid::Uuid};
 #[cfg_attr(test, derive(serde::Serialize))]
 pub struct DeleteRequest {
     pub id: Uuid,
+    #[serde(default)]
+    pub receipt: Option<Uuid>,
 }
 
 #[derive(Serialize)]
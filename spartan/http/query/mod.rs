@@ -1,5 +1,7 @@
 pub mod delete;
 pub mod pop;
 pub mod push;
+pub mod redrive;
 pub mod requeue;
 pub mod size;
+pub mod touch;
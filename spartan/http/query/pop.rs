@@ -1,10 +1,48 @@
+use std::{borrow::Cow, str::from_utf8};
+
 use chrono::{DateTime, FixedOffset};
-use serde::Serialize;
-use spartan_lib::core::{
-    message::{Message, State},
-    payload::{Dispatchable, Identifiable},
+use serde::{Deserialize, Serialize};
+use spartan_lib::{
+    core::{
+        message::{Attributes, Message, State, Timezone},
+        payload::{Dispatchable, Groupable, Identifiable},
+    },
+    uuid::Uuid,
 };
 
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Default, serde::Serialize))]
+pub struct PopRequest {
+    pub wait: Option<u64>,
+    pub count: Option<usize>,
+}
+
+/// Raw pop options, that are provided in query string
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Default, serde::Serialize))]
+pub struct RawPopRequest {
+    pub wait: Option<u64>,
+}
+
+/// Encoding of message body in pop response
+///
+/// Text bodies are returned as is, while binary bodies are encoded with base64
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyEncoding {
+    Utf8,
+    Base64,
+}
+
+impl BodyEncoding {
+    fn encode(body: &[u8]) -> (Cow<'_, str>, Self) {
+        match from_utf8(body) {
+            Ok(body) => (Cow::Borrowed(body), BodyEncoding::Utf8),
+            Err(_) => (Cow::Owned(base64::encode(body)), BodyEncoding::Base64),
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct Timeout<'m> {
     max: &'m u32,
@@ -13,28 +51,46 @@ pub struct Timeout<'m> {
 
 #[derive(Serialize)]
 pub struct Time<'m> {
+    timezone: Option<&'m str>,
     dispatched_at: &'m DateTime<FixedOffset>,
     delay: &'m Option<DateTime<FixedOffset>>,
+    deliver_at: &'m Option<DateTime<FixedOffset>>,
     timeout: Timeout<'m>,
 }
 
 #[derive(Serialize)]
 pub struct PopResponse<'m> {
     id: <Message as Identifiable>::Id,
-    body: &'m <Message as Dispatchable>::Body,
+    body: Cow<'m, str>,
+    encoding: BodyEncoding,
+    content_type: &'m str,
+    group: Option<&'m str>,
+    priority: u32,
+    receipt: &'m Option<Uuid>,
+    attributes: &'m Attributes,
     state: &'m State,
     time: Time<'m>,
 }
 
 impl<'m> From<&'m Message> for PopResponse<'m> {
     fn from(message: &'m Message) -> Self {
+        let (body, encoding) = BodyEncoding::encode(message.body());
+
         PopResponse {
             id: message.id(),
-            body: message.body(),
+            body,
+            encoding,
+            content_type: message.content_type(),
+            group: message.group(),
+            priority: message.priority(),
+            receipt: message.state().receipt(),
+            attributes: message.attributes(),
             state: message.state(),
             time: Time {
+                timezone: message.time().timezone().map(Timezone::name),
                 dispatched_at: message.time().dispatched_at(),
                 delay: message.time().delay(),
+                deliver_at: message.time().deliver_at(),
                 timeout: Timeout {
                     max: message.time().timeout().max(),
                     obtained_at: message.time().timeout().obtained_at(),
@@ -50,9 +106,22 @@ pub mod test_response {
 
     use super::*;
 
+    #[derive(Deserialize)]
+    pub struct TestTime {
+        pub timezone: Option<Box<str>>,
+        pub dispatched_at: DateTime<FixedOffset>,
+        pub deliver_at: Option<DateTime<FixedOffset>>,
+    }
+
     #[derive(Deserialize)]
     pub struct TestPopResponse {
         pub id: <Message as Identifiable>::Id,
-        pub body: Box<<Message as Dispatchable>::Body>,
+        pub body: Box<str>,
+        pub encoding: BodyEncoding,
+        pub content_type: Box<str>,
+        pub group: Option<Box<str>>,
+        pub time: TestTime,
+        pub receipt: Uuid,
+        pub attributes: Attributes,
     }
 }
@@ -1,43 +1,155 @@
 use std::convert::TryFrom;
 
-use serde::Deserialize;
-use spartan_lib::core::message::{
-    builder::{BuilderError, MessageBuilder},
-    Message,
+use bytes::Bytes;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use spartan_lib::{
+    core::{
+        message::{
+            builder::{BuilderError, MessageBuilder},
+            Attributes, Message,
+        },
+        payload::Identifiable,
+    },
+    uuid::Uuid,
 };
 
+/// Content type of raw messages, that were pushed without `Content-Type` header
+const DEFAULT_RAW_CONTENT_TYPE: &str = "application/octet-stream";
+
 #[derive(Deserialize)]
 #[cfg_attr(test, derive(Default, serde::Serialize))]
 pub struct PushRequest {
     pub body: Box<str>,
+    pub content_type: Option<Box<str>>,
+    pub dedup_id: Option<Box<str>>,
+    pub group: Option<Box<str>>,
     pub offset: Option<i32>,
+    pub timezone: Option<Box<str>>,
     pub max_tries: Option<u32>,
     pub timeout: Option<u32>,
     pub delay: Option<u32>,
+    pub deliver_at: Option<DateTime<FixedOffset>>,
+    pub priority: Option<u32>,
+    pub attributes: Option<Attributes>,
 }
 
-impl TryFrom<PushRequest> for Message {
-    type Error = BuilderError;
+/// Raw message push options, that are provided in query string
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Default, serde::Serialize))]
+pub struct RawPushRequest {
+    pub dedup_id: Option<Box<str>>,
+    pub group: Option<Box<str>>,
+    pub offset: Option<i32>,
+    pub timezone: Option<Box<str>>,
+    pub max_tries: Option<u32>,
+    pub timeout: Option<u32>,
+    pub delay: Option<u32>,
+    pub deliver_at: Option<DateTime<FixedOffset>>,
+    pub priority: Option<u32>,
+}
 
-    fn try_from(request: PushRequest) -> Result<Message, Self::Error> {
-        let mut builder = MessageBuilder::default().body(request.body);
+/// Pushed message ID
+///
+/// If message was deduplicated, then ID of the original message is returned
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct PushResponse {
+    pub id: <Message as Identifiable>::Id,
+}
+
+impl From<Uuid> for PushResponse {
+    fn from(id: Uuid) -> Self {
+        PushResponse { id }
+    }
+}
+
+impl RawPushRequest {
+    /// Compose message from raw body and its content type
+    pub fn compose(
+        self,
+        content_type: Option<String>,
+        body: Bytes,
+    ) -> Result<Message, BuilderError> {
+        let builder = MessageBuilder::default()
+            .body(body.to_vec())
+            .content_type(content_type.as_deref().unwrap_or(DEFAULT_RAW_CONTENT_TYPE));
+
+        self.apply(builder).compose()
+    }
+
+    /// Apply provided push options to message builder
+    fn apply(self, mut builder: MessageBuilder) -> MessageBuilder {
+        if let Some(dedup_id) = self.dedup_id {
+            builder = builder.dedup_id(dedup_id);
+        };
+
+        if let Some(group) = self.group {
+            builder = builder.group(group);
+        };
 
-        if let Some(offset) = request.offset {
+        if let Some(offset) = self.offset {
             builder = builder.offset(offset);
         };
 
-        if let Some(max_tries) = request.max_tries {
+        if let Some(timezone) = self.timezone {
+            builder = builder.timezone(timezone);
+        };
+
+        if let Some(max_tries) = self.max_tries {
             builder = builder.max_tries(max_tries);
         };
 
-        if let Some(timeout) = request.timeout {
+        if let Some(timeout) = self.timeout {
             builder = builder.timeout(timeout);
         };
 
-        if let Some(delay) = request.delay {
+        if let Some(delay) = self.delay {
             builder = builder.delay(delay);
         };
 
+        // Exact delivery time takes precedence over relative delay
+        if let Some(deliver_at) = self.deliver_at {
+            builder = builder.deliver_at(deliver_at);
+        };
+
+        if let Some(priority) = self.priority {
+            builder = builder.priority(priority);
+        };
+
+        builder
+    }
+}
+
+impl TryFrom<PushRequest> for Message {
+    type Error = BuilderError;
+
+    fn try_from(request: PushRequest) -> Result<Message, Self::Error> {
+        let options = RawPushRequest {
+            dedup_id: request.dedup_id,
+            group: request.group,
+            offset: request.offset,
+            timezone: request.timezone,
+            max_tries: request.max_tries,
+            timeout: request.timeout,
+            delay: request.delay,
+            deliver_at: request.deliver_at,
+            priority: request.priority,
+        };
+
+        let mut builder =
+            options.apply(MessageBuilder::default().body(request.body.into_boxed_bytes()));
+
+        if let Some(content_type) = request.content_type {
+            builder = builder.content_type(content_type);
+        };
+
+        if let Some(attributes) = request.attributes {
+            for (key, value) in attributes {
+                builder = builder.attribute(key, value);
+            }
+        };
+
         builder.compose()
     }
 }
@@ -0,0 +1,13 @@
+use serde::Serialize;
+
+#[derive(Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct RedriveResponse {
+    pub redriven: usize,
+}
+
+impl From<usize> for RedriveResponse {
+    fn from(redriven: usize) -> Self {
+        RedriveResponse { redriven }
+    }
+}
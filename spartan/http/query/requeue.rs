@@ -2,7 +2,9 @@ use serde::Deserialize;
 use uuid::Uuid;
 
 #[derive(Deserialize)]
-#[cfg_attr(test, derive(serde::Serialize))]
+#[cfg_attr(test, derive(Default, serde::Serialize))]
 pub struct RequeueRequest {
     pub id: Uuid,
+    pub receipt: Uuid,
+    pub delay: Option<u32>,
 }
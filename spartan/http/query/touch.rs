@@ -0,0 +1,10 @@
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+pub struct TouchRequest {
+    pub id: Uuid,
+    pub receipt: Uuid,
+    pub timeout: u32,
+}
@@ -1,6 +1,10 @@
 use std::{convert::Infallible, sync::Arc};
 
-use warp::{any, body::json, delete, get, path, post, wrap_fn, Filter, Rejection, Reply};
+use warp::{
+    any,
+    body::{bytes, json},
+    delete, get, header, path, post, query, wrap_fn, Filter, Rejection, Reply,
+};
 
 use crate::{
     actions::ResponseError,
@@ -12,6 +16,10 @@ macro_rules! route {
     ($name:ident) => {
         crate::actions::$name::$name
     };
+
+    ($module:ident::$name:ident) => {
+        crate::actions::$module::$name
+    };
 }
 
 fn with_manager(
@@ -28,6 +36,7 @@ pub fn attach_routes(
         .and(get())
         .and(path!(String))
         .with(wrap_fn(access))
+        .and(query())
         .map_async(route!(pop));
 
     let push = with_manager(manager.clone())
@@ -37,6 +46,29 @@ pub fn attach_routes(
         .and(json())
         .map_async(route!(push));
 
+    let pop_raw = with_manager(manager.clone())
+        .and(get())
+        .and(path!(String / "raw"))
+        .with(wrap_fn(access))
+        .and(query())
+        .map_async(route!(pop::pop_raw));
+
+    let push_raw = with_manager(manager.clone())
+        .and(post())
+        .and(path!(String / "raw"))
+        .with(wrap_fn(access))
+        .and(header::optional("content-type"))
+        .and(query())
+        .and(bytes())
+        .map_async(route!(push::push_raw));
+
+    let batch = with_manager(manager.clone())
+        .and(post())
+        .and(path!(String / "batch"))
+        .with(wrap_fn(access))
+        .and(json())
+        .map_async(route!(batch));
+
     let delete = with_manager(manager.clone())
         .and(delete())
         .and(path!(String))
@@ -51,6 +83,19 @@ pub fn attach_routes(
         .and(json())
         .map_async(route!(requeue));
 
+    let touch = with_manager(manager.clone())
+        .and(post())
+        .and(path!(String / "touch"))
+        .with(wrap_fn(access))
+        .and(json())
+        .map_async(route!(touch));
+
+    let redrive = with_manager(manager.clone())
+        .and(post())
+        .and(path!(String / "redrive"))
+        .with(wrap_fn(access))
+        .map_async(route!(redrive));
+
     let clear = with_manager(manager.clone())
         .and(post())
         .and(path!(String / "clear"))
@@ -65,6 +110,11 @@ pub fn attach_routes(
 
     size.or(clear)
         .or(requeue)
+        .or(touch)
+        .or(redrive)
+        .or(batch)
+        .or(pop_raw)
+        .or(push_raw)
         .or(pop)
         .or(push)
         .or(delete)
@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use tokio::time::delay_for;
+
+use crate::{config::persistence::Persistence, node::Manager};
+
+/// Log compaction job spawner
+///
+/// Periodically folds sealed log segments into compacted log, when [Log] driver with compaction is enabled.
+/// Active log file is not touched, so queue appends are not blocked.
+///
+/// [Log]: crate::node::persistence::log::Log
+pub async fn spawn_compaction(manager: &Manager<'_>) {
+    debug!("Spawning compaction job.");
+
+    if let Some(config) = manager
+        .config()
+        .persistence
+        .as_ref()
+        .filter(|config| matches!(config.mode, Persistence::Log) && config.compaction)
+    {
+        let timer = Duration::from_secs(config.compaction_timer);
+
+        loop {
+            delay_for(timer).await;
+            if let Err(e) = manager.compact().await {
+                error!("{}", e)
+            }
+        }
+    }
+}
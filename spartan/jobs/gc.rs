@@ -4,23 +4,183 @@ use futures_util::{
     stream::{iter, StreamExt},
     TryStreamExt,
 };
-use spartan_lib::core::dispatcher::SimpleDispatcher;
+use maybe_owned::MaybeOwned;
+use spartan_lib::core::{
+    clock::Clock,
+    db::{Database, StatusAwareDatabase},
+    dispatcher::SimpleDispatcher,
+    payload::{Dispatchable, Identifiable, Status},
+};
 use tokio::time::delay_for;
 
 #[cfg(feature = "replication")]
 use crate::node::replication::primary::storage::PrimaryStorage;
-use crate::node::{event::Event, persistence::PersistenceError, Manager};
+use crate::node::{event::Event, persistence::PersistenceError, Manager, DB};
+
+/// Collects garbage of `queue`, and moves it to `dead_letter` queue.
+///
+/// Garbage is deleted from `queue` only after its push to `dead_letter` queue is committed,
+/// so interrupted GC may leave a copy of message in both queues, but never loses it.
+async fn execute_dead_letter_gc(
+    manager: &Manager<'_>,
+    queue: &DB,
+    name: &str,
+    dead_letter: &str,
+) -> Result<(), PersistenceError> {
+    let dead_letter_queue = match manager.queue(dead_letter) {
+        Ok(queue) => queue,
+        Err(e) => {
+            error!(
+                "Unable to collect garbage of \"{}\" into \"{}\": {}",
+                name, dead_letter, e
+            );
+            return Ok(());
+        }
+    };
+
+    let garbage = {
+        let database = queue.database().await;
+
+        let now = database.clock().now();
+
+        database
+            .positions(|message| message.gc(&now))
+            .into_iter()
+            .filter_map(|id| database.get(id).cloned())
+            .collect::<Vec<_>>()
+    };
+
+    if garbage.is_empty() {
+        return Ok(());
+    }
+
+    debug!(
+        "Moving {} messages from \"{}\" to \"{}\"",
+        garbage.len(),
+        name,
+        dead_letter
+    );
+
+    let ids = garbage
+        .iter()
+        .map(|message| message.id())
+        .collect::<Vec<_>>();
+
+    let commit = {
+        let mut database = dead_letter_queue.database().await;
+
+        let commit = dead_letter_queue
+            .log_event(
+                dead_letter,
+                manager,
+                Event::PushBatch(MaybeOwned::Borrowed(&garbage)),
+            )
+            .await?;
+
+        garbage
+            .into_iter()
+            .for_each(|message| database.push(message));
+
+        commit
+    };
+
+    commit.wait().await?;
+
+    let commit = {
+        let mut database = queue.database().await;
+
+        // Messages may be deleted, while they were pushed to dead letter queue
+        let ids = ids
+            .into_iter()
+            .filter(|id| database.get(*id).is_some())
+            .collect::<Vec<_>>();
+
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let event = Event::Gc(ids);
+        let commit = queue.log_event(name, manager, event.clone()).await?;
+
+        event.apply(&mut *database);
+
+        commit
+    };
+
+    commit.wait().await
+}
+
+/// Requeue messages with expired reservation, so they will be reserved again.
+///
+/// Requeued messages without tries left are collected by the following GC.
+async fn execute_requeue_expired(
+    manager: &Manager<'_>,
+    queue: &DB,
+    name: &str,
+) -> Result<(), PersistenceError> {
+    let mut database = queue.database().await;
+
+    let now = database.clock().now();
+    let ids = database.reserved(|message| message.requeueable() && message.expired(&now));
+    let requeued = ids.len();
+
+    if requeued > 0 {
+        let event = Event::RequeueExpired(ids);
+
+        let commit = queue.log_event(name, manager, event.clone()).await?;
+
+        event.apply(&mut *database);
+        drop(database);
+
+        commit.wait().await?;
+    }
+
+    debug!("Requeued {} expired messages in \"{}\"", requeued, name);
+
+    Ok(())
+}
 
 /// Concurrently iterates over all databases in node, and executes GC on them.
+///
+/// If expired message requeue is enabled, then messages with expired reservation are requeued before GC.
+///
+/// Dead letter queues are skipped, as their messages are kept until being redriven or deleted.
+///
+/// Deduplication index entries outside of deduplication window are pruned in all queues.
 async fn execute_gc(manager: &Manager<'_>) -> Result<(), PersistenceError> {
     iter(manager.node().iter())
         .map(Ok)
         .try_for_each_concurrent(None, |(name, queue)| async move {
             info!("Started GC cycle on database \"{}\"", name);
 
-            queue.log_event(name, manager, Event::Gc).await?;
+            if manager.config().is_dead_letter(name) {
+                debug!("Skipping message GC of dead letter queue \"{}\"", name);
+            } else {
+                if manager.config().requeue_expired {
+                    execute_requeue_expired(manager, queue, name).await?;
+                }
 
-            queue.database().await.gc();
+                if let Some(dead_letter) = manager.config().dead_letter(name) {
+                    execute_dead_letter_gc(manager, queue, name, dead_letter).await?;
+                } else {
+                    let mut database = queue.database().await;
+
+                    let now = database.clock().now();
+                    let ids = database.positions(|message| message.gc(&now));
+
+                    if !ids.is_empty() {
+                        let event = Event::Gc(ids);
+                        let commit = queue.log_event(name, manager, event.clone()).await?;
+
+                        event.apply(&mut *database);
+                        drop(database);
+
+                        commit.wait().await?;
+                    }
+                }
+            }
+
+            queue.dedup().await.prune(manager.config().dedup_window);
 
             #[cfg(feature = "replication")]
             if let Some(storage) = queue.replication_storage().await.as_mut() {
@@ -53,12 +213,27 @@ pub async fn spawn_gc(manager: &Manager<'_>) {
 
 #[cfg(test)]
 mod tests {
+    use std::borrow::Cow;
+
+    use chrono::{Duration, Utc};
     use spartan_lib::core::{
-        dispatcher::SimpleDispatcher, message::builder::MessageBuilder, payload::Status,
+        clock::{MockClock, SystemClock},
+        db::Database,
+        dispatcher::{SimpleDispatcher, StatusAwareDispatcher},
+        message::builder::MessageBuilder,
+        payload::{Identifiable, Status},
     };
+    use tempfile::TempDir;
 
     use super::execute_gc;
-    use crate::{node::Manager, utils::testing::CONFIG};
+    use crate::{
+        config::{
+            persistence::{Persistence, PersistenceConfig},
+            Config,
+        },
+        node::{persistence::PersistenceError, Manager},
+        utils::testing::CONFIG,
+    };
 
     #[tokio::test]
     async fn test_gc() {
@@ -72,7 +247,7 @@ mod tests {
             .compose()
             .unwrap();
 
-        message.reserve();
+        message.reserve(&SystemClock);
         message.requeue();
         manager
             .queue("first")
@@ -87,4 +262,158 @@ mod tests {
 
         assert_eq!(manager.queue("first").unwrap().database().await.size(), 0);
     }
+
+    #[tokio::test]
+    async fn test_dead_letter_gc() {
+        let config = Config {
+            dead_letter: Some(
+                [(
+                    String::from("test").into_boxed_str(),
+                    String::from("test_2").into_boxed_str(),
+                )]
+                .iter()
+                .cloned()
+                .collect(),
+            ),
+            ..Default::default()
+        };
+
+        let manager = Manager::new(&config);
+
+        let mut message = MessageBuilder::default()
+            .body("Hello, world")
+            .max_tries(1)
+            .compose()
+            .unwrap();
+
+        message.reserve(&SystemClock);
+        message.requeue();
+        manager
+            .queue("test")
+            .unwrap()
+            .database()
+            .await
+            .push(message.clone());
+
+        execute_gc(&manager).await.unwrap();
+
+        assert_eq!(manager.queue("test").unwrap().database().await.size(), 0);
+
+        // Dead letter queue messages are not collected by GC
+        execute_gc(&manager).await.unwrap();
+
+        let dead_letter = manager.queue("test_2").unwrap().database().await;
+
+        assert_eq!(dead_letter.size(), 1);
+
+        let dead_message = dead_letter.get(message.id()).unwrap();
+
+        assert_eq!(dead_message.id(), message.id());
+        assert_eq!(*dead_message.state().tries(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_dead_letter_gc() {
+        let dir = TempDir::new().unwrap();
+
+        let config = Config {
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Log,
+                path: Cow::Borrowed(dir.path()),
+                ..Default::default()
+            }),
+            queues: vec![
+                "test".to_string().into_boxed_str(),
+                "test_2".to_string().into_boxed_str(),
+            ]
+            .into_boxed_slice(),
+            dead_letter: Some(
+                [(
+                    String::from("test").into_boxed_str(),
+                    String::from("test_2").into_boxed_str(),
+                )]
+                .iter()
+                .cloned()
+                .collect(),
+            ),
+            ..Default::default()
+        };
+
+        // Log file of dead letter queue can't be opened, as there is a directory in its place
+        std::fs::create_dir_all(dir.path().join("test_2/queue_log")).unwrap();
+
+        let manager = Manager::new(&config);
+
+        let mut message = MessageBuilder::default()
+            .body("Hello, world")
+            .max_tries(1)
+            .compose()
+            .unwrap();
+
+        message.reserve(&SystemClock);
+        message.requeue();
+        manager
+            .queue("test")
+            .unwrap()
+            .database()
+            .await
+            .push(message.clone());
+
+        assert!(matches!(
+            execute_gc(&manager).await.unwrap_err(),
+            PersistenceError::LogWriteError(_)
+        ));
+
+        let database = manager.queue("test").unwrap().database().await;
+
+        assert_eq!(database.size(), 1);
+        assert!(database.get(message.id()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_requeue_expired_gc() {
+        let config = Config {
+            requeue_expired: true,
+            ..Default::default()
+        };
+
+        let manager = Manager::new(&config);
+
+        // Messages are reserved a minute ago, so their reservation has already expired
+        let clock = MockClock::new(Utc::now() - Duration::seconds(60));
+
+        let reserved = |max_tries| {
+            let mut message = MessageBuilder::default()
+                .body("Hello, world")
+                .max_tries(max_tries)
+                .timeout(30)
+                .compose_with_clock(&clock)
+                .unwrap();
+
+            message.reserve(&clock);
+            message
+        };
+
+        let message = reserved(2);
+        let exhausted = reserved(1);
+
+        let queue = manager.queue("test").unwrap();
+
+        queue.database().await.push(message.clone());
+        queue.database().await.push(exhausted);
+
+        assert!(queue.database().await.pop().is_none());
+
+        // Message without tries left is collected right after requeue
+        execute_gc(&manager).await.unwrap();
+
+        assert_eq!(queue.database().await.size(), 1);
+        assert_eq!(queue.database().await.pop().unwrap().id(), message.id());
+
+        // Reservation is restarted on pop
+        execute_gc(&manager).await.unwrap();
+
+        assert_eq!(queue.database().await.size(), 1);
+        assert!(queue.database().await.pop().is_none());
+    }
 }
@@ -4,6 +4,12 @@ pub mod gc;
 /// Persistence handler
 pub mod persistence;
 
+/// Background log compaction
+pub mod compaction;
+
+/// Recurring message scheduler
+pub mod schedule;
+
 #[cfg(feature = "replication")]
 /// Replication job
 pub mod replication;
@@ -94,7 +94,7 @@ mod tests {
                 .pop()
                 .unwrap()
                 .body(),
-            "Hello, world"
+            b"Hello, world"
         );
     }
 }
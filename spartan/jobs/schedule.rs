@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_util::{
+    stream::{iter, StreamExt},
+    TryStreamExt,
+};
+use maybe_owned::MaybeOwned;
+use spartan_lib::core::dispatcher::SimpleDispatcher;
+use tokio::time::delay_for;
+
+use crate::{
+    config::schedule::Schedule,
+    node::{event::Event, persistence::PersistenceError, Manager, DB},
+};
+
+/// Amount of seconds between scheduler runs
+const SCHEDULER_TIMER: u64 = 1;
+
+/// Due ticks of named schedule
+type DueTicks<'a> = (&'a str, &'a Schedule, Vec<DateTime<Utc>>);
+
+/// Push message of schedule tick to queue.
+///
+/// Tick message is deduplicated by schedule name and tick timestamp.
+async fn materialize(
+    manager: &Manager<'_>,
+    queue: &DB,
+    name: &str,
+    schedule_name: &str,
+    schedule: &Schedule,
+    tick: DateTime<Utc>,
+) -> Result<(), PersistenceError> {
+    let dedup_id = format!("schedule:{}:{}", schedule_name, tick.timestamp());
+
+    let message = match schedule.compose(dedup_id) {
+        Ok(message) => message,
+        Err(e) => {
+            error!(
+                "Unable to compose message of schedule \"{}\" in \"{}\": {}",
+                schedule_name, name, e
+            );
+            return Ok(());
+        }
+    };
+
+    let mut database = queue.database().await;
+
+    if let Some(dedup_id) = message.dedup_id() {
+        if queue
+            .dedup()
+            .await
+            .get(dedup_id, manager.config().dedup_window)
+            .is_some()
+        {
+            debug!(
+                "Tick {} of \"{}\" was already materialized",
+                tick, schedule_name
+            );
+            return Ok(());
+        }
+    }
+
+    let commit = queue
+        .log_event(name, manager, Event::Push(MaybeOwned::Borrowed(&message)))
+        .await?;
+
+    database.push(message);
+    drop(database);
+
+    commit.wait().await
+}
+
+/// Materialize due ticks of queue schedules
+///
+/// Schedule cursor is advanced after each materialized tick,
+/// so ticks, that failed to materialize, are retried on the next run.
+async fn materialize_due(
+    manager: &Manager<'_>,
+    queue: &DB,
+    name: &str,
+    due: &[DueTicks<'_>],
+) -> Result<(), PersistenceError> {
+    for (schedule_name, schedule, ticks) in due.iter() {
+        debug!(
+            "Materializing {} ticks of \"{}\" in \"{}\"",
+            ticks.len(),
+            schedule_name,
+            name
+        );
+
+        for tick in ticks {
+            materialize(manager, queue, name, schedule_name, schedule, *tick).await?;
+
+            queue.schedule().await.advance(schedule_name, *tick);
+        }
+    }
+
+    Ok(())
+}
+
+/// Concurrently iterates over all queues with schedules, and materializes messages of ticks, that are due at `now`.
+///
+/// On the first run after start, due ticks were missed while node was down, so they are handled with catch-up policy of schedule.
+async fn execute_schedule(
+    manager: &Manager<'_>,
+    now: DateTime<Utc>,
+    catch_up: bool,
+) -> Result<(), PersistenceError> {
+    iter(manager.config().schedule.iter().flatten())
+        .map(Ok)
+        .try_for_each_concurrent(None, |(name, schedules)| async move {
+            let queue = match manager.queue(name) {
+                Ok(queue) => queue,
+                Err(e) => {
+                    error!("Unable to schedule messages into \"{}\": {}", name, e);
+                    return Ok(());
+                }
+            };
+
+            let due = {
+                let mut state = queue.schedule().await;
+
+                schedules
+                    .iter()
+                    .map(|(schedule_name, schedule)| {
+                        let ticks = state.due_ticks(schedule_name, schedule, now, catch_up);
+                        (&**schedule_name, schedule, ticks)
+                    })
+                    .filter(|(_, _, ticks)| !ticks.is_empty())
+                    .collect::<Vec<_>>()
+            };
+
+            let materialized = materialize_due(manager, queue, name, &due).await;
+
+            // Progress is persisted even if materialization failed,
+            // so that already materialized ticks are not repeated
+            if catch_up || !due.is_empty() {
+                manager.persist_schedule(name, queue).await?;
+            }
+
+            materialized
+        })
+        .await
+}
+
+/// Scheduler job spawner
+///
+/// Periodically materializes messages of queue schedules.
+pub async fn spawn_schedule(manager: &Manager<'_>) {
+    if manager.config().schedule.is_none() {
+        return;
+    }
+
+    debug!("Spawning scheduler.");
+
+    let timer = Duration::from_secs(SCHEDULER_TIMER);
+    let mut catch_up = true;
+
+    loop {
+        if let Err(e) = execute_schedule(manager, Utc::now(), catch_up).await {
+            error!("{}", e);
+        }
+
+        catch_up = false;
+
+        delay_for(timer).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, collections::HashMap};
+
+    use chrono::{DateTime, TimeZone, Utc};
+    use spartan_lib::core::{dispatcher::SimpleDispatcher, payload::Dispatchable};
+    use tempfile::TempDir;
+
+    use super::execute_schedule;
+    use crate::{
+        config::{
+            persistence::{Persistence, PersistenceConfig},
+            schedule::{CatchUp, Schedule, Schedules},
+            Config,
+        },
+        node::Manager,
+    };
+
+    fn schedules(catch_up: CatchUp) -> HashMap<Box<str>, Schedules> {
+        let mut schedule: Schedule =
+            toml::from_str("cron = \"*/5 * * * *\"\n[message]\nbody = \"Hello, world\"").unwrap();
+
+        schedule.catch_up = catch_up;
+
+        let mut schedules = HashMap::new();
+        schedules.insert("every_5".into(), schedule);
+
+        let mut queues = HashMap::new();
+        queues.insert("test".into(), schedules);
+        queues
+    }
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 3, 1).and_hms(10, minute, 0)
+    }
+
+    #[tokio::test]
+    async fn test_schedule() {
+        let config = Config {
+            schedule: Some(schedules(CatchUp::Skip)),
+            ..Default::default()
+        };
+
+        let manager = Manager::new(&config);
+        let queue = manager.queue("test").unwrap();
+
+        execute_schedule(&manager, at(1), false).await.unwrap();
+        assert_eq!(queue.database().await.size(), 0);
+
+        execute_schedule(&manager, at(12), false).await.unwrap();
+        assert_eq!(queue.database().await.size(), 2);
+
+        execute_schedule(&manager, at(12), false).await.unwrap();
+        assert_eq!(queue.database().await.size(), 2);
+
+        assert_eq!(
+            queue.database().await.peek().unwrap().body(),
+            b"Hello, world"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_schedule_catch_up() {
+        let dir = TempDir::new().unwrap();
+
+        let config = Config {
+            schedule: Some(schedules(CatchUp::Latest)),
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Log,
+                path: Cow::Borrowed(dir.path()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        {
+            let manager = Manager::new(&config);
+
+            execute_schedule(&manager, at(1), true).await.unwrap();
+        }
+
+        let mut manager = Manager::new(&config);
+        manager.load_from_fs().await.unwrap();
+
+        let queue = manager.queue("test").unwrap();
+
+        assert_eq!(queue.schedule().await.processed_at("every_5"), Some(at(1)));
+
+        execute_schedule(&manager, at(22), true).await.unwrap();
+        assert_eq!(queue.database().await.size(), 1);
+    }
+}
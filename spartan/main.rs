@@ -53,6 +53,7 @@ async fn main() -> Result<(), Error> {
         Init(command) => command.dispatch(server).await?,
         #[cfg(feature = "replication")]
         Replica(command) => command.dispatch(server).await?,
+        Migrate(command) => command.dispatch(server).await?,
     };
 
     Ok(())
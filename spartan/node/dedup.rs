@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use spartan_lib::core::{message::Message, payload::Identifiable};
+
+use crate::node::event::Event;
+
+/// Message, that was pushed with deduplication ID
+#[derive(Serialize, Deserialize)]
+struct DedupEntry {
+    /// Message ID
+    id: <Message as Identifiable>::Id,
+
+    /// Message dispatch timestamp
+    pushed_at: i64,
+}
+
+impl DedupEntry {
+    fn in_window(&self, now: i64, window: u32) -> bool {
+        now - self.pushed_at < i64::from(window)
+    }
+}
+
+/// Time-bounded index of message deduplication IDs
+///
+/// Index is filled from push events, so it can be rebuilt from event log and replicated along with queue.
+#[derive(Serialize, Deserialize, Default)]
+pub struct DedupIndex {
+    entries: HashMap<Box<str>, DedupEntry>,
+}
+
+impl DedupIndex {
+    /// Get ID of message, that was pushed with provided deduplication ID inside of deduplication window
+    pub fn get(&self, dedup_id: &str, window: u32) -> Option<<Message as Identifiable>::Id> {
+        self.entries
+            .get(dedup_id)
+            .filter(|entry| entry.in_window(Utc::now().timestamp(), window))
+            .map(|entry| entry.id)
+    }
+
+    /// Record deduplication IDs of messages from push events
+    pub fn record(&mut self, event: &Event<'_>) {
+        match event {
+            Event::Push(message) => self.insert(message),
+            Event::PushBatch(messages) => messages.iter().for_each(|message| self.insert(message)),
+            _ => (),
+        }
+    }
+
+    /// Remove entries, that are outside of deduplication window
+    pub fn prune(&mut self, window: u32) {
+        let now = Utc::now().timestamp();
+
+        self.entries.retain(|_, entry| entry.in_window(now, window));
+    }
+
+    fn insert(&mut self, message: &Message) {
+        if let Some(dedup_id) = message.dedup_id() {
+            self.entries.insert(
+                dedup_id.into(),
+                DedupEntry {
+                    id: message.id(),
+                    pushed_at: message.time().dispatched_at().timestamp(),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maybe_owned::MaybeOwned;
+    use spartan_lib::core::{message::builder::MessageBuilder, payload::Identifiable};
+
+    use super::DedupIndex;
+    use crate::node::event::Event;
+
+    #[test]
+    fn test_record_push() {
+        let mut index = DedupIndex::default();
+
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .dedup_id("order")
+            .compose()
+            .unwrap();
+
+        index.record(&Event::Push(MaybeOwned::Borrowed(&message)));
+
+        assert_eq!(index.get("order", 60), Some(message.id()));
+        assert!(index.get("other", 60).is_none());
+    }
+
+    #[test]
+    fn test_record_push_batch() {
+        let mut index = DedupIndex::default();
+
+        let messages = vec![
+            MessageBuilder::default()
+                .body("Hello, world")
+                .compose()
+                .unwrap(),
+            MessageBuilder::default()
+                .body("Hello, world")
+                .dedup_id("order")
+                .compose()
+                .unwrap(),
+        ];
+
+        index.record(&Event::PushBatch(MaybeOwned::Borrowed(&messages)));
+
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!(index.get("order", 60), Some(messages[1].id()));
+    }
+
+    #[test]
+    fn test_window() {
+        let mut index = DedupIndex::default();
+
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .dedup_id("order")
+            .compose()
+            .unwrap();
+
+        index.record(&Event::Push(MaybeOwned::Borrowed(&message)));
+
+        assert!(index.get("order", 0).is_none());
+
+        index.prune(60);
+        assert_eq!(index.entries.len(), 1);
+
+        index.prune(0);
+        assert!(index.entries.is_empty());
+    }
+}
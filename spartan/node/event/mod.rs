@@ -0,0 +1,469 @@
+/// Frozen event layout, that was persisted before format versioning
+pub mod v0;
+
+use chrono::{serde::ts_nanoseconds, DateTime, Utc};
+use maybe_owned::MaybeOwned;
+use serde::{Deserialize, Serialize};
+use spartan_lib::{
+    core::{
+        db::StatusAwareDatabase,
+        dispatcher::{PositionBasedDelete, SimpleDispatcher},
+        message::Message,
+        payload::{Identifiable, Status},
+    },
+    uuid::Uuid,
+};
+
+/// Message reservation, that was made by pop
+///
+/// Pop result depends on current time and generates random receipt handle,
+/// so reservation is recorded as is, instead of repeating pop on replay.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Reservation {
+    id: <Message as Identifiable>::Id,
+    receipt: Uuid,
+    #[serde(with = "ts_nanoseconds")]
+    reserved_at: DateTime<Utc>,
+}
+
+impl Reservation {
+    /// Make reservation of message with new receipt handle
+    pub fn new(id: <Message as Identifiable>::Id, reserved_at: DateTime<Utc>) -> Reservation {
+        Reservation {
+            id,
+            receipt: Uuid::new_v4(),
+            reserved_at,
+        }
+    }
+
+    /// Get ID of reserved message
+    pub fn id(&self) -> <Message as Identifiable>::Id {
+        self.id
+    }
+
+    /// Get reservation of reserved message
+    ///
+    /// Returns [`None`], if message is not reserved
+    pub fn of(message: &Message) -> Option<Reservation> {
+        Some(Reservation {
+            id: message.id(),
+            receipt: (*message.state().receipt())?,
+            reserved_at: message
+                .time()
+                .timeout()
+                .obtained_at()
+                .as_ref()?
+                .with_timezone(&Utc),
+        })
+    }
+
+    /// Repeat reservation in database
+    ///
+    /// Does nothing, if message doesn't exist or is not reservable
+    fn apply<DB>(&self, database: &mut DB)
+    where
+        DB: StatusAwareDatabase<Message, PositionKey = <Message as Identifiable>::Id>,
+    {
+        if database
+            .get(self.id)
+            .filter(|message| message.reservable())
+            .is_none()
+        {
+            return;
+        }
+
+        if let Some(message) = StatusAwareDatabase::reserve(database, self.id) {
+            message.reserve_with_receipt(self.receipt, &self.reserved_at);
+        }
+    }
+}
+
+/// Database event
+///
+/// Only events that mutate database are present here
+///
+/// Pushed message is kept inline, as push events are created for each pushed message.
+///
+/// Pop and GC events contain their results (reservations and IDs of collected messages),
+/// so that replay produces exactly the same database.
+///
+/// Events, that depend on current time, record it (or IDs of affected messages),
+/// so that replay doesn't depend on replay-time clock.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug))]
+#[allow(clippy::large_enum_variant)]
+pub enum Event<'msg> {
+    Push(MaybeOwned<'msg, Message>),
+    PushBatch(MaybeOwned<'msg, Vec<Message>>),
+    Pop(Reservation),
+    PopBatch(Vec<Reservation>),
+    Requeue(<Message as Identifiable>::Id),
+    RequeueDelayed(
+        <Message as Identifiable>::Id,
+        u32,
+        #[serde(with = "ts_nanoseconds")] DateTime<Utc>,
+    ),
+    Touch(
+        <Message as Identifiable>::Id,
+        u32,
+        #[serde(with = "ts_nanoseconds")] DateTime<Utc>,
+    ),
+    RequeueExpired(Vec<<Message as Identifiable>::Id>),
+    Delete(<Message as Identifiable>::Id),
+    Gc(Vec<<Message as Identifiable>::Id>),
+    Clear,
+}
+
+#[cfg(feature = "replication")]
+impl<'msg> Event<'msg> {
+    /// Make `'static` [`Event`] by cloning message if needed
+    ///
+    /// If [`Event`] is of any variant but [`Event::Push`] or [`Event::PushBatch`], then does nothing
+    pub(super) fn into_owned(self) -> Event<'static> {
+        match self {
+            Event::Push(message) => Event::Push(MaybeOwned::Owned(message.into_owned())),
+            Event::PushBatch(messages) => {
+                Event::PushBatch(MaybeOwned::Owned(messages.into_owned()))
+            }
+            // These variants are needed to appease compiler
+            // since it doesn't know that all other variants are 'static
+            Event::Pop(reservation) => Event::Pop(reservation),
+            Event::PopBatch(reservations) => Event::PopBatch(reservations),
+            Event::Requeue(id) => Event::Requeue(id),
+            Event::RequeueDelayed(id, delay, requeued_at) => {
+                Event::RequeueDelayed(id, delay, requeued_at)
+            }
+            Event::Touch(id, timeout, touched_at) => Event::Touch(id, timeout, touched_at),
+            Event::RequeueExpired(ids) => Event::RequeueExpired(ids),
+            Event::Delete(id) => Event::Delete(id),
+            Event::Gc(ids) => Event::Gc(ids),
+            Event::Clear => Event::Clear,
+        }
+    }
+}
+
+#[cfg(test)]
+impl PartialEq for Event<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        matches!((self, other), (Event::Clear, Event::Clear))
+    }
+}
+
+pub trait EventLog<L>: Default {
+    fn from_log(log: L) -> Self {
+        let mut database = Self::default();
+        database.apply_log(log);
+        database
+    }
+
+    fn apply_log(&mut self, log: L);
+}
+
+impl Event<'_> {
+    /// Apply event to database
+    ///
+    /// Events are applied as is, so they must be checked against database state before being logged.
+    /// Recorded time is used instead of database clock, so applying event on primary
+    /// and replaying it on replica produce the same changes.
+    pub fn apply<DB>(self, database: &mut DB)
+    where
+        DB: StatusAwareDatabase<
+                Message,
+                PositionKey = <Message as Identifiable>::Id,
+                RequeueKey = <Message as Identifiable>::Id,
+            > + SimpleDispatcher<Message>
+            + PositionBasedDelete<Message>,
+    {
+        match self {
+            Event::Push(message) => match message {
+                MaybeOwned::Owned(message) => database.push(message),
+                MaybeOwned::Borrowed(_) => {
+                    panic!("Applying push event with borrowed message is not allowed.")
+                }
+            },
+            Event::PushBatch(messages) => match messages {
+                MaybeOwned::Owned(messages) => messages
+                    .into_iter()
+                    .for_each(|message| database.push(message)),
+                MaybeOwned::Borrowed(_) => {
+                    panic!("Applying push batch event with borrowed messages is not allowed.")
+                }
+            },
+            Event::Pop(reservation) => reservation.apply(database),
+            Event::PopBatch(reservations) => reservations
+                .iter()
+                .for_each(|reservation| reservation.apply(database)),
+            Event::Requeue(id) => {
+                StatusAwareDatabase::requeue(
+                    database,
+                    id,
+                    |message| message.requeueable(),
+                    |message| message.requeue(),
+                );
+            }
+            Event::RequeueDelayed(id, delay, requeued_at) => {
+                StatusAwareDatabase::requeue(
+                    database,
+                    id,
+                    |message| message.requeueable(),
+                    |message| {
+                        message.requeue();
+                        message.delay(delay, &requeued_at);
+                    },
+                );
+            }
+            Event::Touch(id, timeout, touched_at) => {
+                if let Some(message) = database.get_mut(id).filter(|message| message.requeueable())
+                {
+                    message.touch(timeout, &touched_at);
+                }
+            }
+            Event::RequeueExpired(ids) => ids.into_iter().for_each(|id| {
+                StatusAwareDatabase::requeue(
+                    database,
+                    id,
+                    |message| message.requeueable(),
+                    |message| message.requeue(),
+                );
+            }),
+            Event::Delete(id) => {
+                database.delete(id);
+            }
+            Event::Gc(ids) => ids.into_iter().for_each(|id| {
+                database.delete(id);
+            }),
+            Event::Clear => {
+                SimpleDispatcher::clear(database);
+            }
+        }
+    }
+}
+
+impl<L, DB> EventLog<L> for DB
+where
+    L: IntoIterator<Item = Event<'static>>,
+    DB: StatusAwareDatabase<
+            Message,
+            PositionKey = <Message as Identifiable>::Id,
+            RequeueKey = <Message as Identifiable>::Id,
+        > + SimpleDispatcher<Message>
+        + PositionBasedDelete<Message>
+        + Default,
+{
+    fn apply_log(&mut self, log: L) {
+        log.into_iter().for_each(|event| event.apply(self));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use maybe_owned::MaybeOwned;
+    use spartan_lib::{
+        core::{
+            db::{Database, StatusAwareDatabase},
+            dispatcher::{SimpleDispatcher, StatusAwareDispatcher},
+            message::{builder::MessageBuilder, Message},
+            payload::{Identifiable, Status},
+        },
+        uuid::Uuid,
+    };
+
+    use super::{Event, EventLog, Reservation};
+    use crate::node::DB;
+
+    fn reservation(id: Uuid) -> Reservation {
+        Reservation {
+            id,
+            receipt: Uuid::new_v4(),
+            reserved_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_events() {
+        let queue = DB::default();
+
+        let message = MessageBuilder::default().body("test").compose().unwrap();
+
+        let events = vec![Event::Push(MaybeOwned::Owned(message.clone()))];
+
+        queue.database().await.apply_log(events);
+
+        assert_eq!(queue.database().await.pop().unwrap().id(), message.id());
+    }
+
+    #[tokio::test]
+    async fn test_apply_batch_events() {
+        let queue = DB::default();
+
+        let message1 = MessageBuilder::default().body("test").compose().unwrap();
+        let message2 = MessageBuilder::default().body("test").compose().unwrap();
+
+        let events = vec![
+            Event::PushBatch(MaybeOwned::Owned(vec![message1.clone(), message2.clone()])),
+            Event::PopBatch(vec![reservation(message1.id())]),
+        ];
+
+        queue.database().await.apply_log(events);
+
+        assert_eq!(queue.database().await.pop().unwrap().id(), message2.id());
+        assert!(queue.database().await.pop().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_apply_touch_event() {
+        let queue = DB::default();
+
+        let message = MessageBuilder::default().body("test").compose().unwrap();
+
+        let events = vec![
+            Event::Push(MaybeOwned::Owned(message.clone())),
+            Event::Touch(message.id(), 60, Utc::now()),
+            Event::Pop(reservation(message.id())),
+            Event::Touch(message.id(), 120, Utc::now()),
+        ];
+
+        queue.database().await.apply_log(events);
+
+        let database = queue.database().await;
+        let message = database.get(message.id()).unwrap();
+
+        assert_eq!(*message.time().timeout().max(), 120);
+    }
+
+    #[tokio::test]
+    async fn test_apply_pop_event() {
+        let queue = DB::default();
+
+        let message = MessageBuilder::default().body("test").compose().unwrap();
+        let first = reservation(message.id());
+
+        let events = vec![
+            Event::Push(MaybeOwned::Owned(message.clone())),
+            Event::Pop(first),
+            // Message is not reservable anymore, so reservation is not repeated
+            Event::Pop(reservation(message.id())),
+        ];
+
+        queue.database().await.apply_log(events);
+
+        let database = queue.database().await;
+        let message = database.get(message.id()).unwrap();
+
+        assert_eq!(Reservation::of(message), Some(first));
+        assert_eq!(*message.state().tries(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let primary = DB::default();
+        let replica = DB::default();
+
+        let messages = vec![
+            MessageBuilder::default()
+                .body("first")
+                .group("customer")
+                .max_tries(2)
+                .compose()
+                .unwrap(),
+            MessageBuilder::default()
+                .body("second")
+                .group("customer")
+                .compose()
+                .unwrap(),
+            MessageBuilder::default()
+                .body("third")
+                .priority(10)
+                .compose()
+                .unwrap(),
+            MessageBuilder::default().body("fourth").compose().unwrap(),
+            MessageBuilder::default()
+                .body("garbage")
+                .max_tries(0)
+                .compose()
+                .unwrap(),
+        ];
+
+        let mut events = vec![Event::PushBatch(MaybeOwned::Owned(messages.clone()))];
+
+        {
+            let mut database = primary.database().await;
+
+            messages
+                .iter()
+                .cloned()
+                .for_each(|message| database.push(message));
+
+            let popped = database.pop().unwrap();
+            events.push(Event::Pop(Reservation::of(popped).unwrap()));
+
+            let popped = database.pop().unwrap();
+            let id = popped.id();
+            events.push(Event::Pop(Reservation::of(popped).unwrap()));
+
+            StatusAwareDispatcher::requeue(&mut *database, id).unwrap();
+            events.push(Event::Requeue(id));
+
+            let popped = database.pop().unwrap();
+            let id = popped.id();
+            events.push(Event::Pop(Reservation::of(popped).unwrap()));
+
+            let event = Event::Touch(id, 120, Utc::now());
+            event.clone().apply(&mut *database);
+            events.push(event);
+
+            let popped = database.pop().unwrap();
+            let id = popped.id();
+            events.push(Event::Pop(Reservation::of(popped).unwrap()));
+
+            let event = Event::RequeueDelayed(id, 30, Utc::now());
+            event.clone().apply(&mut *database);
+            events.push(event);
+
+            let ids = std::iter::from_fn(|| database.pop().map(Identifiable::id))
+                .take(2)
+                .collect::<Vec<_>>();
+            events.push(Event::PopBatch(
+                ids.iter()
+                    .filter_map(|id| database.get(*id))
+                    .filter_map(Reservation::of)
+                    .collect(),
+            ));
+
+            let expired_at = Utc::now() + Duration::hours(1);
+            let ids = database.reserved(|message| message.expired(&expired_at));
+            assert!(!ids.is_empty());
+
+            let event = Event::RequeueExpired(ids);
+            event.clone().apply(&mut *database);
+            events.push(event);
+
+            let garbage = database.drain_gc();
+            assert!(!garbage.is_empty());
+            events.push(Event::Gc(garbage.iter().map(Identifiable::id).collect()));
+        }
+
+        // Replica replays events later, so replay must not depend on its clock
+        tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+
+        replica.database().await.apply_log(events);
+
+        let primary = primary.database().await;
+        let replica = replica.database().await;
+
+        assert_eq!(primary.size(), replica.size());
+
+        for message in messages.iter() {
+            assert_eq!(
+                primary
+                    .get(message.id())
+                    .map(|message: &Message| format!("{:?}", message)),
+                replica
+                    .get(message.id())
+                    .map(|message: &Message| format!("{:?}", message))
+            );
+        }
+    }
+}
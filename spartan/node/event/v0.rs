@@ -0,0 +1,70 @@
+use maybe_owned::MaybeOwned;
+use serde::Deserialize;
+use spartan_lib::{
+    core::{
+        clock::Clock,
+        db::StatusAwareDatabase,
+        dispatcher::{PositionBasedDelete, SimpleDispatcher, StatusAwareDispatcher},
+        message::{v0::Message, Message as CurrentMessage},
+        payload::{Dispatchable, Identifiable},
+    },
+    uuid::Uuid,
+};
+
+use super::{Event as CurrentEvent, Reservation};
+
+/// Database event, that doesn't contain results of pop and GC
+#[derive(Deserialize)]
+pub enum Event {
+    Push(Message),
+    Pop,
+    Requeue(Uuid),
+    Delete(Uuid),
+    Gc,
+    Clear,
+}
+
+impl Event {
+    /// Convert event into current one, applying it to `database`
+    ///
+    /// Results of pop and GC are taken from `database` at conversion time,
+    /// the same way as they were taken on replay of log, that contains such event.
+    ///
+    /// Returns [`None`], if event doesn't change database.
+    pub fn upgrade<DB>(self, database: &mut DB) -> Option<CurrentEvent<'static>>
+    where
+        DB: StatusAwareDatabase<
+                CurrentMessage,
+                PositionKey = <CurrentMessage as Identifiable>::Id,
+                RequeueKey = <CurrentMessage as Identifiable>::Id,
+            > + SimpleDispatcher<CurrentMessage>
+            + PositionBasedDelete<CurrentMessage>,
+    {
+        let event = match self {
+            Event::Push(message) => CurrentEvent::Push(MaybeOwned::Owned(message.into())),
+            // Reservation is already made by pop, so it's not applied again
+            Event::Pop => {
+                return StatusAwareDispatcher::pop(database)
+                    .and_then(Reservation::of)
+                    .map(CurrentEvent::Pop)
+            }
+            Event::Requeue(id) => CurrentEvent::Requeue(id),
+            Event::Delete(id) => CurrentEvent::Delete(id),
+            Event::Gc => {
+                let now = database.clock().now();
+                let ids = database.positions(|message| message.gc(&now));
+
+                if ids.is_empty() {
+                    return None;
+                }
+
+                CurrentEvent::Gc(ids)
+            }
+            Event::Clear => CurrentEvent::Clear,
+        };
+
+        event.clone().apply(database);
+
+        Some(event)
+    }
+}
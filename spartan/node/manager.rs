@@ -1,4 +1,7 @@
+use std::{collections::HashMap, path::Path};
+
 use futures_util::{stream::iter, StreamExt, TryStreamExt};
+use spartan_lib::core::{db::TreeDatabase, message::Message};
 use thiserror::Error;
 use warp::hyper::StatusCode;
 
@@ -8,8 +11,12 @@ use crate::{
     node::{
         event::Event,
         persistence::{
-            log::Log,
-            snapshot::{PersistMode, Snapshot},
+            encryption::Encryption,
+            log::{
+                writer::{Commit, LogWriter},
+                Log,
+            },
+            snapshot::{PersistMode, Snapshot, SCHEDULE_FILE},
             PersistenceError,
         },
         Node, DB,
@@ -35,6 +42,11 @@ pub struct Manager<'c> {
 
     /// Node
     node: Node<'c>,
+
+    /// Queue log writers
+    ///
+    /// Empty if log persistence is disabled
+    writers: HashMap<&'c str, LogWriter<'c>>,
 }
 
 impl<'c> Manager<'c> {
@@ -42,7 +54,28 @@ impl<'c> Manager<'c> {
     pub fn new(config: &'c Config) -> Manager<'c> {
         let mut node = Node::default();
         node.load_from_config(config);
-        Manager { config, node }
+
+        let mut manager = Manager {
+            config,
+            node,
+            writers: HashMap::new(),
+        };
+
+        if let Some(persistence) = config
+            .persistence
+            .as_ref()
+            .filter(|config| matches!(config.mode, Persistence::Log))
+        {
+            let log = Log::new(persistence, manager.encryption());
+
+            manager.writers = config
+                .queues
+                .iter()
+                .map(|name| (&**name, log.writer(&**name)))
+                .collect();
+        }
+
+        manager
     }
 
     /// Obtain queue from local node
@@ -58,11 +91,16 @@ impl<'c> Manager<'c> {
         &self.node
     }
 
+    /// Get persistence encryption, if encryption key is configured
+    fn encryption(&self) -> Option<Encryption<'c>> {
+        Encryption::from_config(self.config)
+    }
+
     pub async fn load_from_fs(&mut self) -> Result<(), PersistenceError> {
         if let Some(config) = self.config.persistence.as_ref() {
             match config.mode {
                 Persistence::Log => {
-                    let driver = Log::new(config);
+                    let driver = Log::new(config, self.encryption());
 
                     for name in self.config.queues.iter() {
                         let queue = driver.load_queue(&**name).await?;
@@ -70,7 +108,7 @@ impl<'c> Manager<'c> {
                     }
                 }
                 Persistence::Snapshot => {
-                    let driver = Snapshot::new(config);
+                    let driver = Snapshot::new(config, self.encryption());
 
                     for name in self.config.queues.iter() {
                         let queue = driver.load_queue(&**name).await?;
@@ -90,7 +128,7 @@ impl<'c> Manager<'c> {
                 Persistence::Log => PersistMode::Replication,
             };
 
-            let driver = &Snapshot::new(config);
+            let driver = &Snapshot::new(config, self.encryption());
 
             iter(self.node.iter())
                 .map(Ok)
@@ -103,14 +141,54 @@ impl<'c> Manager<'c> {
         }
     }
 
-    pub async fn log(&self, queue: &str, event: &Event<'_>) -> Result<(), PersistenceError> {
+    /// Fold sealed log segments of all queues into compacted logs
+    ///
+    /// Does nothing, unless [`Log`] driver with compaction is enabled
+    pub async fn compact(&self) -> Result<(), PersistenceError> {
+        if let Some(config) = self
+            .config
+            .persistence
+            .as_ref()
+            .filter(|config| matches!(config.mode, Persistence::Log) && config.compaction)
+        {
+            let driver = Log::new(config, self.encryption());
+
+            for name in self.config.queues.iter() {
+                driver.compact::<_, TreeDatabase<Message>>(&**name).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Append event to queue log
+    ///
+    /// Event is ordered in log immediately, and returned [`Commit`] resolves after it reaches durability point of fsync policy.
+    pub fn log(&self, queue: &str, event: &Event<'_>) -> Result<Commit, PersistenceError> {
+        match self.writers.get(queue) {
+            Some(writer) => writer.append(event),
+            None => Ok(Commit::done()),
+        }
+    }
+
+    /// Persist schedule state of queue
+    ///
+    /// Messages are persisted immediately by [`Log`] driver, so schedule state has to follow them,
+    /// otherwise schedule ticks would be materialized again after restart.
+    /// [`Snapshot`] driver persists schedule state along with queue.
+    pub async fn persist_schedule(&self, name: &str, queue: &DB) -> Result<(), PersistenceError> {
         if let Some(config) = self
             .config
             .persistence
             .as_ref()
             .filter(|config| matches!(config.mode, Persistence::Log))
         {
-            Log::new(config).persist_event(event, queue).await
+            Snapshot::new(config, self.encryption())
+                .persist(
+                    &*queue.schedule().await,
+                    Path::new(name).join(SCHEDULE_FILE),
+                )
+                .await
         } else {
             Ok(())
         }
@@ -142,17 +220,20 @@ mod tests {
 
     use maybe_owned::MaybeOwned;
     use spartan_lib::core::{
-        dispatcher::SimpleDispatcher, message::builder::MessageBuilder, payload::Dispatchable,
+        dispatcher::SimpleDispatcher,
+        message::builder::MessageBuilder,
+        payload::{Dispatchable, Identifiable},
     };
     use tempfile::TempDir;
 
     use super::Manager;
     use crate::{
         config::{
+            encryption::EncryptionKey,
             persistence::{Persistence, PersistenceConfig},
             Config,
         },
-        node::event::Event,
+        node::{event::Event, persistence::PersistenceError},
     };
 
     #[tokio::test]
@@ -193,7 +274,75 @@ mod tests {
                 .peek()
                 .unwrap()
                 .body(),
-            "Hello, world"
+            b"Hello, world"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_encrypted_snapshot() {
+        let dir = TempDir::new().unwrap();
+
+        let config = Config {
+            encryption_key: Some(EncryptionKey::from([1; 32])),
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Snapshot,
+                path: Cow::Borrowed(dir.path()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        {
+            let manager = Manager::new(&config);
+
+            manager.queue("test").unwrap().database().await.push(
+                MessageBuilder::default()
+                    .body("Hello, world")
+                    .compose()
+                    .unwrap(),
+            );
+
+            manager.snapshot().await.unwrap();
+        }
+
+        let missing_key = Config {
+            encryption_key: None,
+            ..config
+        };
+
+        assert!(matches!(
+            Manager::new(&missing_key).load_from_fs().await.unwrap_err(),
+            PersistenceError::MissingEncryptionKey
+        ));
+
+        let wrong_key = Config {
+            encryption_key: Some(EncryptionKey::from([2; 32])),
+            ..missing_key
+        };
+
+        assert!(matches!(
+            Manager::new(&wrong_key).load_from_fs().await.unwrap_err(),
+            PersistenceError::DecryptionError
+        ));
+
+        let rotated_key = Config {
+            previous_encryption_keys: Some(Box::new([EncryptionKey::from([1; 32])])),
+            ..wrong_key
+        };
+
+        let mut manager = Manager::new(&rotated_key);
+        manager.load_from_fs().await.unwrap();
+
+        assert_eq!(
+            manager
+                .queue("test")
+                .unwrap()
+                .database()
+                .await
+                .peek()
+                .unwrap()
+                .body(),
+            b"Hello, world"
         );
     }
 
@@ -224,6 +373,8 @@ mod tests {
                             .unwrap(),
                     )),
                 )
+                .unwrap()
+                .wait()
                 .await
                 .unwrap();
         }
@@ -240,7 +391,7 @@ mod tests {
                 .peek()
                 .unwrap()
                 .body(),
-            "Hello, world"
+            b"Hello, world"
         );
 
         if compaction {
@@ -256,7 +407,7 @@ mod tests {
                     .peek()
                     .unwrap()
                     .body(),
-                "Hello, world"
+                b"Hello, world"
             );
         }
     }
@@ -270,4 +421,106 @@ mod tests {
     async fn test_load_log_compaction() {
         load_log(true).await;
     }
+
+    #[tokio::test]
+    async fn test_load_snapshot_dedup() {
+        let dir = TempDir::new().unwrap();
+
+        let config = Config {
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Snapshot,
+                path: Cow::Borrowed(dir.path()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .dedup_id("order")
+            .compose()
+            .unwrap();
+
+        {
+            let manager = Manager::new(&config);
+
+            manager
+                .queue("test")
+                .unwrap()
+                .log_event(
+                    "test",
+                    &manager,
+                    Event::Push(MaybeOwned::Borrowed(&message)),
+                )
+                .await
+                .unwrap()
+                .wait()
+                .await
+                .unwrap();
+
+            manager.snapshot().await.unwrap();
+        }
+
+        let mut manager = Manager::new(&config);
+        manager.load_from_fs().await.unwrap();
+
+        assert_eq!(
+            manager
+                .queue("test")
+                .unwrap()
+                .dedup()
+                .await
+                .get("order", 60),
+            Some(message.id())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_log_dedup() {
+        let dir = TempDir::new().unwrap();
+
+        let config = Config {
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Log,
+                path: Cow::Borrowed(dir.path()),
+                compaction: true,
+                ..Default::default()
+            }),
+            queues: vec!["test".to_string().into_boxed_str()].into_boxed_slice(),
+            ..Default::default()
+        };
+
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .dedup_id("order")
+            .compose()
+            .unwrap();
+
+        {
+            let manager = Manager::new(&config);
+
+            manager
+                .log("test", &Event::Push(MaybeOwned::Borrowed(&message)))
+                .unwrap()
+                .wait()
+                .await
+                .unwrap();
+        }
+
+        // Second load restores index from compacted state, as log is pruned by first load
+        for _ in 0..2 {
+            let mut manager = Manager::new(&config);
+            manager.load_from_fs().await.unwrap();
+
+            assert_eq!(
+                manager
+                    .queue("test")
+                    .unwrap()
+                    .dedup()
+                    .await
+                    .get("order", 60),
+                Some(message.id())
+            );
+        }
+    }
 }
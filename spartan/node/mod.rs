@@ -10,6 +10,12 @@ pub mod persistence;
 /// Database event
 pub mod event;
 
+/// Message deduplication index
+pub mod dedup;
+
+/// Queue schedule state
+pub mod schedule;
+
 #[cfg(feature = "replication")]
 /// Database replication
 pub mod replication;
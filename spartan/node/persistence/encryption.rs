@@ -0,0 +1,324 @@
+use std::{borrow::Cow, iter::once, path::Path};
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use rand::{thread_rng, RngCore};
+
+use crate::{
+    config::{encryption::EncryptionKey, Config},
+    node::persistence::PersistenceError,
+};
+
+/// Prefix of encrypted data
+const MAGIC: &[u8] = b"SPARTENC";
+
+/// XChaCha20-Poly1305 nonce length in bytes
+const NONCE_LENGTH: usize = 24;
+
+/// Authenticated encryption of persisted data
+///
+/// Data is encrypted using XChaCha20-Poly1305 with random nonce,
+/// which is stored between magic prefix and ciphertext:
+/// ```
+/// +----------+
+/// |  Magic   |
+/// +----------+
+/// |  Nonce   |
+/// +----------+
+/// |          |
+/// |Ciphertext|
+/// |          |
+/// +----------+
+/// ```
+/// Ciphertext is authenticated along with associated data (see [`associated_data`]),
+/// so encrypted data can't be moved into another file.
+///
+/// Data is always encrypted with current key.
+/// Previous keys are used only for decryption, so files written before key rotation remain readable.
+#[derive(Copy, Clone)]
+pub struct Encryption<'c> {
+    /// Current key
+    key: &'c EncryptionKey,
+
+    /// Keys, that were used before rotation
+    previous_keys: &'c [EncryptionKey],
+}
+
+impl<'c> Encryption<'c> {
+    pub fn new(key: &'c EncryptionKey, previous_keys: &'c [EncryptionKey]) -> Self {
+        Encryption { key, previous_keys }
+    }
+
+    /// Get persistence encryption, if encryption key is configured
+    pub fn from_config(config: &'c Config<'_>) -> Option<Self> {
+        let previous_keys = config
+            .previous_encryption_keys
+            .as_deref()
+            .unwrap_or_default();
+
+        config
+            .encryption_key
+            .as_ref()
+            .map(|key| Encryption::new(key, previous_keys))
+    }
+
+    /// Get current key, that new data is encrypted with
+    pub fn key(&self) -> &'c EncryptionKey {
+        self.key
+    }
+
+    /// Decrypt data, trying current key first, and previous keys after it
+    fn decrypt(&self, data: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+        let data = &data[MAGIC.len()..];
+
+        if data.len() < NONCE_LENGTH {
+            return Err(PersistenceError::DecryptionError);
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+
+        once(self.key)
+            .chain(self.previous_keys)
+            .find_map(|key| {
+                cipher(key)
+                    .decrypt(
+                        XNonce::from_slice(nonce),
+                        Payload {
+                            msg: ciphertext,
+                            aad: associated_data,
+                        },
+                    )
+                    .ok()
+            })
+            .ok_or(PersistenceError::DecryptionError)
+    }
+}
+
+fn cipher(key: &EncryptionKey) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(Key::from_slice(key.as_bytes()))
+}
+
+/// Encrypt data using `key` and new random nonce
+fn encrypt(
+    key: &EncryptionKey,
+    data: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, PersistenceError> {
+    let mut nonce = [0; NONCE_LENGTH];
+    thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = cipher(key)
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: data,
+                aad: associated_data,
+            },
+        )
+        .map_err(|_| PersistenceError::EncryptionError)?;
+
+    let mut buf = Vec::with_capacity(MAGIC.len() + NONCE_LENGTH + ciphertext.len());
+
+    buf.extend(MAGIC);
+    buf.extend(&nonce);
+    buf.extend(ciphertext);
+
+    Ok(buf)
+}
+
+/// Make associated data of persisted `file`
+///
+/// `file` is a path relative to database directory, so it consists of queue name and file kind
+/// (like `queue_name/queue`), which are both bound to ciphertext.
+/// Log segments and previous snapshot generations use path of file, that they were rotated from.
+pub fn associated_data(file: &Path) -> Vec<u8> {
+    let mut buf = Vec::new();
+
+    for component in file.components() {
+        let component = component.as_os_str().to_string_lossy();
+
+        buf.extend(&(component.len() as u64).to_le_bytes());
+        buf.extend(component.as_bytes());
+    }
+
+    buf
+}
+
+/// Check if data was encrypted
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypt serialized data with current `key`, if encryption is enabled
+pub fn seal(
+    key: Option<&EncryptionKey>,
+    data: Vec<u8>,
+    associated_data: &[u8],
+) -> Result<Vec<u8>, PersistenceError> {
+    match key {
+        Some(key) => encrypt(key, &data, associated_data),
+        None => Ok(data),
+    }
+}
+
+/// Decrypt data, if encryption is enabled
+///
+/// Unencrypted data is rejected when encryption is enabled, as it's not authenticated.
+/// Existing database is encrypted by migration, see [`open_unencrypted`].
+pub fn open<'a>(
+    encryption: Option<Encryption<'_>>,
+    data: &'a [u8],
+    associated_data: &[u8],
+) -> Result<Cow<'a, [u8]>, PersistenceError> {
+    match (encryption, is_sealed(data)) {
+        (Some(_), false) => Err(PersistenceError::UnencryptedData),
+        _ => open_unencrypted(encryption, data, associated_data),
+    }
+}
+
+/// Decrypt data, if it was encrypted, returning unencrypted data as is
+///
+/// Should be used only by migration, that encrypts database written before encryption was enabled.
+pub fn open_unencrypted<'a>(
+    encryption: Option<Encryption<'_>>,
+    data: &'a [u8],
+    associated_data: &[u8],
+) -> Result<Cow<'a, [u8]>, PersistenceError> {
+    if is_sealed(data) {
+        encryption
+            .ok_or(PersistenceError::MissingEncryptionKey)?
+            .decrypt(data, associated_data)
+            .map(Cow::Owned)
+    } else {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"Hello, world";
+
+    const AAD: &[u8] = b"test/queue";
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = EncryptionKey::from([1; 32]);
+        let encryption = Encryption::new(&key, &[]);
+
+        let sealed = seal(Some(&key), DATA.to_vec(), AAD).unwrap();
+
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(DATA.len()).any(|window| window == DATA));
+        assert_eq!(&*open(Some(encryption), &sealed, AAD).unwrap(), DATA);
+    }
+
+    #[test]
+    fn test_unique_nonce() {
+        let key = EncryptionKey::from([1; 32]);
+
+        assert_ne!(
+            seal(Some(&key), DATA.to_vec(), AAD).unwrap(),
+            seal(Some(&key), DATA.to_vec(), AAD).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let key = EncryptionKey::from([1; 32]);
+        let wrong_key = EncryptionKey::from([2; 32]);
+
+        let sealed = seal(Some(&key), DATA.to_vec(), AAD).unwrap();
+
+        assert!(matches!(
+            open(Some(Encryption::new(&wrong_key, &[])), &sealed, AAD),
+            Err(PersistenceError::DecryptionError)
+        ));
+    }
+
+    #[test]
+    fn test_missing_key() {
+        let key = EncryptionKey::from([1; 32]);
+
+        let sealed = seal(Some(&key), DATA.to_vec(), AAD).unwrap();
+
+        assert!(matches!(
+            open(None, &sealed, AAD),
+            Err(PersistenceError::MissingEncryptionKey)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_data() {
+        let key = EncryptionKey::from([1; 32]);
+        let encryption = Encryption::new(&key, &[]);
+
+        let mut sealed = seal(Some(&key), DATA.to_vec(), AAD).unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+
+        assert!(matches!(
+            open(Some(encryption), &sealed, AAD),
+            Err(PersistenceError::DecryptionError)
+        ));
+    }
+
+    #[test]
+    fn test_associated_data() {
+        let key = EncryptionKey::from([1; 32]);
+        let encryption = Encryption::new(&key, &[]);
+
+        let queue = associated_data(Path::new("test/queue"));
+        let dedup = associated_data(Path::new("test/dedup"));
+        let other_queue = associated_data(Path::new("test_2/queue"));
+
+        assert_ne!(queue, dedup);
+        assert_ne!(queue, other_queue);
+
+        let sealed = seal(Some(&key), DATA.to_vec(), &queue).unwrap();
+
+        assert_eq!(&*open(Some(encryption), &sealed, &queue).unwrap(), DATA);
+
+        // Encrypted file can't be moved into another queue or replace file of other kind
+        for aad in &[dedup, other_queue] {
+            assert!(matches!(
+                open(Some(encryption), &sealed, aad),
+                Err(PersistenceError::DecryptionError)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let previous_keys = [EncryptionKey::from([1; 32])];
+        let key = EncryptionKey::from([2; 32]);
+
+        let sealed = seal(Some(&previous_keys[0]), DATA.to_vec(), AAD).unwrap();
+
+        assert_eq!(
+            &*open(Some(Encryption::new(&key, &previous_keys)), &sealed, AAD).unwrap(),
+            DATA
+        );
+    }
+
+    #[test]
+    fn test_unencrypted_data() {
+        let key = EncryptionKey::from([1; 32]);
+        let encryption = Encryption::new(&key, &[]);
+
+        // Unencrypted data is not authenticated, so it's accepted only by migration
+        assert!(matches!(
+            open(Some(encryption), DATA, AAD),
+            Err(PersistenceError::UnencryptedData)
+        ));
+        assert_eq!(
+            &*open_unencrypted(Some(encryption), DATA, AAD).unwrap(),
+            DATA
+        );
+
+        assert_eq!(&*seal(None, DATA.to_vec(), AAD).unwrap(), DATA);
+        assert_eq!(&*open(None, DATA, AAD).unwrap(), DATA);
+    }
+}
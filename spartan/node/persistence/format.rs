@@ -0,0 +1,177 @@
+use std::{borrow::Cow, convert::TryInto, mem::size_of};
+
+use crate::{node::persistence::PersistenceError, VERSION};
+
+/// Versioned header of database file
+#[derive(Debug)]
+pub enum FileHeader<'a> {
+    /// Header of expected schema version
+    Current {
+        /// Version of Spartan, that wrote file
+        spartan_version: Cow<'a, str>,
+
+        /// Header length, file body starts right after it
+        len: usize,
+    },
+
+    /// Header of another schema version, which layout may differ from current one
+    Other(u32),
+
+    /// File is shorter than header, and probably was not completely written
+    Torn,
+
+    /// File doesn't start with magic, so it was written before headers were introduced
+    Missing,
+}
+
+/// Make versioned file header
+///
+/// Header is written once, at the beginning of database file.
+/// Spartan version is prefixed with its length in bytes.
+/// ```
+/// +---------------+
+/// |     Magic     |
+/// +---------------+
+/// |Schema version |
+/// +---------------+
+/// |Spartan version|
+/// +---------------+
+/// ```
+pub fn make_header(magic: &[u8], version: u32) -> Vec<u8> {
+    let spartan_version = &VERSION.as_bytes()[..VERSION.len().min(u8::MAX as usize)];
+
+    let mut buf = Vec::with_capacity(
+        magic.len() + size_of::<u32>() + size_of::<u8>() + spartan_version.len(),
+    );
+
+    buf.extend(magic);
+    buf.extend(&version.to_le_bytes());
+    buf.push(spartan_version.len() as u8);
+    buf.extend(spartan_version);
+
+    buf
+}
+
+/// Read header from the beginning of database file
+///
+/// Layout of header after schema version is known only for `version`,
+/// so headers of other versions are returned as [`FileHeader::Other`].
+pub fn read_header<'a>(data: &'a [u8], magic: &[u8], version: u32) -> FileHeader<'a> {
+    if data.len() < magic.len() {
+        return if magic.starts_with(data) {
+            FileHeader::Torn
+        } else {
+            FileHeader::Missing
+        };
+    }
+
+    if !data.starts_with(magic) {
+        return FileHeader::Missing;
+    }
+
+    let rest = &data[magic.len()..];
+
+    if rest.len() < size_of::<u32>() {
+        return FileHeader::Torn;
+    }
+
+    let (file_version, rest) = rest.split_at(size_of::<u32>());
+    let file_version = u32::from_le_bytes(file_version.try_into().unwrap());
+
+    if file_version != version {
+        return FileHeader::Other(file_version);
+    }
+
+    let spartan_version_len = match rest.first() {
+        Some(len) if rest.len() > *len as usize => *len as usize,
+        _ => return FileHeader::Torn,
+    };
+
+    FileHeader::Current {
+        spartan_version: String::from_utf8_lossy(&rest[1..=spartan_version_len]),
+        len: magic.len() + size_of::<u32>() + size_of::<u8>() + spartan_version_len,
+    }
+}
+
+/// Get offset of file body, checking that header has expected schema `version`
+///
+/// Returns [`None`], if header is torn.
+pub fn body_offset(
+    data: &[u8],
+    magic: &[u8],
+    version: u32,
+) -> Result<Option<usize>, PersistenceError> {
+    match read_header(data, magic, version) {
+        FileHeader::Current {
+            spartan_version,
+            len,
+        } => {
+            debug!("File was written by Spartan {}", spartan_version);
+            Ok(Some(len))
+        }
+        FileHeader::Other(file_version) if file_version > version => {
+            Err(PersistenceError::UnsupportedFormatVersion(file_version))
+        }
+        FileHeader::Other(_) | FileHeader::Missing => Err(PersistenceError::OutdatedFormat),
+        FileHeader::Torn => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: &[u8] = b"SPARTTST";
+
+    #[test]
+    fn test_read_header() {
+        let mut data = make_header(MAGIC, 2);
+        let len = data.len();
+        data.extend(b"body");
+
+        match read_header(&data, MAGIC, 2) {
+            FileHeader::Current {
+                spartan_version,
+                len: header_len,
+            } => {
+                assert_eq!(spartan_version, VERSION);
+                assert_eq!(header_len, len);
+            }
+            header => panic!("Unexpected header: {:?}", header),
+        }
+
+        assert!(matches!(read_header(&data, MAGIC, 3), FileHeader::Other(2)));
+        assert!(matches!(
+            read_header(b"body", MAGIC, 2),
+            FileHeader::Missing
+        ));
+
+        for torn_len in 0..len {
+            assert!(matches!(
+                read_header(&data[..torn_len], MAGIC, 2),
+                FileHeader::Torn
+            ));
+        }
+    }
+
+    #[test]
+    fn test_body_offset() {
+        let data = make_header(MAGIC, 2);
+
+        assert_eq!(body_offset(&data, MAGIC, 2).unwrap(), Some(data.len()));
+        assert_eq!(body_offset(&[], MAGIC, 2).unwrap(), None);
+
+        assert!(matches!(
+            body_offset(&data, MAGIC, 1),
+            Err(PersistenceError::UnsupportedFormatVersion(2))
+        ));
+        assert!(matches!(
+            body_offset(&data, MAGIC, 3),
+            Err(PersistenceError::OutdatedFormat)
+        ));
+        assert!(matches!(
+            body_offset(b"body", MAGIC, 2),
+            Err(PersistenceError::OutdatedFormat)
+        ));
+    }
+}
@@ -1,40 +1,196 @@
+/// Long-lived log writer with group commit
+pub mod writer;
+
 use std::{
     convert::TryInto,
-    io::SeekFrom,
+    ffi::OsString,
+    io::{Error as IoError, ErrorKind},
     mem::size_of,
     path::{Path, PathBuf},
 };
 
-use bincode::{deserialize, serialize_into, serialized_size};
+use bincode::{deserialize, serialize};
 use cfg_if::cfg_if;
+use chrono::Utc;
+use crc32fast::{hash, Hasher};
 use once_cell::sync::OnceCell;
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spartan_lib::core::{
+    db::{v0, TreeDatabase},
+    message::Message,
+};
 use tokio::{
-    fs::{create_dir, remove_file, OpenOptions},
-    io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWriteExt},
+    fs::{metadata, read_dir, remove_file, rename, OpenOptions},
+    io::AsyncReadExt,
 };
 
-#[cfg(feature = "replication")]
-use crate::node::persistence::snapshot::REPLICATION_FILE as SNAPSHOT_REPLICATION_FILE;
 use crate::{
-    config::persistence::PersistenceConfig,
+    config::{encryption::EncryptionKey, persistence::PersistenceConfig},
     node::{
+        dedup::DedupIndex,
         event::{Event, EventLog},
-        persistence::{snapshot::Snapshot, PersistenceError},
+        persistence::{
+            encryption::{associated_data, is_sealed, open, open_unencrypted, seal, Encryption},
+            format::{body_offset, make_header, read_header, FileHeader},
+            log::writer::LogWriter,
+            snapshot::{replace, write_temp, PersistMode, Snapshot, DEDUP_FILE},
+            PersistenceError,
+        },
         Queue,
     },
 };
 
 /// Queue log file name
-const QUEUE_FILE: &str = "queue_log";
+pub(crate) const QUEUE_FILE: &str = "queue_log";
+
+/// Suffix of copy of corrupted log file
+const CORRUPT_COPY_SUFFIX: &str = ".corrupt";
 
 /// Queue compacted log file name
-const QUEUE_COMPACTION_FILE: &str = "queue_compacted_log";
+pub(crate) const QUEUE_COMPACTION_FILE: &str = "queue_compacted_log";
+
+/// Log file magic
+const LOG_MAGIC: &[u8] = b"SPARTLOG";
+
+/// Log file format version
+///
+/// Version 1 header doesn't contain Spartan version, version 2 entries don't have entry size checksum,
+/// log without header is written before checksums were introduced
+const LOG_VERSION: u32 = 3;
+
+/// Size of version 1 log file header (magic and version)
+const LOG_V1_HEADER_SIZE: usize = LOG_MAGIC.len() + size_of::<u32>();
+
+/// Size of log entry header (entry size, entry size checksum and entry checksum)
+const ENTRY_HEADER_SIZE: usize = size_of::<u64>() + size_of::<u32>() + size_of::<u32>();
+
+/// Size of version 1 and 2 log entry header (entry size and checksum)
+const ENTRY_V2_HEADER_SIZE: usize = size_of::<u64>() + size_of::<u32>();
+
+/// Get path of sealed log segment with sequence number `segment`
+///
+/// Segments are named after active log file: `queue_log.1`, `queue_log.2`, ...
+fn segment_path(path: &Path, segment: u64) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", segment));
+
+    name.into()
+}
+
+/// Get sealed log segments of queue directory `dir`, ordered from oldest to newest
+async fn read_segments(dir: &Path) -> Result<Vec<(u64, PathBuf)>, IoError> {
+    let mut entries = match read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let prefix = format!("{}.", QUEUE_FILE);
+    let mut segments = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await? {
+        let segment = entry
+            .file_name()
+            .to_str()
+            .filter(|name| name.starts_with(&prefix))
+            .and_then(|name| name[prefix.len()..].parse().ok());
+
+        if let Some(segment) = segment {
+            segments.push((segment, entry.path()));
+        }
+    }
+
+    segments.sort_unstable_by_key(|(segment, _)| *segment);
+
+    Ok(segments)
+}
+
+/// Check if `name` is a name of log file or sealed log segment
+pub(crate) fn is_log_file(name: &str) -> bool {
+    if name == QUEUE_FILE {
+        return true;
+    }
+
+    match name
+        .strip_prefix(QUEUE_FILE)
+        .and_then(|name| name.strip_prefix('.'))
+    {
+        Some(segment) => !segment.is_empty() && segment.bytes().all(|byte| byte.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Get path of copy of corrupted log file, that is kept before its truncation
+///
+/// Copies are named after log file and time of truncation: `queue_log.corrupt.1600000000`.
+fn corrupt_copy_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(
+        "{}.{}",
+        CORRUPT_COPY_SUFFIX,
+        Utc::now().timestamp()
+    ));
+
+    name.into()
+}
+
+/// Check if `name` is a name of copy of corrupted log file
+pub(crate) fn is_corrupt_copy(name: &str) -> bool {
+    name.contains(CORRUPT_COPY_SUFFIX)
+}
+
+/// Conversion of log entry without header to current layout, which drops entry by returning [`None`]
+pub(crate) type UpgradeEntry<'a> =
+    dyn FnMut(&[u8]) -> Result<Option<Vec<u8>>, PersistenceError> + 'a;
+
+/// Log entry, read from the beginning of log slice
+enum LogEntry<'a> {
+    /// Entry with valid checksum
+    Valid(&'a [u8]),
+
+    /// Incomplete entry at the end of log
+    Torn,
+
+    /// Entry with invalid checksum, followed by other data
+    Corrupted,
+}
+
+/// Parsed log file
+struct ParsedLog<T> {
+    /// Valid log entries
+    entries: Vec<T>,
+
+    /// Length of valid log prefix
+    ///
+    /// Everything after it is torn or corrupted tail
+    valid_len: u64,
+
+    /// Valid log prefix is followed by corrupted entry, rather than torn tail
+    ///
+    /// Valid entries may follow corrupted one, so such log is kept before being truncated.
+    corrupted: bool,
+}
+
+/// Database, folded from sealed log segments
+#[derive(Serialize, Deserialize)]
+struct CompactedLog<DB> {
+    /// Sequence number of the newest folded segment
+    ///
+    /// Segments up to it are already applied to database, so they are skipped,
+    /// if compaction was interrupted before their removal.
+    last_segment: u64,
+
+    /// Database state after applying folded segments
+    database: DB,
+}
 
 pub struct Log<'c> {
     /// Persistence config
     config: &'c PersistenceConfig<'c>,
 
+    /// Encryption of log entries and internal snapshots
+    encryption: Option<Encryption<'c>>,
+
     /// Internal instance of [`Snapshot`] driver
     ///
     /// Due to limitations of current replication storage implementation
@@ -44,129 +200,404 @@ pub struct Log<'c> {
 }
 
 impl<'c> Log<'c> {
-    pub fn new(config: &'c PersistenceConfig) -> Self {
+    pub fn new(config: &'c PersistenceConfig, encryption: Option<Encryption<'c>>) -> Self {
         Log {
             config,
+            encryption,
             snapshot: OnceCell::new(),
         }
     }
 
-    /// Make log entry from serializable source
+    /// Make log file header
+    ///
+    /// Header is written once, at the beginning of log file.
+    ///
+    /// See [`make_header`] for header layout.
+    fn make_log_header() -> Vec<u8> {
+        make_header(LOG_MAGIC, LOG_VERSION)
+    }
+
+    /// Make associated data of `queue` log file
+    ///
+    /// Log segments share associated data of log file, that they were rotated from.
+    fn log_associated_data(queue: &Path) -> Vec<u8> {
+        associated_data(&queue.join(QUEUE_FILE))
+    }
+
+    /// Make associated data of log entry, written at `offset` of log file
+    fn entry_associated_data(associated_data: &[u8], offset: u64) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(associated_data.len() + size_of::<u64>());
+
+        buf.extend(associated_data);
+        buf.extend(&offset.to_le_bytes());
+
+        buf
+    }
+
+    /// Make log entry from serialized entry, that is written at `offset` of log file
+    ///
+    /// Returns bytes buffer, filled with header (entry size and checksums) and serialized entry, without any offset between each other.
+    ///
+    /// Header checksum is CRC32 of entry size, so corrupted size is never mistaken for torn entry.
+    /// Entry checksum is CRC32 of both entry size and entry itself.
     ///
-    /// Returns bytes buffer, filled with header (currently only with entry size) and serialized entry, without any offset between each other.
+    /// If encryption is enabled, then each entry is encrypted separately, using its own nonce.
+    /// Entry offset is authenticated along with log `associated_data`, so entries can't be reordered.
     /// ```
-    /// +---------+
-    /// |Entry len|
-    /// +---------+
-    /// |         |
-    /// |  Entry  |
-    /// |         |
-    /// +---------+
+    /// +---------------+
+    /// |   Entry len   |
+    /// +---------------+
+    /// |Header checksum|
+    /// +---------------+
+    /// |Entry checksum |
+    /// +---------------+
+    /// |               |
+    /// |     Entry     |
+    /// |               |
+    /// +---------------+
     /// ```
-    fn make_log_entry<S>(source: &S) -> Result<Vec<u8>, PersistenceError>
-    where
-        S: Serialize,
-    {
-        let size = serialized_size(source).map_err(PersistenceError::SerializationError)?;
+    fn make_log_entry(
+        entry: Vec<u8>,
+        key: Option<&EncryptionKey>,
+        associated_data: &[u8],
+        offset: u64,
+    ) -> Result<Vec<u8>, PersistenceError> {
+        let entry = seal(
+            key,
+            entry,
+            &Self::entry_associated_data(associated_data, offset),
+        )?;
+
+        Self::frame_log_entry(entry)
+    }
+
+    /// Prepend header (entry size and checksums) to serialized entry
+    fn frame_log_entry(entry: Vec<u8>) -> Result<Vec<u8>, PersistenceError> {
+        let size = TryInto::<u64>::try_into(entry.len())
+            .map_err(PersistenceError::LogEntryTooBig)?
+            .to_le_bytes();
 
-        debug!("Log entry size: {}", size);
+        debug!("Log entry size: {}", entry.len());
 
-        let capacity = size_of::<u64>()
-            + TryInto::<usize>::try_into(size).map_err(PersistenceError::LogEntryTooBig)?;
+        let mut buf = Vec::with_capacity(ENTRY_HEADER_SIZE + entry.len());
 
-        let mut buf = Vec::with_capacity(capacity);
+        buf.extend(&size);
+        buf.extend(&hash(&size).to_le_bytes());
+        buf.extend(&Self::checksum(&size, &entry).to_le_bytes());
+        buf.extend(entry);
 
-        buf.extend(&size.to_le_bytes());
+        Ok(buf)
+    }
 
-        buf.resize(capacity, 0);
+    /// Calculate checksum of log entry
+    fn checksum(size: &[u8], entry: &[u8]) -> u32 {
+        let mut hasher = Hasher::new();
 
-        serialize_into(&mut buf[size_of::<u64>()..], source)
-            .map_err(PersistenceError::SerializationError)?;
+        hasher.update(size);
+        hasher.update(entry);
 
-        Ok(buf)
+        hasher.finalize()
+    }
+
+    /// Get entry from the beginning of log slice
+    ///
+    /// Returns [`LogEntry::Torn`], if entry doesn't fit into slice or if it is the last entry and checksum is invalid,
+    /// and [`LogEntry::Corrupted`] if checksum of entry in the middle of slice or checksum of entry size is invalid.
+    ///
+    /// As entry size is protected with its own checksum, torn entry is always a single partial entry at the end of log.
+    fn read_entry(log: &[u8]) -> LogEntry<'_> {
+        if log.len() < ENTRY_HEADER_SIZE {
+            return LogEntry::Torn;
+        }
+
+        let (size, rest) = log.split_at(size_of::<u64>());
+        let (size_checksum, rest) = rest.split_at(size_of::<u32>());
+        let (checksum, rest) = rest.split_at(size_of::<u32>());
+
+        if u32::from_le_bytes(size_checksum.try_into().unwrap()) != hash(size) {
+            // Zero-filled tail is left, if crash happened after file size was updated, but before data was written
+            return if log.iter().all(|byte| *byte == 0) {
+                LogEntry::Torn
+            } else {
+                LogEntry::Corrupted
+            };
+        }
+
+        let len = match u64::from_le_bytes(size.try_into().unwrap()).try_into() {
+            Ok(len) if len <= rest.len() => len,
+            _ => return LogEntry::Torn,
+        };
+
+        let entry = &rest[..len];
+
+        if u32::from_le_bytes(checksum.try_into().unwrap()) == Self::checksum(size, entry) {
+            LogEntry::Valid(entry)
+        } else if len == rest.len() {
+            LogEntry::Torn
+        } else {
+            LogEntry::Corrupted
+        }
+    }
+
+    /// Get entry from the beginning of version 1 or 2 log slice
+    ///
+    /// Entry size of such log is not protected with checksum, so corrupted size is indistinguishable from torn entry.
+    fn read_v2_entry(log: &[u8]) -> LogEntry<'_> {
+        if log.len() < ENTRY_V2_HEADER_SIZE {
+            return LogEntry::Torn;
+        }
+
+        let (size, rest) = log.split_at(size_of::<u64>());
+        let (checksum, rest) = rest.split_at(size_of::<u32>());
+
+        let len = match u64::from_le_bytes(size.try_into().unwrap()).try_into() {
+            Ok(len) if len <= rest.len() => len,
+            _ => return LogEntry::Torn,
+        };
+
+        let entry = &rest[..len];
+
+        if u32::from_le_bytes(checksum.try_into().unwrap()) == Self::checksum(size, entry) {
+            LogEntry::Valid(entry)
+        } else if len == rest.len() {
+            LogEntry::Torn
+        } else {
+            LogEntry::Corrupted
+        }
     }
 
-    /// Get buffer of log entries from byte source
-    async fn parse_log<T, S>(source: &mut S) -> Result<Vec<T>, PersistenceError>
+    /// Get log entries from log file contents
+    ///
+    /// Torn tail (result of interrupted append) is ignored, and its offset is returned as length of valid log.
+    /// Torn tail is at most one partial entry, so it is truncated even if `strict` is set.
+    ///
+    /// Corrupted entry in the middle of log is treated the same way (all entries after it are dropped),
+    /// unless `strict` is set, in which case [`PersistenceError::CorruptedLog`] is returned.
+    /// Such log is marked as corrupted, as valid entries may follow corrupted one.
+    fn parse_log<T>(
+        log: &[u8],
+        encryption: Option<Encryption<'_>>,
+        associated_data: &[u8],
+        strict: bool,
+    ) -> Result<ParsedLog<T>, PersistenceError>
     where
         T: DeserializeOwned,
-        S: AsyncSeek + AsyncRead + Unpin,
     {
+        debug!("Log source size: {}", log.len());
+
         let mut entries = Vec::new();
+        let mut corrupted = false;
+
+        let mut offset = match body_offset(log, LOG_MAGIC, LOG_VERSION)? {
+            Some(offset) => offset,
+            // Header was not completely written, so log doesn't contain any entries yet
+            None => {
+                return Ok(ParsedLog {
+                    entries,
+                    valid_len: 0,
+                    corrupted: false,
+                })
+            }
+        };
 
-        let source_size = source
-            .seek(SeekFrom::End(0))
-            .await
-            .map_err(PersistenceError::from)?;
+        while offset < log.len() {
+            match Self::read_entry(&log[offset..]) {
+                LogEntry::Valid(entry) => {
+                    debug!("Log entry size: {}", entry.len());
 
-        debug!("Log source size: {}", source_size);
+                    let associated_data =
+                        Self::entry_associated_data(associated_data, offset as u64);
 
-        source
-            .seek(SeekFrom::Start(0))
-            .await
-            .map_err(PersistenceError::from)?;
+                    entries.push(
+                        deserialize(&open(encryption, entry, &associated_data)?)
+                            .map_err(PersistenceError::SerializationError)?,
+                    );
 
-        let mut buf = Vec::new();
+                    offset += ENTRY_HEADER_SIZE + entry.len();
+                }
+                LogEntry::Torn => break,
+                LogEntry::Corrupted if strict => {
+                    return Err(PersistenceError::CorruptedLog(offset as u64))
+                }
+                LogEntry::Corrupted => {
+                    error!(
+                        "Log entry at offset {} is corrupted, dropping it and all following entries",
+                        offset
+                    );
+                    corrupted = true;
+                    break;
+                }
+            }
+        }
 
-        while source
-            .seek(SeekFrom::Current(0))
-            .await
-            .map_err(PersistenceError::from)?
-            < source_size
-        {
-            let size = source.read_u64_le().await.map_err(PersistenceError::from)?;
+        Ok(ParsedLog {
+            entries,
+            valid_len: offset as u64,
+            corrupted,
+        })
+    }
 
-            debug!("Log entry size: {}", size);
+    /// Get checksummed entries of log body, that starts at `offset`, along with their offsets
+    ///
+    /// Entries are read with `read_entry`, and are prefixed with header of `header_size`.
+    /// Torn tail is dropped, as it would be truncated on load anyway.
+    fn read_entries(
+        log: &[u8],
+        mut offset: usize,
+        header_size: usize,
+        read_entry: fn(&[u8]) -> LogEntry<'_>,
+    ) -> Result<Vec<(u64, &[u8])>, PersistenceError> {
+        let mut entries = Vec::new();
 
-            buf.reserve(size.try_into().map_err(PersistenceError::LogEntryTooBig)?);
+        while offset < log.len() {
+            match read_entry(&log[offset..]) {
+                LogEntry::Valid(entry) => {
+                    entries.push((offset as u64, entry));
+                    offset += header_size + entry.len();
+                }
+                LogEntry::Torn => {
+                    warn!("Dropping {} bytes of torn log tail", log.len() - offset);
+                    break;
+                }
+                LogEntry::Corrupted => return Err(PersistenceError::CorruptedLog(offset as u64)),
+            }
+        }
 
-            source
-                .take(size)
-                .read_buf(&mut buf)
-                .await
-                .map_err(PersistenceError::from)?;
+        Ok(entries)
+    }
+
+    /// Get entries of log, written before checksums were introduced, along with their offsets
+    ///
+    /// Entries are prefixed only with their size.
+    fn read_legacy_entries(log: &[u8]) -> Vec<(u64, &[u8])> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+
+        while log.len() - offset >= size_of::<u64>() {
+            let (size, rest) = log[offset..].split_at(size_of::<u64>());
+
+            let len = match u64::from_le_bytes(size.try_into().unwrap()).try_into() {
+                Ok(len) if len <= rest.len() => len,
+                _ => break,
+            };
 
-            entries.push(deserialize(&buf).map_err(PersistenceError::SerializationError)?);
+            entries.push((offset as u64, &rest[..len]));
 
-            buf.clear();
+            offset += size_of::<u64>() + len;
         }
 
-        Ok(entries)
+        if offset < log.len() {
+            warn!(
+                "Dropping {} bytes of torn tail of legacy log",
+                log.len() - offset
+            );
+        }
+
+        entries
     }
 
-    /// Appends [make_log_entry] result of `source` to `destination`
+    /// Upgrade contents of log file, written in older layout, to current one
     ///
-    /// [make_log_entry]: Log::make_log_entry
-    async fn append<P, S>(&self, source: &S, destination: P) -> Result<(), PersistenceError>
-    where
-        P: AsRef<Path>,
-        S: Serialize,
-    {
-        let path = self.config.path.join(destination);
+    /// Entries of log without header are converted to current layout with `upgrade`,
+    /// which may drop entry by returning [`None`].
+    /// Unencrypted entries are encrypted, if `encryption` is enabled.
+    /// Log of current layout, which entries are all encrypted, is left as is,
+    /// so upgrade doesn't require encryption key of such log.
+    ///
+    /// Returns [`None`], if log already has current layout.
+    pub(crate) fn migrate_log(
+        log: &[u8],
+        encryption: Option<Encryption<'_>>,
+        associated_data: &[u8],
+        upgrade: &mut UpgradeEntry<'_>,
+    ) -> Result<Option<Vec<u8>>, PersistenceError> {
+        let legacy = matches!(
+            read_header(log, LOG_MAGIC, LOG_VERSION),
+            FileHeader::Missing
+        );
+
+        let entries = match read_header(log, LOG_MAGIC, LOG_VERSION) {
+            FileHeader::Torn => return Ok(None),
+            FileHeader::Current { len, .. } => {
+                if encryption.is_none() {
+                    return Ok(None);
+                }
 
-        debug!("Appending to {}", path.display());
+                let entries = Self::read_entries(log, len, ENTRY_HEADER_SIZE, Self::read_entry)?;
 
-        if let Some(parent) = path.parent() {
-            if !parent.is_dir() {
-                create_dir(&parent).await.map_err(PersistenceError::from)?;
+                if entries.iter().all(|(_, entry)| is_sealed(entry)) {
+                    return Ok(None);
+                }
+
+                entries
+            }
+            // Checksummed entries without Spartan version in header
+            FileHeader::Other(1) => Self::read_entries(
+                log,
+                LOG_V1_HEADER_SIZE,
+                ENTRY_V2_HEADER_SIZE,
+                Self::read_v2_entry,
+            )?,
+            // Entries without entry size checksum
+            FileHeader::Other(2) => match read_header(log, LOG_MAGIC, 2) {
+                FileHeader::Current { len, .. } => {
+                    Self::read_entries(log, len, ENTRY_V2_HEADER_SIZE, Self::read_v2_entry)?
+                }
+                // Header was not completely written, so log doesn't contain any entries yet
+                _ => Vec::new(),
+            },
+            FileHeader::Other(version) => {
+                return Err(PersistenceError::UnsupportedFormatVersion(version))
             }
+            // Entries, prefixed only with their size
+            FileHeader::Missing => Self::read_legacy_entries(log),
+        };
+
+        let mut buf = Self::make_log_header();
+
+        // Entries are moved to another offset, so encrypted ones are sealed again
+        for (offset, entry) in entries {
+            let entry = open_unencrypted(
+                encryption,
+                entry,
+                &Self::entry_associated_data(associated_data, offset),
+            )?;
+
+            let entry = if legacy {
+                match upgrade(&entry)? {
+                    Some(entry) => entry,
+                    None => continue,
+                }
+            } else {
+                entry.into_owned()
+            };
+
+            let offset = buf.len() as u64;
+
+            buf.extend(Self::make_log_entry(
+                entry,
+                encryption.map(|encryption| encryption.key()),
+                associated_data,
+                offset,
+            )?);
         }
 
-        OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)
-            .await
-            .map_err(PersistenceError::from)?
-            .write_all(&Self::make_log_entry(source)?)
-            .await
-            .map_err(PersistenceError::from)
+        Ok(Some(buf))
     }
 
     /// Get log entries from `source` log file using [parse_log]
     ///
+    /// Torn or corrupted tail of log file is truncated, so new entries are appended right after the last valid one.
+    ///
+    /// Corrupted log is copied aside before truncation (see [`corrupt_copy_path`]),
+    /// so entries after corrupted one may be recovered manually.
+    ///
     /// [parse_log]: Log::parse_log
-    async fn load<S, P>(&self, source: P) -> Result<Vec<S>, PersistenceError>
+    async fn load<S, P>(
+        &self,
+        source: P,
+        associated_data: &[u8],
+    ) -> Result<Vec<S>, PersistenceError>
     where
         S: DeserializeOwned,
         P: AsRef<Path>,
@@ -177,146 +608,392 @@ impl<'c> Log<'c> {
 
         let mut file = OpenOptions::new()
             .read(true)
-            .open(path)
+            .write(true)
+            .open(&path)
+            .await
+            .map_err(PersistenceError::from)?;
+
+        let mut log = Vec::new();
+
+        file.read_to_end(&mut log)
             .await
             .map_err(PersistenceError::from)?;
 
-        Self::parse_log(&mut file).await
+        let parsed = Self::parse_log(&log, self.encryption, associated_data, self.config.strict)?;
+
+        if parsed.corrupted {
+            let copy_path = corrupt_copy_path(&path);
+
+            error!(
+                "Copying corrupted {} to {} before truncation",
+                path.display(),
+                copy_path.display()
+            );
+
+            replace(&write_temp(&copy_path, &log).await?, &copy_path).await?;
+        }
+
+        if parsed.valid_len < log.len() as u64 {
+            warn!(
+                "Truncating {} bytes of torn or corrupted tail of {}",
+                log.len() as u64 - parsed.valid_len,
+                path.display()
+            );
+
+            file.set_len(parsed.valid_len)
+                .await
+                .map_err(PersistenceError::from)?;
+        }
+
+        Ok(parsed.entries)
     }
 
-    /// Append single event to `source` log file (usually queue name)
-    pub async fn persist_event<P>(
-        &self,
-        event: &Event<'_>,
-        source: P,
-    ) -> Result<(), PersistenceError>
+    /// Make long-lived writer of `queue` log file
+    pub fn writer<P>(&self, queue: P) -> LogWriter<'c>
     where
         P: AsRef<Path>,
     {
-        self.append(event, source.as_ref().join(QUEUE_FILE)).await
+        LogWriter::new(
+            self.config.path.join(&queue).join(QUEUE_FILE),
+            self.config,
+            self.encryption,
+            Self::log_associated_data(queue.as_ref()),
+        )
     }
 
-    /// Restore database events from `source` log file (usually queue name)
-    ///
-    /// If specified in [`PersistenceConfig`], compaction will be executed after successful loading.
-    pub async fn load_queue<P, DB>(&self, source: P) -> Result<Queue<DB>, PersistenceError>
+    /// Get sealed log segments of `queue`, ordered from oldest to newest
+    async fn segments<P>(&self, queue: P) -> Result<Vec<(u64, PathBuf)>, PersistenceError>
     where
         P: AsRef<Path>,
-        DB: EventLog<Vec<Event<'static>>> + Serialize + DeserializeOwned,
     {
-        let events = match self
-            .load::<Event, _>(source.as_ref().join(QUEUE_FILE))
+        read_segments(&self.config.path.join(queue))
+            .await
+            .map_err(PersistenceError::from)
+    }
+
+    /// Get events of sealed log `segments` of `queue`
+    async fn load_segments(
+        &self,
+        queue: &Path,
+        segments: &[(u64, PathBuf)],
+    ) -> Result<Vec<Event<'static>>, PersistenceError> {
+        let associated_data = Self::log_associated_data(queue);
+        let mut events = Vec::new();
+
+        for (_, path) in segments {
+            events.extend(self.load::<Event, _>(path, &associated_data).await?);
+        }
+
+        Ok(events)
+    }
+
+    /// Remove sealed log segments, that were folded into compacted log
+    ///
+    /// The newest segment is truncated instead, so log writer never reuses its sequence number.
+    async fn remove_segments(&self, segments: &[(u64, PathBuf)]) -> Result<(), PersistenceError> {
+        let (newest, older) = match segments.split_last() {
+            Some(segments) => segments,
+            None => return Ok(()),
+        };
+
+        for (_, path) in older {
+            debug!("Removing {}", path.display());
+
+            remove_file(path).await.map_err(PersistenceError::from)?;
+        }
+
+        OpenOptions::new()
+            .write(true)
+            .open(&newest.1)
+            .await
+            .map_err(PersistenceError::from)?
+            .set_len(0)
+            .await
+            .map_err(PersistenceError::from)
+    }
+
+    /// Seal active log file of `queue` into the segment after `last_segment` and existing ones
+    async fn seal(&self, queue: &Path, last_segment: u64) -> Result<(), PersistenceError> {
+        let segment = self
+            .segments(queue)
+            .await?
+            .last()
+            .map_or(last_segment, |(segment, _)| *segment)
+            .max(last_segment)
+            + 1;
+
+        let path = self.config.path.join(queue).join(QUEUE_FILE);
+
+        match rename(&path, segment_path(&path, segment)).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                error!("Log file not found: {}", e);
+                Ok(())
+            }
+            result => result.map_err(PersistenceError::from),
+        }
+    }
+
+    /// Load compacted log of `queue`, if it exists
+    ///
+    /// Previous generations of compacted log are never restored, as segments folded since then may be already removed,
+    /// so unreadable compacted log is an error.
+    async fn load_compacted<DB>(
+        &self,
+        queue: &Path,
+    ) -> Result<Option<CompactedLog<DB>>, PersistenceError>
+    where
+        DB: DeserializeOwned,
+    {
+        match self
+            .get_snapshot()
+            .load_latest(queue.join(QUEUE_COMPACTION_FILE))
             .await
         {
-            Ok(events) => events,
+            Ok(compacted) => Ok(Some(compacted)),
             Err(PersistenceError::FileOpenError(e)) => {
-                error!("Log file not found: {}", e);
-                Vec::new()
+                error!("Compaction file not found: {}", e);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Apply sealed log segments of `queue`, that are newer than `compacted` log, to it,
+    /// and persist it along with deduplication index
+    ///
+    /// Folded segments are removed only after compacted log is persisted,
+    /// so compaction may be safely interrupted at any point.
+    async fn fold<DB>(
+        &self,
+        queue: &Path,
+        compacted: Option<CompactedLog<DB>>,
+        dedup: &mut DedupIndex,
+    ) -> Result<DB, PersistenceError>
+    where
+        DB: EventLog<Vec<Event<'static>>> + Serialize,
+    {
+        let last_segment = compacted
+            .as_ref()
+            .map_or(0, |compacted| compacted.last_segment);
+
+        let segments = self.segments(queue).await?;
+        let pending = segments
+            .iter()
+            .filter(|(segment, _)| *segment > last_segment)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let events = self.load_segments(queue, &pending).await?;
+        events.iter().for_each(|event| dedup.record(event));
+
+        let database = match compacted {
+            Some(CompactedLog { mut database, .. }) => {
+                database.apply_log(events);
+                database
+            }
+            None => DB::from_log(events),
+        };
+
+        let database = match pending.last() {
+            Some((last_segment, _)) => {
+                // Pruned log entries are no longer available to rebuild deduplication index
+                self.get_snapshot()
+                    .persist(dedup, queue.join(DEDUP_FILE))
+                    .await?;
+
+                let compacted = CompactedLog {
+                    last_segment: *last_segment,
+                    database,
+                };
+
+                self.get_snapshot()
+                    .persist(&compacted, queue.join(QUEUE_COMPACTION_FILE))
+                    .await?;
+
+                compacted.database
             }
-            Err(e) => return Err(e),
+            None => database,
         };
 
+        self.remove_segments(&segments).await?;
+
+        Ok(database)
+    }
+
+    /// Restore database events from sealed segments and active log file of `source` (usually queue name)
+    ///
+    /// If specified in [`PersistenceConfig`], active log file is sealed and all segments are folded into compacted log.
+    pub async fn load_queue<P, DB>(&self, source: P) -> Result<Queue<DB>, PersistenceError>
+    where
+        P: AsRef<Path>,
+        DB: EventLog<Vec<Event<'static>>> + Serialize + DeserializeOwned,
+    {
+        let source = source.as_ref();
+
+        let snapshot = self
+            .get_snapshot()
+            .load_queue_snapshot::<DB>(source, PersistMode::Replication)
+            .await?;
+
+        let mut dedup = snapshot.dedup;
+
         let database = if self.config.compaction {
-            let compaction_path = source.as_ref().join(QUEUE_COMPACTION_FILE);
+            let compacted = self.load_compacted(source).await?;
 
-            let inner_db = match self.get_snapshot().load::<DB, _>(&compaction_path).await {
-                Ok(mut database) => {
-                    database.apply_log(events);
-                    database
-                }
+            self.seal(
+                source,
+                compacted
+                    .as_ref()
+                    .map_or(0, |compacted| compacted.last_segment),
+            )
+            .await?;
+
+            self.fold(source, compacted, &mut dedup).await?
+        } else {
+            let segments = self.segments(source).await?;
+            let mut events = self.load_segments(source, &segments).await?;
+
+            match self
+                .load::<Event, _>(source.join(QUEUE_FILE), &Self::log_associated_data(source))
+                .await
+            {
+                Ok(active) => events.extend(active),
                 Err(PersistenceError::FileOpenError(e)) => {
-                    error!("Compaction file not found: {}", e);
-                    DB::from_log(events)
+                    error!("Log file not found: {}", e);
                 }
                 Err(e) => return Err(e),
             };
 
-            self.get_snapshot()
-                .persist(&inner_db, &compaction_path)
-                .await?;
-
-            match self.prune(&source).await {
-                Err(PersistenceError::FileOpenError(_)) | Ok(_) => (),
-                Err(e) => return Err(e),
-            };
+            events.iter().for_each(|event| dedup.record(event));
 
-            inner_db
-        } else {
             DB::from_log(events)
         };
 
         cfg_if! {
             if #[cfg(feature = "replication")] {
-                // Thanks to GC threshold, it's currently impossible to use log driver
-                let replication_storage = match self.get_snapshot().load(source.as_ref().join(SNAPSHOT_REPLICATION_FILE)).await {
-                    Ok(storage) => storage,
-                    Err(PersistenceError::FileOpenError(e)) => {
-                        error!("{}", e);
-                        None
-                    },
-                    Err(e) => return Err(e)
-                };
-
-                let queue = Queue::new(database, replication_storage);
+                let queue = Queue::new(database, dedup, snapshot.schedule, snapshot.replication_storage);
             } else {
-                let queue = Queue::new(database);
+                let queue = Queue::new(database, dedup, snapshot.schedule);
             }
         }
 
         Ok(queue)
     }
 
-    /// Prune `queue` log file
-    async fn prune<P>(&self, queue: P) -> Result<(), PersistenceError>
+    /// Fold sealed log segments of `queue` into compacted log
+    ///
+    /// Active log file is left untouched, so compaction doesn't block appends to queue log.
+    pub async fn compact<P, DB>(&self, queue: P) -> Result<(), PersistenceError>
     where
         P: AsRef<Path>,
+        DB: EventLog<Vec<Event<'static>>> + Serialize + DeserializeOwned,
     {
-        let path = [&self.config.path, queue.as_ref(), QUEUE_FILE.as_ref()]
-            .iter()
-            .collect::<PathBuf>();
+        let queue = queue.as_ref();
+        let segments = self.segments(queue).await?;
+
+        // Truncated segments are already folded, so compacted log isn't loaded without new ones
+        let mut pending = 0;
+
+        for (_, path) in segments.iter() {
+            if metadata(path).await.map_err(PersistenceError::from)?.len() > 0 {
+                pending += 1;
+            }
+        }
+
+        if pending == 0 {
+            return Ok(());
+        }
+
+        debug!("Compacting {} log segments of {}", pending, queue.display());
+
+        let compacted = self.load_compacted::<DB>(queue).await?;
 
-        debug!("Pruning {}", path.display());
+        let mut dedup: DedupIndex = self
+            .get_snapshot()
+            .load_or_default(queue.join(DEDUP_FILE))
+            .await?;
 
-        remove_file(path).await.map_err(PersistenceError::from)
+        self.fold(queue, compacted, &mut dedup).await?;
+
+        Ok(())
+    }
+
+    /// Upgrade body of compacted log, written before format versioning
+    ///
+    /// Such compacted log was written along with log pruning, so there are no folded segments to skip.
+    pub(crate) fn upgrade_compacted_log(body: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+        let database: v0::TreeDatabase =
+            deserialize(body).map_err(PersistenceError::InvalidFileFormat)?;
+
+        serialize(&CompactedLog {
+            last_segment: 0,
+            database: TreeDatabase::<Message>::from(database),
+        })
+        .map_err(PersistenceError::SerializationError)
+    }
+
+    /// Load database of compacted log of `queue`, or empty database if there is no compacted log
+    pub(crate) async fn compacted_database<DB>(&self, queue: &Path) -> Result<DB, PersistenceError>
+    where
+        DB: DeserializeOwned + Default,
+    {
+        Ok(self
+            .load_compacted(queue)
+            .await?
+            .map(|compacted| compacted.database)
+            .unwrap_or_default())
     }
 
     /// Get shared [`Snapshot`] instance
     fn get_snapshot(&self) -> &Snapshot<'_> {
-        self.snapshot.get_or_init(|| Snapshot::new(self.config))
+        self.snapshot
+            .get_or_init(|| Snapshot::new(self.config, self.encryption))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{borrow::Cow, io::Cursor};
+    use std::borrow::Cow;
 
     use maybe_owned::MaybeOwned;
     use spartan_lib::core::{
         db::TreeDatabase,
-        dispatcher::StatusAwareDispatcher,
+        dispatcher::{SimpleDispatcher, StatusAwareDispatcher},
         message::{builder::MessageBuilder, Message},
         payload::Dispatchable,
     };
     use tempfile::{NamedTempFile, TempDir};
 
     use super::*;
-    use crate::{config::persistence::Persistence, node::DB};
+    use crate::{
+        config::{encryption::EncryptionKey, persistence::Persistence},
+        node::DB,
+    };
 
     #[tokio::test]
     async fn test_append_read() {
-        let file = NamedTempFile::new().unwrap();
+        let dir = TempDir::new().unwrap();
         let config = PersistenceConfig {
-            path: Cow::Borrowed(file.path().parent().unwrap()),
+            path: Cow::Borrowed(dir.path()),
             ..Default::default()
         };
 
-        let log = Log::new(&config);
+        let log = Log::new(&config, None);
 
-        log.append(&String::from("Hello, world"), file.path())
+        log.writer("test")
+            .append(&String::from("Hello, world"))
+            .unwrap()
+            .wait()
             .await
             .unwrap();
 
-        let entries = log.load::<String, _>(file.path()).await.unwrap();
+        let entries = log
+            .load::<String, _>(
+                Path::new("test").join(QUEUE_FILE),
+                &Log::log_associated_data(Path::new("test")),
+            )
+            .await
+            .unwrap();
         assert_eq!(entries.len(), 1);
         assert_eq!(entries.first().unwrap(), &String::from("Hello, world"));
     }
@@ -329,33 +1006,313 @@ mod tests {
             ..Default::default()
         };
 
-        let log = Log::new(&config);
+        let log = Log::new(&config, None);
 
-        let entries = log.load::<String, _>(file.path()).await.unwrap();
+        let entries = log.load::<String, _>(file.path(), &[]).await.unwrap();
         assert!(entries.is_empty());
     }
 
+    fn make_log(entries: &[Vec<u32>]) -> Vec<u8> {
+        let mut log = Log::make_log_header();
+
+        for entry in entries {
+            let offset = log.len() as u64;
+
+            log.append(
+                &mut Log::make_log_entry(serialize(entry).unwrap(), None, &[], offset).unwrap(),
+            );
+        }
+
+        log
+    }
+
+    #[test]
+    fn test_serialize_log_entry() {
+        let log = make_log(&[vec![1, 2, 3]]);
+        let parsed = Log::parse_log::<Vec<u32>>(&log, None, &[], false).unwrap();
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(&*parsed.entries.first().unwrap(), &[1, 2, 3]);
+        assert_eq!(parsed.valid_len, log.len() as u64);
+    }
+
+    #[test]
+    fn test_multiple_log_entries() {
+        let log = make_log(&[vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]);
+        let parsed = Log::parse_log::<Vec<u32>>(&log, None, &[], false).unwrap();
+        assert_eq!(parsed.entries.len(), 3);
+        assert_eq!(
+            parsed.entries,
+            vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]
+        );
+    }
+
+    #[test]
+    fn test_invalid_log_header() {
+        let mut log = make_log(&[vec![1, 2, 3]]);
+        log[0] = 0;
+
+        assert!(matches!(
+            Log::parse_log::<Vec<u32>>(&log, None, &[], false),
+            Err(PersistenceError::OutdatedFormat)
+        ));
+
+        let mut log = make_log(&[vec![1, 2, 3]]);
+        log[LOG_MAGIC.len()] = LOG_VERSION as u8 + 1;
+
+        assert!(matches!(
+            Log::parse_log::<Vec<u32>>(&log, None, &[], false),
+            Err(PersistenceError::UnsupportedFormatVersion(version)) if version == LOG_VERSION + 1
+        ));
+    }
+
+    fn keep_entry(entry: &[u8]) -> Result<Option<Vec<u8>>, PersistenceError> {
+        Ok(Some(entry.to_vec()))
+    }
+
+    /// Make body of version 1 or 2 log, which entries don't have entry size checksum
+    fn make_v2_log_body(entries: &[Vec<u32>]) -> Vec<u8> {
+        let mut body = Vec::new();
+
+        for entry in entries {
+            let entry = serialize(entry).unwrap();
+            let size = (entry.len() as u64).to_le_bytes();
+
+            body.extend(&size);
+            body.extend(&Log::checksum(&size, &entry).to_le_bytes());
+            body.extend(entry);
+        }
+
+        body
+    }
+
+    #[test]
+    fn test_migrate_log_v1() {
+        let entries = [vec![1, 2, 3], vec![4, 5, 6]];
+
+        let mut log_v1 = Vec::from(LOG_MAGIC);
+        log_v1.extend(&1u32.to_le_bytes());
+        log_v1.extend(make_v2_log_body(&entries));
+
+        assert!(matches!(
+            Log::parse_log::<Vec<u32>>(&log_v1, None, &[], false),
+            Err(PersistenceError::OutdatedFormat)
+        ));
+
+        let migrated = Log::migrate_log(&log_v1, None, &[], &mut keep_entry)
+            .unwrap()
+            .unwrap();
+        assert_eq!(migrated, make_log(&entries));
+        assert!(Log::migrate_log(&migrated, None, &[], &mut keep_entry)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_migrate_log_v2() {
+        let entries = [vec![1, 2, 3], vec![4, 5, 6]];
+
+        let mut log_v2 = make_header(LOG_MAGIC, 2);
+        log_v2.extend(make_v2_log_body(&entries));
+
+        assert!(matches!(
+            Log::parse_log::<Vec<u32>>(&log_v2, None, &[], false),
+            Err(PersistenceError::OutdatedFormat)
+        ));
+
+        let migrated = Log::migrate_log(&log_v2, None, &[], &mut keep_entry)
+            .unwrap()
+            .unwrap();
+        assert_eq!(migrated, make_log(&entries));
+    }
+
+    #[test]
+    fn test_torn_log_header() {
+        let log = make_log(&[]);
+
+        let parsed =
+            Log::parse_log::<Vec<u32>>(&log[..Log::make_log_header().len() - 1], None, &[], true)
+                .unwrap();
+        assert!(parsed.entries.is_empty());
+        assert_eq!(parsed.valid_len, 0);
+    }
+
+    #[test]
+    fn test_torn_log_tail() {
+        let log = make_log(&[vec![1, 2, 3], vec![4, 5, 6]]);
+        let valid_len = make_log(&[vec![1, 2, 3]]).len();
+
+        for len in valid_len..log.len() {
+            let parsed = Log::parse_log::<Vec<u32>>(&log[..len], None, &[], true).unwrap();
+            assert_eq!(parsed.entries, vec![vec![1, 2, 3]]);
+            assert_eq!(parsed.valid_len, valid_len as u64);
+        }
+
+        let mut log = log;
+        *log.last_mut().unwrap() ^= 1;
+
+        let parsed = Log::parse_log::<Vec<u32>>(&log, None, &[], true).unwrap();
+        assert_eq!(parsed.entries, vec![vec![1, 2, 3]]);
+        assert_eq!(parsed.valid_len, valid_len as u64);
+    }
+
+    #[test]
+    fn test_corrupted_log() {
+        let mut log = make_log(&[vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]);
+        let valid_len = make_log(&[vec![1, 2, 3]]).len();
+
+        log[valid_len + ENTRY_HEADER_SIZE] ^= 1;
+
+        let parsed = Log::parse_log::<Vec<u32>>(&log, None, &[], false).unwrap();
+        assert_eq!(parsed.entries, vec![vec![1, 2, 3]]);
+        assert_eq!(parsed.valid_len, valid_len as u64);
+
+        assert!(matches!(
+            Log::parse_log::<Vec<u32>>(&log, None, &[], true),
+            Err(PersistenceError::CorruptedLog(offset)) if offset == valid_len as u64
+        ));
+    }
+
+    #[test]
+    fn test_corrupted_entry_size() {
+        let mut log = make_log(&[vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]);
+        let valid_len = make_log(&[vec![1, 2, 3]]).len();
+
+        // Entry size points past the end of log
+        log[valid_len + size_of::<u64>() - 1] ^= 1;
+
+        let parsed = Log::parse_log::<Vec<u32>>(&log, None, &[], false).unwrap();
+        assert_eq!(parsed.entries, vec![vec![1, 2, 3]]);
+        assert_eq!(parsed.valid_len, valid_len as u64);
+
+        assert!(matches!(
+            Log::parse_log::<Vec<u32>>(&log, None, &[], true),
+            Err(PersistenceError::CorruptedLog(offset)) if offset == valid_len as u64
+        ));
+    }
+
+    #[test]
+    fn test_zero_filled_log_tail() {
+        let mut log = make_log(&[vec![1, 2, 3]]);
+        let valid_len = log.len();
+
+        log.extend(&[0; ENTRY_HEADER_SIZE * 2]);
+
+        let parsed = Log::parse_log::<Vec<u32>>(&log, None, &[], true).unwrap();
+        assert_eq!(parsed.entries, vec![vec![1, 2, 3]]);
+        assert_eq!(parsed.valid_len, valid_len as u64);
+    }
+
     #[tokio::test]
-    async fn test_serialize_log_entry() {
-        let entry = Log::make_log_entry(&vec![1u32, 2, 3]).unwrap();
-        let parsed = Log::parse_log::<Vec<u32>, _>(&mut Cursor::new(entry))
+    async fn test_truncate_torn_tail() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        let log = Log::new(&config, None);
+        let path = Path::new("test").join(QUEUE_FILE);
+
+        log.writer("test")
+            .append(&String::from("Hello"))
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.path().join(&path))
+            .unwrap();
+        let valid_len = file.metadata().unwrap().len();
+
+        file.set_len(valid_len + ENTRY_HEADER_SIZE as u64 + 1)
+            .unwrap();
+
+        let entries = log
+            .load::<String, _>(&path, &Log::log_associated_data(Path::new("test")))
             .await
             .unwrap();
-        assert_eq!(parsed.len(), 1);
-        assert_eq!(&*parsed.first().unwrap(), &[1, 2, 3]);
+        assert_eq!(entries, vec![String::from("Hello")]);
+        assert_eq!(file.metadata().unwrap().len(), valid_len);
+
+        log.writer("test")
+            .append(&String::from("world"))
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+
+        let entries = log
+            .load::<String, _>(&path, &Log::log_associated_data(Path::new("test")))
+            .await
+            .unwrap();
+        assert_eq!(entries, vec![String::from("Hello"), String::from("world")]);
     }
 
     #[tokio::test]
-    async fn test_multiple_log_entries() {
-        let mut entries = Vec::new();
-        entries.append(&mut Log::make_log_entry(&vec![1u32, 2, 3]).unwrap());
-        entries.append(&mut Log::make_log_entry(&vec![4, 5, 6]).unwrap());
-        entries.append(&mut Log::make_log_entry(&vec![7, 8, 9]).unwrap());
-        let parsed = Log::parse_log::<Vec<u32>, _>(&mut Cursor::new(entries))
+    async fn test_keep_corrupted_log() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        let log = Log::new(&config, None);
+        let path = Path::new("test").join(QUEUE_FILE);
+        let writer = log.writer("test");
+
+        writer
+            .append(&String::from("Hello"))
+            .unwrap()
+            .wait()
             .await
             .unwrap();
-        assert_eq!(parsed.len(), 3);
-        assert_eq!(parsed, vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]);
+
+        let valid_len = std::fs::metadata(dir.path().join(&path)).unwrap().len();
+
+        for entry in &["world", "again"] {
+            writer
+                .append(&String::from(*entry))
+                .unwrap()
+                .wait()
+                .await
+                .unwrap();
+        }
+
+        let mut original = std::fs::read(dir.path().join(&path)).unwrap();
+        original[valid_len as usize + ENTRY_HEADER_SIZE] ^= 1;
+        std::fs::write(dir.path().join(&path), &original).unwrap();
+
+        let entries = log
+            .load::<String, _>(&path, &Log::log_associated_data(Path::new("test")))
+            .await
+            .unwrap();
+        assert_eq!(entries, vec![String::from("Hello")]);
+        assert_eq!(
+            std::fs::metadata(dir.path().join(&path)).unwrap().len(),
+            valid_len
+        );
+
+        let copies = std::fs::read_dir(dir.path().join("test"))
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| is_corrupt_copy(entry.file_name().to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(copies.len(), 1);
+
+        let name = copies[0].file_name().into_string().unwrap();
+        assert!(!is_log_file(&name));
+
+        // Entries after corrupted one are still recoverable from the copy
+        let copy = std::fs::read(copies[0].path()).unwrap();
+        assert_eq!(copy, original);
+
+        let entry_len = ENTRY_HEADER_SIZE + serialize(&String::from("world")).unwrap().len();
+        assert!(matches!(
+            Log::read_entry(&copy[valid_len as usize + entry_len..]),
+            LogEntry::Valid(entry) if deserialize::<String>(&open(None, entry, &[]).unwrap()).unwrap() == "again"
+        ));
     }
 
     #[tokio::test]
@@ -370,14 +1327,178 @@ mod tests {
             path: Cow::Borrowed(tempdir.path()),
             timer: 0,
             compaction: false,
+            ..Default::default()
         };
-        let log = Log::new(&config);
+        let log = Log::new(&config, None);
 
-        log.persist_event(&event, "test").await.unwrap();
+        log.writer("test")
+            .append(&event)
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
 
         let queue: DB = log.load_queue("test").await.unwrap();
 
-        assert_eq!(queue.database().await.pop().unwrap().body(), "Hello");
+        assert_eq!(queue.database().await.pop().unwrap().body(), b"Hello");
+    }
+
+    /// Make log of `entries`, encrypted with `key`
+    fn make_encrypted_log(
+        entries: &[&str],
+        key: &EncryptionKey,
+        associated_data: &[u8],
+    ) -> Vec<u8> {
+        let mut log = Log::make_log_header();
+
+        for entry in entries {
+            let offset = log.len() as u64;
+
+            log.append(
+                &mut Log::make_log_entry(
+                    serialize(entry).unwrap(),
+                    Some(key),
+                    associated_data,
+                    offset,
+                )
+                .unwrap(),
+            );
+        }
+
+        log
+    }
+
+    #[test]
+    fn test_encrypted_log_entries() {
+        let key = EncryptionKey::from([1; 32]);
+        let encryption = Encryption::new(&key, &[]);
+        let associated_data = Log::log_associated_data(Path::new("test"));
+
+        let log = make_encrypted_log(&["Hello", "Hello"], &key, &associated_data);
+
+        assert!(!log.windows(5).any(|window| window == b"Hello"));
+
+        let parsed =
+            Log::parse_log::<String>(&log, Some(encryption), &associated_data, true).unwrap();
+        assert_eq!(
+            parsed.entries,
+            vec![String::from("Hello"), String::from("Hello")]
+        );
+
+        assert!(matches!(
+            Log::parse_log::<String>(&log, None, &associated_data, true),
+            Err(PersistenceError::MissingEncryptionKey)
+        ));
+
+        // Entries of another queue log can't be loaded
+        assert!(matches!(
+            Log::parse_log::<String>(
+                &log,
+                Some(encryption),
+                &Log::log_associated_data(Path::new("test_2")),
+                true
+            ),
+            Err(PersistenceError::DecryptionError)
+        ));
+    }
+
+    #[test]
+    fn test_reordered_encrypted_log_entries() {
+        let key = EncryptionKey::from([1; 32]);
+        let encryption = Encryption::new(&key, &[]);
+        let associated_data = Log::log_associated_data(Path::new("test"));
+
+        let log = make_encrypted_log(&["Hello", "world"], &key, &associated_data);
+        let header_len = Log::make_log_header().len();
+        let entry_len = (log.len() - header_len) / 2;
+
+        // Entries have the same size, so swapped entries are framed correctly
+        let mut reordered = Log::make_log_header();
+        reordered.extend(&log[header_len + entry_len..]);
+        reordered.extend(&log[header_len..header_len + entry_len]);
+
+        assert!(matches!(
+            Log::parse_log::<String>(&reordered, Some(encryption), &associated_data, true),
+            Err(PersistenceError::DecryptionError)
+        ));
+    }
+
+    #[test]
+    fn test_unencrypted_log_entries() {
+        let key = EncryptionKey::from([1; 32]);
+        let encryption = Encryption::new(&key, &[]);
+        let associated_data = Log::log_associated_data(Path::new("test"));
+
+        let log = make_log(&[vec![1, 2, 3], vec![4, 5, 6]]);
+
+        assert!(matches!(
+            Log::parse_log::<Vec<u32>>(&log, Some(encryption), &associated_data, true),
+            Err(PersistenceError::UnencryptedData)
+        ));
+
+        let migrated = Log::migrate_log(&log, Some(encryption), &associated_data, &mut keep_entry)
+            .unwrap()
+            .unwrap();
+
+        assert!(Log::migrate_log(
+            &migrated,
+            Some(encryption),
+            &associated_data,
+            &mut keep_entry
+        )
+        .unwrap()
+        .is_none());
+        assert!(
+            Log::migrate_log(&migrated, None, &associated_data, &mut keep_entry)
+                .unwrap()
+                .is_none()
+        );
+
+        let parsed =
+            Log::parse_log::<Vec<u32>>(&migrated, Some(encryption), &associated_data, true)
+                .unwrap();
+        assert_eq!(parsed.entries, vec![vec![1, 2, 3], vec![4, 5, 6]]);
+    }
+
+    #[tokio::test]
+    async fn test_restore_encrypted_events() {
+        let tempdir = TempDir::new().expect("Unable to create temporary test directory");
+        let event = Event::Push(MaybeOwned::Owned(
+            MessageBuilder::default().body("Hello").compose().unwrap(),
+        ));
+
+        let config = PersistenceConfig {
+            mode: Persistence::Log,
+            path: Cow::Borrowed(tempdir.path()),
+            timer: 0,
+            compaction: false,
+            ..Default::default()
+        };
+
+        let key = EncryptionKey::from([1; 32]);
+        let wrong_key = EncryptionKey::from([2; 32]);
+
+        Log::new(&config, Some(Encryption::new(&key, &[])))
+            .writer("test")
+            .append(&event)
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            Log::new(&config, Some(Encryption::new(&wrong_key, &[])))
+                .load_queue::<_, TreeDatabase<Message>>("test")
+                .await,
+            Err(PersistenceError::DecryptionError)
+        ));
+
+        let queue: DB = Log::new(&config, Some(Encryption::new(&key, &[])))
+            .load_queue("test")
+            .await
+            .unwrap();
+
+        assert_eq!(queue.database().await.pop().unwrap().body(), b"Hello");
     }
 
     #[tokio::test]
@@ -392,28 +1513,182 @@ mod tests {
             path: Cow::Borrowed(tempdir.path()),
             timer: 0,
             compaction: true,
+            ..Default::default()
         };
-        let log = Log::new(&config);
+        let log = Log::new(&config, None);
 
-        log.persist_event(&event, "test").await.unwrap();
+        log.writer("test")
+            .append(&event)
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
 
         let queue: DB = log.load_queue("test").await.unwrap();
 
-        assert_eq!(queue.database().await.pop().unwrap().body(), "Hello");
+        assert_eq!(queue.database().await.pop().unwrap().body(), b"Hello");
 
         assert!(matches!(
-            log.load::<Event, _>(Path::new("test").join(QUEUE_FILE))
+            log.load::<Event, _>(Path::new("test").join(QUEUE_FILE), &[])
                 .await
                 .unwrap_err(),
             PersistenceError::FileOpenError(_)
         ));
 
-        let snapshot = Snapshot::new(&config);
-        let mut database: TreeDatabase<Message> = snapshot
+        let snapshot = Snapshot::new(&config, None);
+        let mut compacted: CompactedLog<TreeDatabase<Message>> = snapshot
             .load(Path::new("test").join(QUEUE_COMPACTION_FILE))
             .await
             .unwrap();
 
-        assert_eq!(database.pop().unwrap().body(), "Hello");
+        assert_eq!(compacted.last_segment, 1);
+        assert_eq!(compacted.database.pop().unwrap().body(), b"Hello");
+    }
+
+    #[tokio::test]
+    async fn test_background_compaction() {
+        let tempdir = TempDir::new().expect("Unable to create temporary test directory");
+
+        let config = PersistenceConfig {
+            mode: Persistence::Log,
+            path: Cow::Borrowed(tempdir.path()),
+            segment_size: 1,
+            ..Default::default()
+        };
+        let log = Log::new(&config, None);
+        let writer = log.writer("test");
+
+        for body in &["1", "2", "3"] {
+            let event = Event::Push(MaybeOwned::Owned(
+                MessageBuilder::default().body(*body).compose().unwrap(),
+            ));
+
+            writer.append(&event).unwrap().wait().await.unwrap();
+        }
+
+        assert_eq!(log.segments("test").await.unwrap().len(), 2);
+
+        log.compact::<_, TreeDatabase<Message>>("test")
+            .await
+            .unwrap();
+
+        // The newest folded segment is kept empty, so its sequence number is not reused
+        let segments = log.segments("test").await.unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].0, 2);
+        assert_eq!(std::fs::metadata(&segments[0].1).unwrap().len(), 0);
+
+        let snapshot = Snapshot::new(&config, None);
+        let compacted: CompactedLog<TreeDatabase<Message>> = snapshot
+            .load(Path::new("test").join(QUEUE_COMPACTION_FILE))
+            .await
+            .unwrap();
+
+        assert_eq!(compacted.last_segment, 2);
+        assert_eq!(compacted.database.size(), 2);
+
+        let queue: DB = log.load_queue("test").await.unwrap();
+        assert_eq!(queue.database().await.size(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_compacted_log() {
+        let tempdir = TempDir::new().expect("Unable to create temporary test directory");
+
+        let config = PersistenceConfig {
+            mode: Persistence::Log,
+            path: Cow::Borrowed(tempdir.path()),
+            snapshot_generations: 1,
+            ..Default::default()
+        };
+        let log = Log::new(&config, None);
+        let path = Path::new("test").join(QUEUE_COMPACTION_FILE);
+
+        for last_segment in 1..=2 {
+            log.get_snapshot()
+                .persist(
+                    &CompactedLog {
+                        last_segment,
+                        database: TreeDatabase::<Message>::default(),
+                    },
+                    &path,
+                )
+                .await
+                .unwrap();
+        }
+
+        std::fs::write(tempdir.path().join(&path), b"corrupted").unwrap();
+
+        // Previous generation doesn't contain events of already removed segments
+        assert!(matches!(
+            log.load_queue::<_, TreeDatabase<Message>>("test").await,
+            Err(PersistenceError::OutdatedFormat)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_compaction() {
+        let tempdir = TempDir::new().expect("Unable to create temporary test directory");
+
+        let config = PersistenceConfig {
+            mode: Persistence::Log,
+            path: Cow::Borrowed(tempdir.path()),
+            segment_size: 1,
+            ..Default::default()
+        };
+        let log = Log::new(&config, None);
+        let writer = log.writer("test");
+
+        for body in &["1", "2", "3"] {
+            let event = Event::Push(MaybeOwned::Owned(
+                MessageBuilder::default().body(*body).compose().unwrap(),
+            ));
+
+            writer.append(&event).unwrap().wait().await.unwrap();
+        }
+
+        let segments = log
+            .segments("test")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, path)| (std::fs::read(&path).unwrap(), path))
+            .collect::<Vec<_>>();
+
+        log.compact::<_, TreeDatabase<Message>>("test")
+            .await
+            .unwrap();
+
+        // Crash after compacted log is persisted, but before folded segments are removed
+        for (segment, path) in segments.iter() {
+            std::fs::write(path, segment).unwrap();
+        }
+
+        log.compact::<_, TreeDatabase<Message>>("test")
+            .await
+            .unwrap();
+
+        let snapshot = Snapshot::new(&config, None);
+        let compacted: CompactedLog<TreeDatabase<Message>> = snapshot
+            .load(Path::new("test").join(QUEUE_COMPACTION_FILE))
+            .await
+            .unwrap();
+
+        assert_eq!(compacted.database.size(), 2);
+
+        for (segment, path) in segments.iter() {
+            std::fs::write(path, segment).unwrap();
+        }
+
+        let queue: DB = log.load_queue("test").await.unwrap();
+        assert_eq!(queue.database().await.size(), 3);
+
+        // Active log is sealed after folded segments, and folded on load
+        let segments = log.segments("test").await.unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].0, 3);
+
+        let queue: DB = log.load_queue("test").await.unwrap();
+        assert_eq!(queue.database().await.size(), 3);
     }
 }
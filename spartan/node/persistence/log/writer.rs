@@ -0,0 +1,578 @@
+use std::{
+    io::{Error as IoError, ErrorKind},
+    mem::take,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bincode::serialize;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use tokio::{
+    fs::{create_dir, rename, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot::{channel, Receiver, Sender},
+    },
+    time::timeout,
+};
+
+use crate::{
+    config::{
+        encryption::EncryptionKey,
+        persistence::{Fsync, PersistenceConfig},
+    },
+    node::persistence::{
+        encryption::Encryption,
+        log::{read_segments, segment_path, Log},
+        PersistenceError,
+    },
+};
+
+/// Result of group commit, shared between all of its entries
+type CommitResult = Result<(), Arc<IoError>>;
+
+/// Log entry, waiting to be written
+struct Append {
+    /// Serialized log entry
+    ///
+    /// Entry is encrypted by background task, as its offset in log file is known only there.
+    entry: Vec<u8>,
+
+    /// Commit result receiver
+    done: Sender<CommitResult>,
+}
+
+/// Pending log entry commit
+///
+/// Entry is already ordered in log, but may be not yet written.
+#[must_use]
+pub struct Commit(Option<Receiver<CommitResult>>);
+
+impl Commit {
+    /// Commit, that doesn't require waiting (for example, if log persistence is disabled)
+    pub fn done() -> Self {
+        Commit(None)
+    }
+
+    /// Wait for entry to reach durability point of fsync policy
+    pub async fn wait(self) -> Result<(), PersistenceError> {
+        match self.0 {
+            Some(receiver) => receiver
+                .await
+                .map_err(|_| PersistenceError::LogWriterClosed)?
+                .map_err(PersistenceError::LogWriteError),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Long-lived queue log writer
+///
+/// Entries are serialized and ordered by caller (usually with database lock being held),
+/// and written by background task, that keeps log file open.
+///
+/// All entries, that were appended while previous write was in progress, are written
+/// (and synced, if required by fsync policy) together as one group commit.
+///
+/// Log file is sealed into numbered segment, once it reaches configured size or age,
+/// and new log file is started. Sealed segments are folded into compacted log in background.
+///
+/// Failed group commit closes writer, so log never contains entries, that follow lost ones.
+pub struct LogWriter<'c> {
+    /// Log file path
+    path: PathBuf,
+
+    /// Persistence config
+    config: &'c PersistenceConfig<'c>,
+
+    /// Encryption of log entries
+    encryption: Option<Encryption<'c>>,
+
+    /// Associated data of log file, that entries are encrypted with
+    associated_data: Vec<u8>,
+
+    /// Background task sender, initialized on first append
+    sender: OnceCell<UnboundedSender<Append>>,
+}
+
+impl<'c> LogWriter<'c> {
+    pub fn new(
+        path: PathBuf,
+        config: &'c PersistenceConfig<'c>,
+        encryption: Option<Encryption<'c>>,
+        associated_data: Vec<u8>,
+    ) -> Self {
+        LogWriter {
+            path,
+            config,
+            encryption,
+            associated_data,
+            sender: OnceCell::new(),
+        }
+    }
+
+    /// Serialize `source` and append it to log
+    ///
+    /// Requires Tokio runtime, as background task is spawned on first append.
+    pub fn append<S>(&self, source: &S) -> Result<Commit, PersistenceError>
+    where
+        S: Serialize,
+    {
+        let entry = serialize(source).map_err(PersistenceError::SerializationError)?;
+
+        let sender = self.sender.get_or_init(|| {
+            let (sender, receiver) = unbounded_channel();
+
+            tokio::spawn(
+                LogTask {
+                    path: self.path.clone(),
+                    key: self.encryption.map(|encryption| encryption.key().clone()),
+                    associated_data: self.associated_data.clone(),
+                    fsync: self.config.fsync,
+                    interval: Duration::from_millis(self.config.fsync_interval),
+                    segment_size: self.config.segment_size,
+                    segment_age: Duration::from_secs(self.config.segment_age),
+                    file: None,
+                    len: 0,
+                    opened_at: Instant::now(),
+                    next_segment: None,
+                    synced_at: Instant::now(),
+                    dirty: false,
+                }
+                .run(receiver),
+            );
+
+            sender
+        });
+
+        let (done, receiver) = channel();
+
+        sender
+            .send(Append { entry, done })
+            .map_err(|_| PersistenceError::LogWriterClosed)?;
+
+        Ok(Commit(Some(receiver)))
+    }
+}
+
+/// Background task of [`LogWriter`]
+struct LogTask {
+    /// Log file path
+    path: PathBuf,
+
+    /// Key, that log entries are encrypted with
+    key: Option<EncryptionKey>,
+
+    /// Associated data of log file, that entries are encrypted with
+    associated_data: Vec<u8>,
+
+    /// Fsync policy
+    fsync: Fsync,
+
+    /// Interval between fsyncs with [`Fsync::Interval`] policy
+    interval: Duration,
+
+    /// Log file size, after which it's sealed into segment
+    segment_size: u64,
+
+    /// Log file age, after which it's sealed into segment
+    segment_age: Duration,
+
+    /// Open log file
+    ///
+    /// [`None`] before first write, or after failed one
+    file: Option<File>,
+
+    /// Length of successfully written log
+    ///
+    /// Used to truncate partially written group commit after failure
+    len: u64,
+
+    /// Time, when log file was opened
+    opened_at: Instant,
+
+    /// Sequence number of next sealed segment
+    ///
+    /// [`None`] until log directory is scanned for existing segments
+    next_segment: Option<u64>,
+
+    /// Last fsync time
+    synced_at: Instant,
+
+    /// Log contains entries, that were written after last fsync
+    dirty: bool,
+}
+
+impl LogTask {
+    async fn run(mut self, mut receiver: UnboundedReceiver<Append>) {
+        loop {
+            let first = if matches!(self.fsync, Fsync::Interval) && self.dirty {
+                let deadline = self.interval.checked_sub(self.synced_at.elapsed());
+
+                match timeout(deadline.unwrap_or_default(), receiver.recv()).await {
+                    Ok(append) => append,
+                    Err(_) => {
+                        self.sync().await;
+                        continue;
+                    }
+                }
+            } else {
+                receiver.recv().await
+            };
+
+            let first = match first {
+                Some(append) => append,
+                None => break,
+            };
+
+            let mut batch = vec![first];
+
+            while let Ok(append) = receiver.try_recv() {
+                batch.push(append);
+            }
+
+            debug!(
+                "Group commit of {} entries to {}",
+                batch.len(),
+                self.path.display()
+            );
+
+            let result = self
+                .write(
+                    batch
+                        .iter_mut()
+                        .map(|append| take(&mut append.entry))
+                        .collect(),
+                )
+                .await
+                .map_err(Arc::new);
+
+            if let Err(e) = &result {
+                error!("Unable to write {}: {}", self.path.display(), e);
+            }
+
+            for append in batch {
+                // Receiver may be already dropped, if request was cancelled
+                let _ = append.done.send(result.clone());
+            }
+
+            // Entries, that follow failed group commit, may depend on it, so they are rejected as well
+            if result.is_err() {
+                receiver.close();
+
+                while let Some(append) = receiver.recv().await {
+                    let _ = append.done.send(result.clone());
+                }
+
+                error!("Log writer of {} is closed", self.path.display());
+
+                break;
+            }
+        }
+
+        if self.dirty {
+            self.sync().await;
+        }
+    }
+
+    /// Write group commit of serialized `entries` to log file, and sync it if required by fsync policy
+    async fn write(&mut self, entries: Vec<Vec<u8>>) -> Result<(), IoError> {
+        if self.file.is_some()
+            && (self.len >= self.segment_size || self.opened_at.elapsed() >= self.segment_age)
+        {
+            if let Err(e) = self.rotate().await {
+                error!("Unable to rotate {}: {}", self.path.display(), e);
+            }
+        }
+
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => self.open().await?,
+        };
+
+        let mut buf = Vec::new();
+
+        for entry in entries {
+            let offset = self.len + buf.len() as u64;
+
+            match Log::make_log_entry(entry, self.key.as_ref(), &self.associated_data, offset) {
+                Ok(entry) => buf.extend(entry),
+                Err(e) => {
+                    self.file = Some(file);
+                    return Err(IoError::new(ErrorKind::InvalidInput, e.to_string()));
+                }
+            }
+        }
+
+        file.write_all(&buf).await?;
+
+        if let Fsync::Always = self.fsync {
+            file.sync_data().await?;
+        } else {
+            self.dirty = true;
+        }
+
+        self.len += buf.len() as u64;
+        self.file = Some(file);
+
+        if matches!(self.fsync, Fsync::Interval) && self.synced_at.elapsed() >= self.interval {
+            self.sync().await;
+        }
+
+        Ok(())
+    }
+
+    /// Open log file, writing log header if it's empty
+    ///
+    /// Partially written group commit, that is left after failure, is truncated.
+    async fn open(&mut self) -> Result<File, IoError> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.is_dir() {
+                create_dir(parent).await?;
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+
+        let len = file.metadata().await?.len();
+
+        if len == 0 {
+            let header = Log::make_log_header();
+
+            file.write_all(&header).await?;
+            file.sync_data().await?;
+
+            self.len = header.len() as u64;
+        } else if self.len != 0 && len > self.len {
+            warn!(
+                "Truncating partially written group commit of {}",
+                self.path.display()
+            );
+
+            file.set_len(self.len).await?;
+        } else {
+            self.len = len;
+        }
+
+        self.opened_at = Instant::now();
+
+        Ok(file)
+    }
+
+    /// Seal log file into next segment
+    ///
+    /// Log file is synced regardless of fsync policy, so segment is complete before it's compacted.
+    /// New log file is created on next write.
+    async fn rotate(&mut self) -> Result<(), IoError> {
+        let segment = match self.next_segment {
+            Some(segment) => segment,
+            None => {
+                let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+
+                read_segments(dir)
+                    .await?
+                    .last()
+                    .map_or(1, |(segment, _)| segment + 1)
+            }
+        };
+
+        if let Some(file) = self.file.as_mut() {
+            file.sync_data().await?;
+        }
+
+        let segment_path = segment_path(&self.path, segment);
+
+        debug!(
+            "Rotating {} to {}",
+            self.path.display(),
+            segment_path.display()
+        );
+
+        rename(&self.path, segment_path).await?;
+
+        self.file = None;
+        self.len = 0;
+        self.dirty = false;
+        self.next_segment = Some(segment + 1);
+
+        Ok(())
+    }
+
+    /// Sync log file
+    async fn sync(&mut self) {
+        if let Some(file) = self.file.as_mut() {
+            match file.sync_data().await {
+                Ok(_) => self.dirty = false,
+                Err(e) => error!("Unable to sync {}: {}", self.path.display(), e),
+            }
+        }
+
+        self.synced_at = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use futures_util::future::try_join_all;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    async fn write_read(fsync: Fsync) {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            fsync,
+            fsync_interval: 10,
+            ..Default::default()
+        };
+
+        let log = Log::new(&config, None);
+        let writer = log.writer("test");
+
+        let commits = (0..10)
+            .map(|i| writer.append(&i).unwrap().wait())
+            .collect::<Vec<_>>();
+
+        try_join_all(commits).await.unwrap();
+
+        let entries = log
+            .load::<u32, _>(writer.path.as_path(), &writer.associated_data)
+            .await
+            .unwrap();
+        assert_eq!(entries, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_write_always() {
+        write_read(Fsync::Always).await;
+    }
+
+    #[tokio::test]
+    async fn test_write_interval() {
+        write_read(Fsync::Interval).await;
+    }
+
+    #[tokio::test]
+    async fn test_write_never() {
+        write_read(Fsync::Never).await;
+    }
+
+    #[tokio::test]
+    async fn test_append_to_existing_log() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        let log = Log::new(&config, None);
+
+        log.writer("test")
+            .append(&String::from("Hello"))
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+
+        let writer = log.writer("test");
+
+        writer
+            .append(&String::from("world"))
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+
+        let entries = log
+            .load::<String, _>(writer.path.as_path(), &writer.associated_data)
+            .await
+            .unwrap();
+        assert_eq!(entries, vec![String::from("Hello"), String::from("world")]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_closes_writer() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        // Directory in place of log file
+        std::fs::create_dir_all(dir.path().join("test/queue_log")).unwrap();
+
+        let log = Log::new(&config, None);
+        let writer = log.writer("test");
+
+        let first = writer.append(&0).unwrap();
+        let second = writer.append(&1).unwrap();
+
+        assert!(matches!(
+            first.wait().await.unwrap_err(),
+            PersistenceError::LogWriteError(_)
+        ));
+        assert!(matches!(
+            second.wait().await.unwrap_err(),
+            PersistenceError::LogWriteError(_)
+        ));
+
+        assert!(matches!(
+            writer.append(&2).err().unwrap(),
+            PersistenceError::LogWriterClosed
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_segment_rotation() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            segment_size: 1,
+            ..Default::default()
+        };
+
+        let log = Log::new(&config, None);
+        let writer = log.writer("test");
+
+        for i in 0..5 {
+            writer.append(&i).unwrap().wait().await.unwrap();
+        }
+
+        let segments = read_segments(&dir.path().join("test")).await.unwrap();
+        assert_eq!(
+            segments
+                .iter()
+                .map(|(segment, _)| *segment)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+
+        let mut entries = Vec::new();
+
+        for (_, path) in segments {
+            entries.extend(
+                log.load::<u32, _>(path, &writer.associated_data)
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        entries.extend(
+            log.load::<u32, _>(writer.path.as_path(), &writer.associated_data)
+                .await
+                .unwrap(),
+        );
+
+        assert_eq!(entries, (0..5).collect::<Vec<_>>());
+    }
+}
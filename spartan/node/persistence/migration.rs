@@ -0,0 +1,373 @@
+use std::path::Path;
+
+use bincode::{deserialize, serialize};
+use spartan_lib::core::{db::TreeDatabase, message::Message};
+use tokio::fs::{read, read_dir};
+
+#[cfg(feature = "replication")]
+use crate::node::persistence::snapshot::{upgrade_replication_storage, REPLICATION_FILE};
+use crate::{
+    config::{persistence::PersistenceConfig, Config},
+    node::{
+        event::v0,
+        persistence::{
+            encryption::{associated_data, Encryption},
+            log::{
+                is_corrupt_copy, is_log_file, Log, QUEUE_COMPACTION_FILE, QUEUE_FILE as LOG_FILE,
+            },
+            snapshot::{
+                replace, same_layout, upgrade_queue, write_temp, Snapshot,
+                QUEUE_FILE as SNAPSHOT_FILE,
+            },
+            PersistenceError,
+        },
+    },
+};
+
+/// Upgrade all database files of configured queues to current layout
+///
+/// If encryption key is configured, then unencrypted files are encrypted with it.
+///
+/// Files are replaced atomically, so interrupted migration may be safely restarted.
+/// Server must not be running during migration.
+///
+/// Returns amount of upgraded files.
+pub async fn migrate(config: &Config<'_>) -> Result<usize, PersistenceError> {
+    let persistence = match config.persistence.as_ref() {
+        Some(persistence) => persistence,
+        None => return Ok(0),
+    };
+
+    let encryption = Encryption::from_config(config);
+    let mut migrated = 0;
+
+    for name in config.queues.iter() {
+        migrated += migrate_queue(persistence, name, encryption).await?;
+    }
+
+    Ok(migrated)
+}
+
+/// Get kind of database file, stripping sequence number of log segment or snapshot generation
+fn file_kind(name: &str) -> &str {
+    if is_log_file(name) {
+        return LOG_FILE;
+    }
+
+    match name.rfind('.') {
+        Some(index) if name[index + 1..].bytes().all(|byte| byte.is_ascii_digit()) => {
+            &name[..index]
+        }
+        _ => name,
+    }
+}
+
+/// Get order, in which database file `name` is upgraded
+///
+/// Log files are upgraded last, from oldest segment to active log,
+/// as their events without header are replayed on top of upgraded compacted log.
+fn migration_order(name: &str) -> (bool, u64) {
+    if !is_log_file(name) {
+        return (false, 0);
+    }
+
+    match name[LOG_FILE.len()..].strip_prefix('.') {
+        Some(segment) => (true, segment.parse().unwrap_or(0)),
+        None => (true, u64::MAX),
+    }
+}
+
+/// Upgrade database files of `queue` in database directory of `persistence` config
+async fn migrate_queue(
+    persistence: &PersistenceConfig<'_>,
+    queue: &str,
+    encryption: Option<Encryption<'_>>,
+) -> Result<usize, PersistenceError> {
+    let mut entries = match read_dir(persistence.path.join(queue))
+        .await
+        .map_err(PersistenceError::from)
+    {
+        Ok(entries) => entries,
+        Err(PersistenceError::FileOpenError(_)) => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut files = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await.map_err(PersistenceError::from)? {
+        let path = entry.path();
+
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+
+        // Leftover of interrupted snapshot write, or copy of corrupted log, that is kept as is
+        if name.ends_with(".tmp") || is_corrupt_copy(&name) || !path.is_file() {
+            continue;
+        }
+
+        files.push((name, path));
+    }
+
+    files.sort_unstable_by_key(|(name, _)| migration_order(name));
+
+    let mut migrated = 0;
+
+    // Database, which events of log without header are replayed on
+    let mut database: Option<TreeDatabase<Message>> = None;
+
+    for (name, path) in files {
+        let file = read(&path).await.map_err(PersistenceError::from)?;
+        let kind = file_kind(&name);
+        let associated_data = associated_data(&Path::new(queue).join(kind));
+
+        let upgraded = if is_log_file(&name) {
+            let database = match &mut database {
+                Some(database) => database,
+                None => database.insert(if persistence.compaction {
+                    Log::new(persistence, encryption)
+                        .compacted_database(Path::new(queue))
+                        .await?
+                } else {
+                    TreeDatabase::default()
+                }),
+            };
+
+            Log::migrate_log(&file, encryption, &associated_data, &mut |entry| {
+                let event: v0::Event =
+                    deserialize(entry).map_err(PersistenceError::InvalidFileFormat)?;
+
+                event
+                    .upgrade(database)
+                    .map(|event| serialize(&event).map_err(PersistenceError::SerializationError))
+                    .transpose()
+            })?
+        } else {
+            let upgrade = match kind {
+                SNAPSHOT_FILE => upgrade_queue,
+                QUEUE_COMPACTION_FILE => Log::upgrade_compacted_log,
+                #[cfg(feature = "replication")]
+                REPLICATION_FILE => upgrade_replication_storage,
+                _ => same_layout,
+            };
+
+            Snapshot::migrate_snapshot(&file, encryption, &associated_data, upgrade)?
+        };
+
+        if let Some(upgraded) = upgraded {
+            info!("Upgrading {}", path.display());
+
+            replace(&write_temp(&path, &upgraded).await?, &path).await?;
+
+            migrated += 1;
+        }
+    }
+
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, path::Path};
+
+    use maybe_owned::MaybeOwned;
+    use spartan_lib::core::{
+        dispatcher::{SimpleDispatcher, StatusAwareDispatcher},
+        message::{builder::MessageBuilder, Message},
+        payload::Dispatchable,
+    };
+    use tempfile::TempDir;
+
+    use super::{file_kind, migrate};
+    use crate::{
+        config::{
+            encryption::EncryptionKey,
+            persistence::{Persistence, PersistenceConfig},
+            Config,
+        },
+        node::{event::Event, persistence::PersistenceError, Manager},
+    };
+
+    fn message(body: &str) -> Message {
+        MessageBuilder::default().body(body).compose().unwrap()
+    }
+
+    /// Write database `files` of queue "test", persisted by Spartan before format versioning
+    fn write_v0_files(dir: &Path, files: &[(&str, &[u8])]) {
+        std::fs::create_dir(dir.join("test")).unwrap();
+
+        for (name, file) in files {
+            std::fs::write(dir.join("test").join(name), file).unwrap();
+        }
+    }
+
+    /// Snapshot of database with available "Hello" message and reserved "Reserved" message
+    const V0_QUEUE: &[u8] = include_bytes!("fixtures/v0/queue");
+
+    /// Compacted log of the same database as [`V0_QUEUE`]
+    const V0_COMPACTED_LOG: &[u8] = include_bytes!("fixtures/v0/queue_compacted_log");
+
+    /// Log with push of "world" and "again" messages, pop, delete of "world" message and GC
+    const V0_LOG: &[u8] = include_bytes!("fixtures/v0/queue_log");
+
+    /// Primary replication storage with push of "world" message and pop
+    #[cfg(feature = "replication")]
+    const V0_REPLICATION: &[u8] = include_bytes!("fixtures/v0/replication");
+
+    #[tokio::test]
+    async fn test_migrate_legacy_snapshot() {
+        let dir = TempDir::new().unwrap();
+
+        let config = Config {
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Snapshot,
+                path: Cow::Borrowed(dir.path()),
+                ..Default::default()
+            }),
+            queues: vec!["test".to_string().into_boxed_str()].into_boxed_slice(),
+            ..Default::default()
+        };
+
+        write_v0_files(dir.path(), &[("queue", V0_QUEUE)]);
+
+        assert!(Manager::new(&config).load_from_fs().await.is_err());
+
+        assert_eq!(migrate(&config).await.unwrap(), 1);
+        assert_eq!(migrate(&config).await.unwrap(), 0);
+
+        let mut manager = Manager::new(&config);
+        manager.load_from_fs().await.unwrap();
+
+        let database = manager.queue("test").unwrap().database().await;
+        let hello = database.peek().unwrap();
+
+        assert_eq!(hello.body(), b"Hello");
+        assert_eq!(*hello.state().max_tries(), 2);
+        assert_eq!(database.size(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_log() {
+        let dir = TempDir::new().unwrap();
+
+        let config = Config {
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Log,
+                path: Cow::Borrowed(dir.path()),
+                compaction: true,
+                ..Default::default()
+            }),
+            queues: vec!["test".to_string().into_boxed_str()].into_boxed_slice(),
+            ..Default::default()
+        };
+
+        write_v0_files(
+            dir.path(),
+            &[
+                ("queue_compacted_log", V0_COMPACTED_LOG),
+                ("queue_log", V0_LOG),
+                #[cfg(feature = "replication")]
+                ("replication", V0_REPLICATION),
+            ],
+        );
+
+        assert!(Manager::new(&config).load_from_fs().await.is_err());
+
+        let files = if cfg!(feature = "replication") { 3 } else { 2 };
+
+        assert_eq!(migrate(&config).await.unwrap(), files);
+        assert_eq!(migrate(&config).await.unwrap(), 0);
+
+        let mut manager = Manager::new(&config);
+        manager.load_from_fs().await.unwrap();
+
+        let queue = manager.queue("test").unwrap();
+
+        {
+            let mut database = queue.database().await;
+
+            // "Hello" is reserved by pop, "world" is deleted and expired "Reserved" is collected by GC
+            assert_eq!(database.size(), 2);
+            assert_eq!(
+                StatusAwareDispatcher::pop(&mut *database).unwrap().body(),
+                b"again"
+            );
+            assert!(StatusAwareDispatcher::pop(&mut *database).is_none());
+        }
+
+        #[cfg(feature = "replication")]
+        {
+            let mut storage = queue.replication_storage().await;
+            let primary = storage.as_mut().unwrap().get_primary();
+
+            assert!(primary.slice(1).is_none());
+            assert!(primary.slice(3).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_file_kind() {
+        assert_eq!(file_kind("queue"), "queue");
+        assert_eq!(file_kind("queue.2"), "queue");
+        assert_eq!(file_kind("queue_compacted_log.1"), "queue_compacted_log");
+        assert_eq!(file_kind("queue_log"), "queue_log");
+        assert_eq!(file_kind("queue_log.10"), "queue_log");
+    }
+
+    #[tokio::test]
+    async fn test_migrate_unencrypted_database() {
+        let dir = TempDir::new().unwrap();
+
+        let config = Config {
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Log,
+                path: Cow::Borrowed(dir.path()),
+                compaction: false,
+                ..Default::default()
+            }),
+            queues: vec!["test".to_string().into_boxed_str()].into_boxed_slice(),
+            ..Default::default()
+        };
+
+        {
+            let manager = Manager::new(&config);
+            let queue = manager.queue("test").unwrap();
+
+            for body in &["Hello", "world"] {
+                queue
+                    .log_event(
+                        "test",
+                        &manager,
+                        Event::Push(MaybeOwned::Owned(message(body))),
+                    )
+                    .await
+                    .unwrap()
+                    .wait()
+                    .await
+                    .unwrap();
+            }
+
+            manager.snapshot().await.unwrap();
+        }
+
+        let encrypted = Config {
+            encryption_key: Some(EncryptionKey::from([1; 32])),
+            ..config
+        };
+
+        // Unencrypted files are not authenticated, so they are not loaded with encryption enabled
+        assert!(matches!(
+            Manager::new(&encrypted).load_from_fs().await.unwrap_err(),
+            PersistenceError::UnencryptedData
+        ));
+
+        assert!(migrate(&encrypted).await.unwrap() > 0);
+        assert_eq!(migrate(&encrypted).await.unwrap(), 0);
+
+        let mut manager = Manager::new(&encrypted);
+        manager.load_from_fs().await.unwrap();
+
+        assert_eq!(manager.queue("test").unwrap().database().await.size(), 2);
+    }
+}
@@ -11,9 +11,23 @@ pub mod log;
 /// Best performance, yet worse reliability.
 pub mod snapshot;
 
+/// Encryption at rest
+///
+/// Used by both drivers, if encryption key is configured.
+pub mod encryption;
+
+/// Versioned file headers
+///
+/// Used by both drivers to detect files, written in older layouts.
+pub mod format;
+
+/// Upgrade of database files, written in older layouts
+pub mod migration;
+
 use std::{
     io::{Error as IoError, ErrorKind},
     num::TryFromIntError,
+    sync::Arc,
 };
 
 use bincode::Error as BincodeError;
@@ -30,10 +44,32 @@ pub enum PersistenceError {
     SerializationError(BincodeError),
     #[error("Log entry size is too big for current platform")]
     LogEntryTooBig(TryFromIntError),
+    #[error("Database file is truncated")]
+    TruncatedFile,
+    #[error("Database file has outdated format. Run `spartan migrate` to upgrade it")]
+    OutdatedFormat,
+    #[error("Database file has unsupported format version {0}. It was probably written by newer Spartan version")]
+    UnsupportedFormatVersion(u32),
+    #[error("Log file is corrupted at offset {0}")]
+    CorruptedLog(u64),
+    #[error("Unable to write log: {0}")]
+    LogWriteError(Arc<IoError>),
+    #[error("Log writer is closed")]
+    LogWriterClosed,
+    #[error("Queue is poisoned by failed log write. Restart is required to restore it from log")]
+    QueuePoisoned,
     #[error("Unable to read database file: {0}")]
     FileOpenError(IoError),
     #[error("IO error: {0}")]
     GenericIoError(IoError),
+    #[error("Database is encrypted, but encryption key is not configured")]
+    MissingEncryptionKey,
+    #[error("Database is not encrypted, but encryption key is configured. Run `spartan migrate` to encrypt it")]
+    UnencryptedData,
+    #[error("Unable to decrypt database. Encryption key is probably wrong")]
+    DecryptionError,
+    #[error("Unable to encrypt database")]
+    EncryptionError,
 }
 
 impl From<IoError> for PersistenceError {
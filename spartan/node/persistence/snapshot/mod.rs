@@ -1,20 +1,130 @@
-use std::path::Path;
+use std::{
+    ffi::OsString,
+    io::{Error as IoError, ErrorKind},
+    path::{Path, PathBuf},
+};
 
 use bincode::{deserialize, serialize};
 use cfg_if::cfg_if;
 use serde::{de::DeserializeOwned, Serialize};
-use tokio::fs::{create_dir, read, write};
+use spartan_lib::core::{
+    db::{v0, TreeDatabase},
+    message::Message,
+};
+use tokio::{
+    fs::{create_dir, metadata, read, remove_file, rename, File},
+    io::AsyncWriteExt,
+};
 
+#[cfg(feature = "replication")]
+use crate::node::replication::{storage::ReplicationStorage, v0 as replication_v0};
 use crate::{
     config::persistence::PersistenceConfig,
-    node::{persistence::PersistenceError, Queue},
+    node::{
+        dedup::DedupIndex,
+        persistence::{
+            encryption::{associated_data, is_sealed, open, seal, Encryption},
+            format::{body_offset, make_header, read_header, FileHeader},
+            PersistenceError,
+        },
+        schedule::ScheduleState,
+        Queue,
+    },
 };
 
-const QUEUE_FILE: &str = "queue";
+/// Snapshot file magic
+const SNAPSHOT_MAGIC: &[u8] = b"SPARTSNP";
+
+/// Snapshot file format version
+///
+/// Snapshot without header is written before versioning was introduced
+const SNAPSHOT_VERSION: u32 = 1;
+
+pub(crate) const QUEUE_FILE: &str = "queue";
+
+pub(crate) const DEDUP_FILE: &str = "dedup";
+
+pub(crate) const SCHEDULE_FILE: &str = "schedule";
 
 #[cfg(feature = "replication")]
 pub(crate) const REPLICATION_FILE: &str = "replication";
 
+/// Get path of snapshot file with `suffix` appended to its name
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+
+    name.into()
+}
+
+/// Get path of previous snapshot `generation`
+///
+/// Generations are named after snapshot file: `queue.1` is the newest previous generation, `queue.2` is older, ...
+fn generation_path(path: &Path, generation: usize) -> PathBuf {
+    suffixed_path(path, &format!(".{}", generation))
+}
+
+/// Write `buf` to synced temporary file next to `path`
+///
+/// Returns path of temporary file, which should be moved into place with [`replace`].
+pub(crate) async fn write_temp(path: &Path, buf: &[u8]) -> Result<PathBuf, PersistenceError> {
+    let temp_path = suffixed_path(path, ".tmp");
+
+    let mut file = File::create(&temp_path)
+        .await
+        .map_err(PersistenceError::from)?;
+
+    file.write_all(buf).await.map_err(PersistenceError::from)?;
+    file.sync_all().await.map_err(PersistenceError::from)?;
+
+    Ok(temp_path)
+}
+
+/// Atomically replace file at `path` with temporary file, written by [`write_temp`]
+pub(crate) async fn replace(temp_path: &Path, path: &Path) -> Result<(), PersistenceError> {
+    rename(temp_path, path)
+        .await
+        .map_err(PersistenceError::from)?;
+
+    // Make rename itself durable
+    #[cfg(unix)]
+    {
+        if let Some(parent) = path.parent() {
+            File::open(parent)
+                .await
+                .map_err(PersistenceError::from)?
+                .sync_all()
+                .await
+                .map_err(PersistenceError::from)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Keep body of snapshot without header as is, as its layout wasn't changed since then
+pub(crate) fn same_layout(body: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    Ok(body.to_vec())
+}
+
+/// Convert queue database, written before format versioning, to current layout
+pub(crate) fn upgrade_queue(body: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    let database: v0::TreeDatabase =
+        deserialize(body).map_err(PersistenceError::InvalidFileFormat)?;
+
+    serialize(&TreeDatabase::<Message>::from(database))
+        .map_err(PersistenceError::SerializationError)
+}
+
+/// Convert replication storage, written before format versioning, to current layout
+#[cfg(feature = "replication")]
+pub(crate) fn upgrade_replication_storage(body: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    let storage: Option<replication_v0::ReplicationStorage> =
+        deserialize(body).map_err(PersistenceError::InvalidFileFormat)?;
+
+    serialize(&storage.map(ReplicationStorage::from)).map_err(PersistenceError::SerializationError)
+}
+
 /// Snapshot persistence modes
 #[derive(Copy, Clone)]
 pub enum PersistMode {
@@ -25,17 +135,40 @@ pub enum PersistMode {
     Replication,
 }
 
+/// Snapshot files of queue, loaded from the same generation
+pub(crate) struct QueueSnapshot<DB> {
+    /// Queue database, loaded only if queue is persisted with [`PersistMode::Queue`]
+    pub(crate) database: Option<DB>,
+
+    /// Message deduplication index
+    pub(crate) dedup: DedupIndex,
+
+    /// Progress of queue schedules
+    pub(crate) schedule: ScheduleState,
+
+    #[cfg(feature = "replication")]
+    /// Replication storage
+    pub(crate) replication_storage: Option<ReplicationStorage>,
+}
+
 pub struct Snapshot<'c> {
     /// Persistence config
     config: &'c PersistenceConfig<'c>,
+
+    /// Encryption of persisted files
+    encryption: Option<Encryption<'c>>,
 }
 
 impl<'c> Snapshot<'c> {
-    pub fn new(config: &'c PersistenceConfig) -> Self {
-        Snapshot { config }
+    pub fn new(config: &'c PersistenceConfig, encryption: Option<Encryption<'c>>) -> Self {
+        Snapshot { config, encryption }
     }
 
-    /// Serialize `source` into `destination`.
+    /// Serialize `source` into `destination`, encrypting it if encryption is enabled.
+    ///
+    /// Snapshot is written to temporary file, synced and renamed into place,
+    /// so crash during write never destroys previous snapshot.
+    /// Previous snapshots are kept as generations, configured in [`PersistenceConfig`].
     pub(crate) async fn persist<S, P>(
         &self,
         source: &S,
@@ -45,7 +178,7 @@ impl<'c> Snapshot<'c> {
         S: Serialize,
         P: AsRef<Path>,
     {
-        let path = self.config.path.join(destination);
+        let path = self.config.path.join(&destination);
 
         debug!("Writing to {}", path.display());
 
@@ -55,33 +188,346 @@ impl<'c> Snapshot<'c> {
             }
         }
 
-        write(
-            path,
+        let mut buf = make_header(SNAPSHOT_MAGIC, SNAPSHOT_VERSION);
+
+        buf.extend(seal(
+            self.encryption.map(|encryption| encryption.key()),
             serialize(source).map_err(PersistenceError::SerializationError)?,
-        )
-        .await
-        .map_err(PersistenceError::from)
+            &associated_data(destination.as_ref()),
+        )?);
+
+        let temp_path = write_temp(&path, &buf).await?;
+
+        self.rotate_generations(&path).await?;
+
+        replace(&temp_path, &path).await
+    }
+
+    /// Shift previous generations of snapshot at `path`, dropping the oldest one
+    ///
+    /// Current snapshot becomes the newest previous generation.
+    async fn rotate_generations(&self, path: &Path) -> Result<(), PersistenceError> {
+        let generations = self.config.snapshot_generations;
+
+        if generations == 0 {
+            return Ok(());
+        }
+
+        match remove_file(generation_path(path, generations)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => (),
+        }
+
+        for generation in (1..generations).rev() {
+            match rename(
+                generation_path(path, generation),
+                generation_path(path, generation + 1),
+            )
+            .await
+            {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+
+        match rename(path, generation_path(path, 1)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
     }
 
     /// Load serialized database from `source`
+    ///
+    /// If latest snapshot is missing or unreadable, previous generations are tried from the newest one.
+    /// When none of them can be loaded, error of the latest unreadable snapshot is returned,
+    /// or [`PersistenceError::FileOpenError`] if there are no snapshots at all.
     pub(crate) async fn load<S, P>(&self, source: P) -> Result<S, PersistenceError>
     where
         P: AsRef<Path>,
         S: DeserializeOwned,
     {
-        let path = self.config.path.join(source);
+        let path = self.config.path.join(&source);
+        let associated_data = associated_data(source.as_ref());
+
+        let mut error = match self.load_generation(&path, &associated_data).await {
+            Ok(source) => return Ok(source),
+            Err(e) => e,
+        };
+
+        for generation in 1..=self.config.snapshot_generations {
+            let generation_path = generation_path(&path, generation);
+
+            match self
+                .load_generation(&generation_path, &associated_data)
+                .await
+            {
+                Ok(source) => {
+                    warn!(
+                        "Unable to load {}, restored previous generation {}: {}",
+                        path.display(),
+                        generation_path.display(),
+                        error
+                    );
+
+                    return Ok(source);
+                }
+                Err(PersistenceError::FileOpenError(_)) => (),
+                Err(e) => {
+                    if let PersistenceError::FileOpenError(_) = error {
+                        error = e;
+                    }
+                }
+            }
+        }
 
+        Err(error)
+    }
+
+    /// Load serialized data from `source` without falling back to previous generations
+    ///
+    /// Used for files, which previous generations are stale, as data folded into them is already removed.
+    pub(crate) async fn load_latest<S, P>(&self, source: P) -> Result<S, PersistenceError>
+    where
+        P: AsRef<Path>,
+        S: DeserializeOwned,
+    {
+        self.load_generation(
+            &self.config.path.join(&source),
+            &associated_data(source.as_ref()),
+        )
+        .await
+    }
+
+    /// Load single snapshot generation from `path`, which was encrypted with `associated_data` of snapshot file
+    async fn load_generation<S>(
+        &self,
+        path: &Path,
+        associated_data: &[u8],
+    ) -> Result<S, PersistenceError>
+    where
+        S: DeserializeOwned,
+    {
         debug!("Loading from {}", path.display());
 
-        deserialize(&read(path).await.map_err(PersistenceError::from)?)
+        let file = read(path).await.map_err(PersistenceError::from)?;
+
+        let offset = body_offset(&file, SNAPSHOT_MAGIC, SNAPSHOT_VERSION)?
+            .ok_or(PersistenceError::TruncatedFile)?;
+
+        deserialize(&open(self.encryption, &file[offset..], associated_data)?)
             .map_err(PersistenceError::InvalidFileFormat)
     }
 
+    /// Upgrade contents of snapshot file, written in older layout, to current one
+    ///
+    /// Body of snapshot without header is converted to current layout with `upgrade`.
+    /// Unencrypted snapshot is encrypted, if `encryption` is enabled.
+    /// Encrypted snapshot is left as is, so upgrade doesn't require its encryption key.
+    ///
+    /// Returns [`None`], if snapshot already has current layout.
+    pub(crate) fn migrate_snapshot(
+        snapshot: &[u8],
+        encryption: Option<Encryption<'_>>,
+        associated_data: &[u8],
+        upgrade: fn(&[u8]) -> Result<Vec<u8>, PersistenceError>,
+    ) -> Result<Option<Vec<u8>>, PersistenceError> {
+        let body = match read_header(snapshot, SNAPSHOT_MAGIC, SNAPSHOT_VERSION) {
+            FileHeader::Current { len, .. } => match encryption {
+                Some(_) if !is_sealed(&snapshot[len..]) => snapshot[len..].to_vec(),
+                _ => return Ok(None),
+            },
+            FileHeader::Torn => return Ok(None),
+            FileHeader::Other(version) => {
+                return Err(PersistenceError::UnsupportedFormatVersion(version))
+            }
+            FileHeader::Missing => upgrade(snapshot)?,
+        };
+
+        let mut buf = make_header(SNAPSHOT_MAGIC, SNAPSHOT_VERSION);
+
+        buf.extend(seal(
+            encryption.map(|encryption| encryption.key()),
+            body,
+            associated_data,
+        )?);
+
+        Ok(Some(buf))
+    }
+
+    /// Load file `source` of snapshot `generation`, where generation 0 is the latest snapshot
+    async fn load_file<S>(&self, source: &Path, generation: usize) -> Result<S, PersistenceError>
+    where
+        S: DeserializeOwned,
+    {
+        let path = self.config.path.join(source);
+
+        let path = match generation {
+            0 => path,
+            generation => generation_path(&path, generation),
+        };
+
+        self.load_generation(&path, &associated_data(source)).await
+    }
+
+    /// Load optional file `source` of snapshot `generation`
+    ///
+    /// Returns [`None`], if file doesn't exist.
+    /// Missing file, which next generation exists, is left by interrupted generation rotation,
+    /// so [`PersistenceError::FileOpenError`] is returned for it instead.
+    async fn load_optional_file<S>(
+        &self,
+        source: &Path,
+        generation: usize,
+    ) -> Result<Option<S>, PersistenceError>
+    where
+        S: DeserializeOwned,
+    {
+        match self.load_file(source, generation).await {
+            Ok(file) => Ok(Some(file)),
+            Err(PersistenceError::FileOpenError(e)) => {
+                let next_generation =
+                    generation_path(&self.config.path.join(source), generation + 1);
+
+                if generation < self.config.snapshot_generations
+                    && metadata(&next_generation).await.is_ok()
+                {
+                    return Err(PersistenceError::FileOpenError(e));
+                }
+
+                debug!("{} doesn't exist, using default value", source.display());
+
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Load files of queue `name`, persisted with `mode`, from single snapshot `generation`
+    ///
+    /// Returns [`PersistenceError::FileOpenError`], if previous generation doesn't have any of queue files.
+    async fn load_queue_generation<DB>(
+        &self,
+        name: &Path,
+        mode: PersistMode,
+        generation: usize,
+    ) -> Result<QueueSnapshot<DB>, PersistenceError>
+    where
+        DB: DeserializeOwned,
+    {
+        let database = match mode {
+            PersistMode::Queue => Some(self.load_file(&name.join(QUEUE_FILE), generation).await?),
+            PersistMode::Replication => None,
+        };
+
+        let dedup = self
+            .load_optional_file(&name.join(DEDUP_FILE), generation)
+            .await?;
+        let schedule = self
+            .load_optional_file(&name.join(SCHEDULE_FILE), generation)
+            .await?;
+
+        let found = database.is_some() || dedup.is_some() || schedule.is_some();
+
+        #[cfg(feature = "replication")]
+        let replication_storage: Option<Option<ReplicationStorage>> = self
+            .load_optional_file(&name.join(REPLICATION_FILE), generation)
+            .await?;
+
+        #[cfg(feature = "replication")]
+        let found = found || replication_storage.is_some();
+
+        if generation > 0 && !found {
+            return Err(PersistenceError::FileOpenError(IoError::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Generation {} of {} doesn't exist",
+                    generation,
+                    name.display()
+                ),
+            )));
+        }
+
+        Ok(QueueSnapshot {
+            database,
+            dedup: dedup.unwrap_or_default(),
+            schedule: schedule.unwrap_or_default(),
+            #[cfg(feature = "replication")]
+            replication_storage: replication_storage.flatten(),
+        })
+    }
+
+    /// Load snapshot files of queue `name`, persisted with `mode`
+    ///
+    /// All files are loaded from the same generation, so they are consistent with each other.
+    /// If any file of latest snapshot is unreadable, previous generations are tried from the newest one.
+    /// When none of them can be loaded, error of the latest unreadable generation is returned.
+    pub(crate) async fn load_queue_snapshot<DB>(
+        &self,
+        name: &Path,
+        mode: PersistMode,
+    ) -> Result<QueueSnapshot<DB>, PersistenceError>
+    where
+        DB: DeserializeOwned,
+    {
+        let mut error = match self.load_queue_generation(name, mode, 0).await {
+            Ok(snapshot) => return Ok(snapshot),
+            Err(e) => e,
+        };
+
+        for generation in 1..=self.config.snapshot_generations {
+            match self.load_queue_generation(name, mode, generation).await {
+                Ok(snapshot) => {
+                    error!(
+                        "Unable to load snapshot of {}, restored all queue files from previous generation {}: {}",
+                        name.display(),
+                        generation,
+                        error
+                    );
+
+                    return Ok(snapshot);
+                }
+                Err(PersistenceError::FileOpenError(_)) => (),
+                Err(e) => {
+                    if let PersistenceError::FileOpenError(_) = error {
+                        error = e;
+                    }
+                }
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Load serialized queue metadata (like deduplication index) from `source`
+    ///
+    /// Returns default value if file doesn't exist, as metadata files are optional
+    pub(crate) async fn load_or_default<S, P>(&self, source: P) -> Result<S, PersistenceError>
+    where
+        P: AsRef<Path>,
+        S: DeserializeOwned + Default,
+    {
+        match self.load(source.as_ref()).await {
+            Ok(source) => Ok(source),
+            Err(PersistenceError::FileOpenError(_)) => {
+                debug!(
+                    "{} doesn't exist, using default value",
+                    source.as_ref().display()
+                );
+                Ok(S::default())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Persist queue with provided [`PersistMode`]
     ///
     /// Usually, when using this driver you may prefer [`PersistMode::Queue`],
     /// but if your driver doesn't support replication storage serialization,
     /// then pair it with [`Snapshot`] and choose [`PersistMode::Replication`] mode
+    ///
+    /// Deduplication index and schedule state are persisted in both modes
+    ///
+    /// Poisoned queue is skipped, as its state doesn't match log anymore.
     pub async fn persist_queue<P, DB>(
         &self,
         name: P,
@@ -92,11 +538,25 @@ impl<'c> Snapshot<'c> {
         P: AsRef<Path>,
         DB: Serialize,
     {
+        if queue.poisoned() {
+            error!(
+                "Skipping persistence of poisoned queue {}",
+                name.as_ref().display()
+            );
+            return Ok(());
+        }
+
         if let PersistMode::Queue = mode {
             self.persist(&*queue.database().await, name.as_ref().join(QUEUE_FILE))
                 .await?;
         }
 
+        self.persist(&*queue.dedup().await, name.as_ref().join(DEDUP_FILE))
+            .await?;
+
+        self.persist(&*queue.schedule().await, name.as_ref().join(SCHEDULE_FILE))
+            .await?;
+
         #[cfg(feature = "replication")]
         {
             self.persist(
@@ -115,25 +575,276 @@ impl<'c> Snapshot<'c> {
         P: AsRef<Path>,
         DB: DeserializeOwned,
     {
-        let database = self.load(name.as_ref().join(QUEUE_FILE)).await?;
+        let snapshot = self
+            .load_queue_snapshot(name.as_ref(), PersistMode::Queue)
+            .await?;
+
+        let database = snapshot
+            .database
+            .expect("Database is loaded in queue persistence mode");
 
         cfg_if! {
             if #[cfg(feature = "replication")] {
-                let replication_storage = match self.load(name.as_ref().join(REPLICATION_FILE)).await {
-                    Ok(storage) => storage,
-                    Err(PersistenceError::FileOpenError(e)) => {
-                        error!("{}", e);
-                        None
-                    },
-                    Err(e) => return Err(e)
-                };
-
-                let queue = Queue::new(database, replication_storage);
+                let queue = Queue::new(database, snapshot.dedup, snapshot.schedule, snapshot.replication_storage);
             } else {
-                let queue = Queue::new(database);
+                let queue = Queue::new(database, snapshot.dedup, snapshot.schedule);
             }
         }
 
         Ok(queue)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use chrono::{TimeZone, Utc};
+    use maybe_owned::MaybeOwned;
+    use spartan_lib::core::{dispatcher::SimpleDispatcher, message::builder::MessageBuilder};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        config::encryption::EncryptionKey,
+        node::{event::Event, DB},
+    };
+
+    /// Replace snapshot body with data, that can't be deserialized
+    fn corrupt(path: &Path) {
+        let mut snapshot = make_header(SNAPSHOT_MAGIC, SNAPSHOT_VERSION);
+        snapshot.extend(&[255; 4]);
+
+        std::fs::write(path, snapshot).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_persist_generations() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            snapshot_generations: 2,
+            ..Default::default()
+        };
+
+        let snapshot = Snapshot::new(&config, None);
+
+        for i in 0..4u32 {
+            snapshot.persist(&i, "test").await.unwrap();
+        }
+
+        let path = dir.path().join("test");
+        let associated_data = associated_data(Path::new("test"));
+
+        assert_eq!(snapshot.load::<u32, _>("test").await.unwrap(), 3);
+        assert_eq!(
+            snapshot
+                .load_generation::<u32>(&generation_path(&path, 1), &associated_data)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            snapshot
+                .load_generation::<u32>(&generation_path(&path, 2), &associated_data)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(!generation_path(&path, 3).exists());
+        assert!(!suffixed_path(&path, ".tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn test_load_previous_generation() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        let snapshot = Snapshot::new(&config, None);
+
+        snapshot
+            .persist(&String::from("Hello"), "test")
+            .await
+            .unwrap();
+        snapshot
+            .persist(&String::from("world"), "test")
+            .await
+            .unwrap();
+
+        // Latest snapshot is unreadable
+        corrupt(&dir.path().join("test"));
+
+        assert_eq!(
+            snapshot.load::<String, _>("test").await.unwrap(),
+            String::from("Hello")
+        );
+
+        // Latest snapshot is missing, as crash happened between generation rotation and rename
+        std::fs::remove_file(dir.path().join("test")).unwrap();
+
+        assert_eq!(
+            snapshot.load::<String, _>("test").await.unwrap(),
+            String::from("Hello")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_without_valid_generation() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        let snapshot = Snapshot::new(&config, None);
+
+        assert!(matches!(
+            snapshot.load::<String, _>("test").await,
+            Err(PersistenceError::FileOpenError(_))
+        ));
+
+        corrupt(&dir.path().join("test"));
+
+        assert!(matches!(
+            snapshot.load::<String, _>("test").await,
+            Err(PersistenceError::InvalidFileFormat(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_load_queue_from_single_generation() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        let snapshot = Snapshot::new(&config, None);
+        let queue = DB::default();
+
+        for (body, processed_at) in &[("Hello", 1), ("world", 2)] {
+            let message = MessageBuilder::default()
+                .body(*body)
+                .dedup_id(*body)
+                .compose()
+                .unwrap();
+
+            queue
+                .dedup()
+                .await
+                .record(&Event::Push(MaybeOwned::Borrowed(&message)));
+            queue
+                .schedule()
+                .await
+                .advance("tick", Utc.timestamp(*processed_at, 0));
+            queue.database().await.push(message);
+
+            snapshot
+                .persist_queue("test", &queue, PersistMode::Queue)
+                .await
+                .unwrap();
+        }
+
+        // Only queue database of latest snapshot is unreadable
+        corrupt(&dir.path().join("test").join(QUEUE_FILE));
+
+        let queue: DB = snapshot.load_queue("test").await.unwrap();
+
+        assert_eq!(queue.database().await.size(), 1);
+        assert!(queue.dedup().await.get("Hello", 60).is_some());
+        assert!(queue.dedup().await.get("world", 60).is_none());
+        assert_eq!(
+            queue.schedule().await.processed_at("tick"),
+            Some(Utc.timestamp(1, 0))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        let snapshot = Snapshot::new(&config, None);
+        let legacy = serialize(&String::from("Hello")).unwrap();
+
+        std::fs::write(dir.path().join("test"), &legacy).unwrap();
+
+        assert!(matches!(
+            snapshot.load::<String, _>("test").await,
+            Err(PersistenceError::OutdatedFormat)
+        ));
+
+        let migrated = Snapshot::migrate_snapshot(&legacy, None, b"", same_layout)
+            .unwrap()
+            .unwrap();
+        assert!(
+            Snapshot::migrate_snapshot(&migrated, None, b"", same_layout)
+                .unwrap()
+                .is_none()
+        );
+
+        std::fs::write(dir.path().join("test"), &migrated).unwrap();
+
+        assert_eq!(
+            snapshot.load::<String, _>("test").await.unwrap(),
+            String::from("Hello")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate_unencrypted_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let config = PersistenceConfig {
+            path: Cow::Borrowed(dir.path()),
+            ..Default::default()
+        };
+
+        let key = EncryptionKey::from([1; 32]);
+        let encryption = Encryption::new(&key, &[]);
+
+        Snapshot::new(&config, None)
+            .persist(&String::from("Hello"), "test")
+            .await
+            .unwrap();
+
+        let snapshot = Snapshot::new(&config, Some(encryption));
+
+        assert!(matches!(
+            snapshot.load::<String, _>("test").await,
+            Err(PersistenceError::UnencryptedData)
+        ));
+
+        let path = dir.path().join("test");
+        let associated_data = associated_data(Path::new("test"));
+
+        let migrated = Snapshot::migrate_snapshot(
+            &std::fs::read(&path).unwrap(),
+            Some(encryption),
+            &associated_data,
+            same_layout,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(Snapshot::migrate_snapshot(
+            &migrated,
+            Some(encryption),
+            &associated_data,
+            same_layout
+        )
+        .unwrap()
+        .is_none());
+
+        std::fs::write(&path, &migrated).unwrap();
+
+        assert_eq!(
+            snapshot.load::<String, _>("test").await.unwrap(),
+            String::from("Hello")
+        );
+    }
+}
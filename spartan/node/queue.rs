@@ -1,17 +1,57 @@
-use tokio::sync::{Mutex, MutexGuard};
+#[cfg(feature = "replication")]
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::{Mutex, MutexGuard, Notify};
 
 #[cfg(feature = "replication")]
 use crate::node::replication::storage::ReplicationStorage;
-use crate::node::{event::Event, persistence::PersistenceError, Manager};
+use crate::node::{
+    dedup::DedupIndex,
+    event::Event,
+    persistence::{log::writer::Commit, PersistenceError},
+    schedule::ScheduleState,
+    Manager,
+};
 
 pub struct Queue<DB> {
     /// Inner database
     database: Mutex<DB>,
 
+    /// Long-polling pop notifier
+    /// Notified on each event that may make message available for reservation
+    notify: Notify,
+
+    /// Message deduplication index
+    dedup: Mutex<DedupIndex>,
+
+    /// Progress of queue schedules
+    schedule: Mutex<ScheduleState>,
+
+    /// Queue database was changed by event, that failed to be committed to log
+    ///
+    /// Database doesn't match log anymore, so poisoned queue doesn't accept events and is not persisted.
+    poisoned: AtomicBool,
+
     #[cfg(feature = "replication")]
     /// Replication storage
     /// None if replication is not enabled
     replication_storage: Mutex<Option<ReplicationStorage>>,
+
+    #[cfg(feature = "replication")]
+    /// Logged events, that are not yet committed, in log order
+    pending_events: Mutex<PendingEvents>,
+}
+
+#[cfg(feature = "replication")]
+#[derive(Default)]
+/// Events, that are sent to replication storage once they are committed
+struct PendingEvents {
+    /// Index of next logged event
+    next_index: u64,
+
+    /// Logged events with their indexes
+    events: VecDeque<(u64, Event<'static>)>,
 }
 
 impl<DB> Default for Queue<DB>
@@ -21,25 +61,45 @@ where
     fn default() -> Self {
         Queue {
             database: Mutex::new(DB::default()),
+            notify: Notify::new(),
+            dedup: Mutex::new(DedupIndex::default()),
+            schedule: Mutex::new(ScheduleState::default()),
+            poisoned: AtomicBool::new(false),
             #[cfg(feature = "replication")]
             replication_storage: Mutex::new(None),
+            #[cfg(feature = "replication")]
+            pending_events: Mutex::new(PendingEvents::default()),
         }
     }
 }
 
 impl<DB> Queue<DB> {
     #[cfg(feature = "replication")]
-    pub fn new(database: DB, replication_storage: Option<ReplicationStorage>) -> Queue<DB> {
+    pub fn new(
+        database: DB,
+        dedup: DedupIndex,
+        schedule: ScheduleState,
+        replication_storage: Option<ReplicationStorage>,
+    ) -> Queue<DB> {
         Queue {
             database: Mutex::new(database),
+            notify: Notify::new(),
+            dedup: Mutex::new(dedup),
+            schedule: Mutex::new(schedule),
+            poisoned: AtomicBool::new(false),
             replication_storage: Mutex::new(replication_storage),
+            pending_events: Mutex::new(PendingEvents::default()),
         }
     }
 
     #[cfg(not(feature = "replication"))]
-    pub fn new(database: DB) -> Queue<DB> {
+    pub fn new(database: DB, dedup: DedupIndex, schedule: ScheduleState) -> Queue<DB> {
         Queue {
             database: Mutex::new(database),
+            notify: Notify::new(),
+            dedup: Mutex::new(dedup),
+            schedule: Mutex::new(schedule),
+            poisoned: AtomicBool::new(false),
         }
     }
 
@@ -47,6 +107,24 @@ impl<DB> Queue<DB> {
         self.database.lock().await
     }
 
+    pub async fn dedup(&self) -> MutexGuard<'_, DedupIndex> {
+        self.dedup.lock().await
+    }
+
+    pub async fn schedule(&self) -> MutexGuard<'_, ScheduleState> {
+        self.schedule.lock().await
+    }
+
+    /// Check if queue is poisoned by failed log write
+    pub fn poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    /// Wait for event, that may make message available for reservation
+    pub async fn notified(&self) {
+        self.notify.notified().await
+    }
+
     #[cfg(feature = "replication")]
     pub async fn replication_storage(&self) -> MutexGuard<'_, Option<ReplicationStorage>> {
         self.replication_storage.lock().await
@@ -69,17 +147,131 @@ impl<DB> Queue<DB> {
         }
     }
 
+    /// Log event to persistence
+    ///
+    /// Events should be logged with database lock being held, and only for mutations,
+    /// that will change database, so that log and replicas observe the same sequence of changes.
+    ///
+    /// Mutation should be checked against database, and applied only after event is logged,
+    /// so that rejected event leaves database unchanged.
+    /// Event is written to log in background, so database is changed before event is committed,
+    /// and failed commit poisons queue (see [`QueueCommit::wait`]).
+    ///
+    /// Push and requeue events also wake long-polling pop requests (one per message),
+    /// and database lock makes sure, that woken request will observe database change.
+    ///
+    /// Deduplication IDs of pushed messages are recorded to deduplication index.
+    ///
+    /// Returned [`QueueCommit`] should be awaited after database lock is released,
+    /// so that concurrent events are written to log as one group commit.
     pub async fn log_event(
         &self,
         name: &str,
         manager: &Manager<'_>,
         event: Event<'_>,
-    ) -> Result<(), PersistenceError> {
-        manager.log(name, &event).await?;
+    ) -> Result<QueueCommit<'_, DB>, PersistenceError> {
+        if self.poisoned() {
+            return Err(PersistenceError::QueuePoisoned);
+        }
+
+        let notify = match &event {
+            Event::Push(_) | Event::Requeue(_) | Event::RequeueDelayed(..) => 1,
+            Event::PushBatch(messages) => messages.len(),
+            Event::RequeueExpired(ids) => ids.len(),
+            _ => 0,
+        };
+
+        let commit = manager.log(name, &event)?;
+
+        self.dedup.lock().await.record(&event);
 
         #[cfg(feature = "replication")]
-        if let Some(storage) = self.replication_storage().await.as_mut() {
-            storage.map_primary(|storage| storage.push(event.into_owned()));
+        let index = if self.replication_storage().await.is_some() {
+            let mut pending = self.pending_events.lock().await;
+            let index = pending.next_index;
+
+            pending.next_index += 1;
+            pending.events.push_back((index, event.into_owned()));
+
+            Some(index)
+        } else {
+            None
+        };
+
+        for _ in 0..notify {
+            self.notify.notify();
+        }
+
+        Ok(QueueCommit {
+            commit,
+            queue: self,
+            #[cfg(feature = "replication")]
+            index,
+        })
+    }
+
+    /// Send committed events up to `index` to replication storage
+    ///
+    /// Log is written in order, so all events before committed one are committed as well.
+    #[cfg(feature = "replication")]
+    async fn replicate(&self, index: u64) {
+        let mut pending = self.pending_events.lock().await;
+        let mut replication_storage = self.replication_storage().await;
+
+        while matches!(pending.events.front(), Some((pending_index, _)) if *pending_index <= index)
+        {
+            if let (Some((_, event)), Some(storage)) =
+                (pending.events.pop_front(), replication_storage.as_mut())
+            {
+                storage.map_primary(|storage| storage.push(event));
+            }
+        }
+    }
+}
+
+/// Pending commit of queue event
+#[must_use]
+pub struct QueueCommit<'q, DB> {
+    /// Commit of log entry
+    commit: Commit,
+
+    /// Queue, that event was logged to
+    queue: &'q Queue<DB>,
+
+    #[cfg(feature = "replication")]
+    /// Index of pending event, that is sent to replication storage after commit
+    index: Option<u64>,
+}
+
+impl<'q, DB> QueueCommit<'q, DB> {
+    /// Commit, that doesn't require waiting (for example, if there was no event to log)
+    pub fn done(queue: &'q Queue<DB>) -> Self {
+        QueueCommit {
+            commit: Commit::done(),
+            queue,
+            #[cfg(feature = "replication")]
+            index: None,
+        }
+    }
+
+    /// Wait for event to be committed to log
+    ///
+    /// Event is sent to replication storage only after it's committed.
+    ///
+    /// If commit fails, then queue is poisoned, as its database is already changed.
+    /// Poisoned queue rejects all following events, until it's restored from log by restart.
+    pub async fn wait(self) -> Result<(), PersistenceError> {
+        if let Err(e) = self.commit.wait().await {
+            if !self.queue.poisoned.swap(true, Ordering::AcqRel) {
+                error!("Queue is poisoned by failed log write: {}", e);
+            }
+
+            return Err(e);
+        }
+
+        #[cfg(feature = "replication")]
+        if let Some(index) = self.index {
+            self.queue.replicate(index).await;
         }
 
         Ok(())
@@ -88,14 +280,178 @@ impl<DB> Queue<DB> {
 
 #[cfg(test)]
 mod tests {
-    use crate::node::{
-        replication::{
-            primary::storage::PrimaryStorage, replica::storage::ReplicaStorage,
-            storage::ReplicationStorage,
+    use std::borrow::Cow;
+
+    use maybe_owned::MaybeOwned;
+    use spartan_lib::core::{
+        dispatcher::SimpleDispatcher,
+        message::{builder::MessageBuilder, Message},
+    };
+    use tempfile::TempDir;
+
+    use crate::{
+        config::{
+            persistence::{Persistence, PersistenceConfig},
+            Config,
+        },
+        node::{
+            event::Event,
+            persistence::PersistenceError,
+            replication::{
+                primary::storage::PrimaryStorage, replica::storage::ReplicaStorage,
+                storage::ReplicationStorage,
+            },
+            Manager, DB,
         },
-        DB,
+        utils::testing::CONFIG,
     };
 
+    fn message(body: &str) -> Message {
+        MessageBuilder::default().body(body).compose().unwrap()
+    }
+
+    /// Config with log persistence in `dir`, where log file of queue "test" can't be opened
+    fn failing_log_config(dir: &TempDir) -> Config<'_> {
+        // Directory in place of log file
+        std::fs::create_dir_all(dir.path().join("test/queue_log")).unwrap();
+
+        Config {
+            persistence: Some(PersistenceConfig {
+                mode: Persistence::Log,
+                path: Cow::Borrowed(dir.path()),
+                ..Default::default()
+            }),
+            queues: vec!["test".to_string().into_boxed_str()].into_boxed_slice(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_poisons_queue() {
+        let dir = TempDir::new().unwrap();
+        let config = failing_log_config(&dir);
+
+        let manager = Manager::new(&config);
+        let queue = manager.queue("test").unwrap();
+
+        let message = message("Hello, world");
+
+        let commit = queue
+            .log_event(
+                "test",
+                &manager,
+                Event::Push(MaybeOwned::Borrowed(&message)),
+            )
+            .await
+            .unwrap();
+
+        queue.database().await.push(message);
+
+        assert!(!queue.poisoned());
+
+        assert!(matches!(
+            commit.wait().await.unwrap_err(),
+            PersistenceError::LogWriteError(_)
+        ));
+
+        assert!(queue.poisoned());
+
+        assert!(matches!(
+            queue
+                .log_event("test", &manager, Event::Clear)
+                .await
+                .err()
+                .unwrap(),
+            PersistenceError::QueuePoisoned
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replicate_committed_event() {
+        let manager = Manager::new(&CONFIG);
+        let queue = manager.queue("test").unwrap();
+
+        queue
+            .prepare_replication(
+                |_| false,
+                || ReplicationStorage::Primary(PrimaryStorage::default()),
+            )
+            .await;
+
+        let commit = queue
+            .log_event(
+                "test",
+                &manager,
+                Event::Push(MaybeOwned::Owned(message("Hello, world"))),
+            )
+            .await
+            .unwrap();
+
+        // Event is not sent to replicas until it's committed
+        assert!(queue
+            .replication_storage()
+            .await
+            .as_mut()
+            .unwrap()
+            .get_primary()
+            .slice(1)
+            .unwrap()
+            .is_empty());
+
+        commit.wait().await.unwrap();
+
+        assert_eq!(
+            queue
+                .replication_storage()
+                .await
+                .as_mut()
+                .unwrap()
+                .get_primary()
+                .slice(1)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_is_not_replicated() {
+        let dir = TempDir::new().unwrap();
+        let config = failing_log_config(&dir);
+
+        let manager = Manager::new(&config);
+        let queue = manager.queue("test").unwrap();
+
+        queue
+            .prepare_replication(
+                |_| false,
+                || ReplicationStorage::Primary(PrimaryStorage::default()),
+            )
+            .await;
+
+        queue
+            .log_event(
+                "test",
+                &manager,
+                Event::Push(MaybeOwned::Owned(message("Hello, world"))),
+            )
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap_err();
+
+        assert!(queue
+            .replication_storage()
+            .await
+            .as_mut()
+            .unwrap()
+            .get_primary()
+            .slice(1)
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn test_prepare_replication_empty() {
         let queue = DB::default();
@@ -49,3 +49,6 @@ pub mod primary;
 
 /// Replica node
 pub mod replica;
+
+/// Frozen replication storage layout, that was persisted before format versioning
+pub mod v0;
@@ -23,6 +23,17 @@ impl Default for PrimaryStorage {
 }
 
 impl PrimaryStorage {
+    /// Make storage with empty log, that continues from `next_index`
+    ///
+    /// Events before it are considered as already collected by GC.
+    pub fn continue_from(next_index: u64) -> Self {
+        PrimaryStorage {
+            next_index,
+            gc_threshold: next_index.saturating_sub(1),
+            log: BTreeMap::new(),
+        }
+    }
+
     pub fn push(&mut self, event: Event<'static>) {
         self.log.insert(self.next_index, event);
         self.next_index += 1;
@@ -73,7 +84,7 @@ mod tests {
         let mut storage = PrimaryStorage::default();
 
         for _ in 0..6 {
-            storage.push(Event::Pop);
+            storage.push(Event::Clear);
         }
 
         let slice = storage.slice(1).unwrap();
@@ -113,13 +124,13 @@ mod tests {
         let mut storage = PrimaryStorage::default();
 
         for _ in 0..6 {
-            storage.push(Event::Pop);
+            storage.push(Event::Clear);
         }
 
         let slice = storage.slice(1).unwrap();
         assert_eq!(slice.len(), 6);
 
         let (index, event) = slice.first().unwrap();
-        assert_eq!((**index, &**event), (1, &Event::Pop));
+        assert_eq!((**index, &**event), (1, &Event::Clear));
     }
 }
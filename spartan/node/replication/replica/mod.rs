@@ -126,12 +126,20 @@ pub async fn accept_connection<'m, 'c>(
 
                 let index = range.last().map(|(index, _)| **index);
 
-                db.database()
-                    .await
-                    .apply_log(range.into_iter().map(|(_, event)| match event {
+                let events = range
+                    .into_iter()
+                    .map(|(_, event)| match event {
                         MaybeOwned::Owned(event) => event,
                         MaybeOwned::Borrowed(_) => unreachable!(),
-                    }));
+                    })
+                    .collect::<Vec<_>>();
+
+                {
+                    let mut dedup = db.dedup().await;
+                    events.iter().for_each(|event| dedup.record(event));
+                }
+
+                db.database().await.apply_log(events);
 
                 if let Some(index) = index {
                     debug!("Setting {} as confirmed index of {}", index, queue);
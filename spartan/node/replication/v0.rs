@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::node::{
+    event::v0::Event,
+    replication::{
+        primary::storage::PrimaryStorage as CurrentPrimaryStorage,
+        replica::storage::ReplicaStorage as CurrentReplicaStorage,
+        storage::ReplicationStorage as CurrentReplicationStorage,
+    },
+};
+
+/// Primary storage with log of events, that don't contain results of pop and GC
+#[derive(Deserialize)]
+pub struct PrimaryStorage {
+    pub next_index: u64,
+    pub gc_threshold: u64,
+    pub log: BTreeMap<u64, Event>,
+}
+
+impl From<PrimaryStorage> for CurrentPrimaryStorage {
+    /// Log is dropped, as replicas can't replay it without results of pop and GC,
+    /// so replicas, that haven't received it yet, are treated as lagging behind GC.
+    fn from(storage: PrimaryStorage) -> Self {
+        if !storage.log.is_empty() {
+            warn!(
+                "Dropping replication log events from {} to {}, replicas will treat them as collected by GC",
+                storage.gc_threshold + 1,
+                storage.next_index - 1
+            );
+        }
+
+        CurrentPrimaryStorage::continue_from(storage.next_index)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReplicaStorage {
+    pub confirmed_index: u64,
+}
+
+impl From<ReplicaStorage> for CurrentReplicaStorage {
+    fn from(storage: ReplicaStorage) -> Self {
+        let mut converted = CurrentReplicaStorage::default();
+        converted.confirm(storage.confirmed_index);
+        converted
+    }
+}
+
+#[derive(Deserialize)]
+pub enum ReplicationStorage {
+    Primary(PrimaryStorage),
+    Replica(ReplicaStorage),
+}
+
+impl From<ReplicationStorage> for CurrentReplicationStorage {
+    fn from(storage: ReplicationStorage) -> Self {
+        match storage {
+            ReplicationStorage::Primary(storage) => {
+                CurrentReplicationStorage::Primary(storage.into())
+            }
+            ReplicationStorage::Replica(storage) => {
+                CurrentReplicationStorage::Replica(storage.into())
+            }
+        }
+    }
+}
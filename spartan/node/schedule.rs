@@ -0,0 +1,221 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::schedule::{CatchUp, Schedule};
+
+/// Max amount of missed ticks, that are materialized with [`CatchUp::All`] policy
+pub const MAX_CATCH_UP_TICKS: usize = 1000;
+
+/// Progress of queue schedules
+///
+/// Contains timestamp of the last processed moment of each schedule,
+/// so that ticks are neither lost nor repeated between scheduler runs.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ScheduleState {
+    processed: HashMap<Box<str>, i64>,
+}
+
+impl ScheduleState {
+    /// Get moment, up to which schedule ticks were processed
+    pub fn processed_at(&self, name: &str) -> Option<DateTime<Utc>> {
+        self.processed
+            .get(name)
+            .map(|timestamp| Utc.timestamp(*timestamp, 0))
+    }
+
+    /// Collect schedule ticks, that are due at `now`
+    ///
+    /// Schedule, that was never processed before, starts from `now` without any ticks.
+    /// If `catch_up` is set, then due ticks are treated as missed ones and are filtered with schedule [`CatchUp`] policy.
+    ///
+    /// Returned ticks are not marked as processed, each of them has to be marked with [`advance`] once materialized.
+    /// Ticks, that are skipped by catch-up policy or limit, are marked as processed right away.
+    ///
+    /// [`advance`]: ScheduleState::advance
+    pub fn due_ticks(
+        &mut self,
+        name: &str,
+        schedule: &Schedule,
+        now: DateTime<Utc>,
+        catch_up: bool,
+    ) -> Vec<DateTime<Utc>> {
+        let mut cursor = match self.processed_at(name) {
+            Some(cursor) => cursor,
+            None => {
+                self.advance(name, now);
+                return Vec::new();
+            }
+        };
+
+        let mut ticks = VecDeque::new();
+        let mut skipped = None;
+
+        while let Some(tick) = schedule.next_tick(cursor).filter(|tick| *tick <= now) {
+            if ticks.len() == MAX_CATCH_UP_TICKS {
+                skipped = ticks.pop_front();
+            }
+
+            ticks.push_back(tick);
+            cursor = tick;
+        }
+
+        if catch_up {
+            match schedule.catch_up {
+                CatchUp::Skip => {
+                    ticks.clear();
+                }
+                CatchUp::Latest => {
+                    skipped = ticks
+                        .drain(..ticks.len().saturating_sub(1))
+                        .next_back()
+                        .or(skipped);
+                }
+                CatchUp::All => (),
+            }
+        }
+
+        if ticks.is_empty() {
+            self.advance(name, now);
+        } else if let Some(skipped) = skipped {
+            self.advance(name, skipped);
+        }
+
+        ticks.into()
+    }
+
+    /// Mark schedule as processed up to `at`
+    pub fn advance(&mut self, name: &str, at: DateTime<Utc>) {
+        self.processed.insert(name.into(), at.timestamp());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use super::{ScheduleState, MAX_CATCH_UP_TICKS};
+    use crate::config::schedule::{CatchUp, Schedule};
+
+    fn schedule(cron: &str, catch_up: CatchUp) -> Schedule {
+        let mut schedule: Schedule = toml::from_str(&format!(
+            "cron = \"{}\"\n[message]\nbody = \"Hello, world\"",
+            cron
+        ))
+        .unwrap();
+
+        schedule.catch_up = catch_up;
+        schedule
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 3, 1).and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn test_due_ticks() {
+        let schedule = schedule("*/15 * * * *", CatchUp::Skip);
+        let mut state = ScheduleState::default();
+
+        assert!(state
+            .due_ticks("test", &schedule, at(10, 5), false)
+            .is_empty());
+        assert_eq!(state.processed_at("test"), Some(at(10, 5)));
+
+        assert!(state
+            .due_ticks("test", &schedule, at(10, 10), false)
+            .is_empty());
+        assert_eq!(state.processed_at("test"), Some(at(10, 10)));
+
+        assert_eq!(
+            state.due_ticks("test", &schedule, at(10, 15), false),
+            vec![at(10, 15)]
+        );
+        state.advance("test", at(10, 15));
+
+        assert_eq!(
+            state.due_ticks("test", &schedule, at(10, 50), false),
+            vec![at(10, 30), at(10, 45)]
+        );
+    }
+
+    #[test]
+    fn test_unprocessed_ticks() {
+        let schedule = schedule("*/15 * * * *", CatchUp::Latest);
+        let mut state = ScheduleState::default();
+
+        state.due_ticks("test", &schedule, at(10, 0), false);
+
+        // Ticks are due again, until they are marked as processed
+        assert_eq!(
+            state.due_ticks("test", &schedule, at(10, 50), false),
+            vec![at(10, 15), at(10, 30), at(10, 45)]
+        );
+        assert_eq!(state.processed_at("test"), Some(at(10, 0)));
+
+        state.advance("test", at(10, 15));
+
+        assert_eq!(
+            state.due_ticks("test", &schedule, at(10, 50), false),
+            vec![at(10, 30), at(10, 45)]
+        );
+
+        // Ticks, that are skipped by catch-up policy, are processed right away
+        assert_eq!(
+            state.due_ticks("test", &schedule, at(10, 50), true),
+            vec![at(10, 45)]
+        );
+        assert_eq!(state.processed_at("test"), Some(at(10, 30)));
+    }
+
+    #[test]
+    fn test_catch_up() {
+        let policies = [
+            (CatchUp::Skip, vec![]),
+            (CatchUp::Latest, vec![at(11, 45)]),
+            (
+                CatchUp::All,
+                vec![
+                    at(10, 15),
+                    at(10, 30),
+                    at(10, 45),
+                    at(11, 0),
+                    at(11, 15),
+                    at(11, 30),
+                    at(11, 45),
+                ],
+            ),
+        ];
+
+        for (catch_up, expected) in policies.iter() {
+            let schedule = schedule("*/15 * * * *", *catch_up);
+            let mut state = ScheduleState::default();
+
+            state.due_ticks("test", &schedule, at(10, 0), false);
+
+            assert_eq!(
+                &state.due_ticks("test", &schedule, at(11, 50), true),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_catch_up_limit() {
+        let schedule = schedule("* * * * *", CatchUp::All);
+        let mut state = ScheduleState::default();
+
+        state.due_ticks(
+            "test",
+            &schedule,
+            Utc.ymd(2021, 2, 1).and_hms(0, 0, 0),
+            false,
+        );
+
+        let ticks = state.due_ticks("test", &schedule, at(0, 0), true);
+
+        assert_eq!(ticks.len(), MAX_CATCH_UP_TICKS);
+        assert_eq!(ticks.last(), Some(&at(0, 0)));
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Source of current time
+///
+/// All time-dependent message operations (delays, timeouts and expiration) receive clock,
+/// so that they can be driven by [`MockClock`] in tests instead of real sleeping.
+pub trait Clock {
+    /// Get current UTC time
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock, that returns current system time
+///
+/// ```
+/// use spartan_lib::core::clock::{Clock, SystemClock};
+/// use spartan_lib::chrono::Utc;
+///
+/// assert!(SystemClock.now() <= Utc::now());
+/// ```
+#[derive(Default, Debug, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Manually advanced clock
+///
+/// Clones share the same time, so clock may be advanced after it was moved into database.
+///
+/// ```
+/// use spartan_lib::core::clock::{Clock, MockClock};
+/// use spartan_lib::chrono::{Duration, TimeZone, Utc};
+///
+/// let clock = MockClock::new(Utc.ymd(2021, 3, 1).and_hms(10, 0, 0));
+/// let shared = clock.clone();
+///
+/// clock.advance(Duration::seconds(30));
+///
+/// assert_eq!(shared.now(), Utc.ymd(2021, 3, 1).and_hms(10, 0, 30));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl MockClock {
+    /// Create clock, stopped at provided time
+    pub fn new(now: DateTime<Utc>) -> MockClock {
+        MockClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Move clock forward by provided duration
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("Poisoned mock clock");
+        *now = *now + duration;
+    }
+
+    /// Set clock to provided time
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().expect("Poisoned mock clock") = now;
+    }
+}
+
+/// Mock clock, stopped at current system time
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new(Utc::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("Poisoned mock clock")
+    }
+}
+
+/// Clock, that is stopped at provided time
+///
+/// Used to evaluate all messages of a single dispatcher operation at the same time.
+impl Clock for DateTime<Utc> {
+    fn now(&self) -> DateTime<Utc> {
+        *self
+    }
+}
@@ -4,13 +4,36 @@ mod tree;
 /// `VecDatabase`
 mod vec;
 
+/// Frozen `TreeDatabase` layout, that was persisted before format versioning
+pub mod v0;
+
 pub use tree::TreeDatabase;
 pub use vec::VecDatabase;
 
+use crate::core::clock::Clock;
+
 /// Interface for working with databases
-pub trait Database<M>: Default {
+pub trait Database<M> {
     type PositionKey: Copy;
 
+    /// Clock, that is used by dispatchers for time-dependent operations
+    type Clock: Clock;
+
+    /// Get database clock
+    ///
+    /// ```
+    /// use spartan_lib::chrono::{TimeZone, Utc};
+    /// use spartan_lib::core::clock::{Clock, MockClock};
+    /// use spartan_lib::core::db::Database;
+    /// use spartan_lib::core::db::VecDatabase;
+    ///
+    /// let clock = MockClock::new(Utc.ymd(2021, 3, 1).and_hms(10, 0, 0));
+    /// let db = VecDatabase::<u32, _>::with_clock(clock.clone());
+    ///
+    /// assert_eq!(db.clock().now(), clock.now());
+    /// ```
+    fn clock(&self) -> &Self::Clock;
+
     /// Push raw message to database
     ///
     /// ```
@@ -28,6 +51,7 @@ pub trait Database<M>: Default {
     /// Get database position key of the first message, that matches predicate
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::db::Database;
     /// use spartan_lib::core::db::VecDatabase;
     /// use spartan_lib::core::message::builder::MessageBuilder;
@@ -38,15 +62,36 @@ pub trait Database<M>: Default {
     ///
     /// db.push_raw(message);
     ///
-    /// assert_eq!(db.position(|msg| msg.obtainable()).unwrap(), 0);
+    /// assert_eq!(db.position(|msg| msg.obtainable(&SystemClock)).unwrap(), 0);
     /// ```
     fn position<F>(&self, predicate: F) -> Option<Self::PositionKey>
     where
         F: Fn(&M) -> bool;
 
+    /// Get database position keys of all messages, that match predicate
+    ///
+    /// Position keys of `VecDatabase` are indexes, so they are invalidated by removal of message
+    ///
+    /// ```
+    /// use spartan_lib::core::db::Database;
+    /// use spartan_lib::core::db::VecDatabase;
+    ///
+    /// let mut db = VecDatabase::default();
+    ///
+    /// db.push_raw(1);
+    /// db.push_raw(2);
+    /// db.push_raw(3);
+    ///
+    /// assert_eq!(db.positions(|msg| *msg != 2), vec![0, 2]);
+    /// ```
+    fn positions<F>(&self, predicate: F) -> Vec<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool;
+
     /// Get shared message reference by database position key
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::db::Database;
     /// use spartan_lib::core::db::VecDatabase;
     /// use spartan_lib::core::message::builder::MessageBuilder;
@@ -57,15 +102,16 @@ pub trait Database<M>: Default {
     ///
     /// db.push_raw(message);
     ///
-    /// let position = db.position(|msg| msg.obtainable()).unwrap();
+    /// let position = db.position(|msg| msg.obtainable(&SystemClock)).unwrap();
     ///
-    /// assert!(db.get(position).unwrap().obtainable());
+    /// assert!(db.get(position).unwrap().obtainable(&SystemClock));
     /// ```
     fn get(&self, position: Self::PositionKey) -> Option<&M>;
 
     /// Get mutable message reference by database position key
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::db::Database;
     /// use spartan_lib::core::db::VecDatabase;
     /// use spartan_lib::core::message::builder::MessageBuilder;
@@ -76,9 +122,9 @@ pub trait Database<M>: Default {
     ///
     /// db.push_raw(message);
     ///
-    /// let position = db.position(|msg| msg.obtainable()).unwrap();
+    /// let position = db.position(|msg| msg.obtainable(&SystemClock)).unwrap();
     ///
-    /// db.get_mut(position).unwrap().reserve();
+    /// db.get_mut(position).unwrap().reserve(&SystemClock);
     /// ```
     fn get_mut(&mut self, position: Self::PositionKey) -> Option<&mut M>;
 
@@ -87,6 +133,7 @@ pub trait Database<M>: Default {
     /// Returns owned message if position key is present in database
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::db::Database;
     /// use spartan_lib::core::db::VecDatabase;
     /// use spartan_lib::core::message::builder::MessageBuilder;
@@ -97,7 +144,7 @@ pub trait Database<M>: Default {
     ///
     /// db.push_raw(message);
     ///
-    /// let position = db.position(|msg| msg.obtainable()).unwrap();
+    /// let position = db.position(|msg| msg.obtainable(&SystemClock)).unwrap();
     ///
     /// db.delete_pos(position).unwrap();
     /// ```
@@ -123,6 +170,27 @@ pub trait Database<M>: Default {
     where
         F: Fn(&M) -> bool;
 
+    /// Remove messages, that match predicate, from database
+    ///
+    /// Returns owned removed messages
+    ///
+    /// ```
+    /// use spartan_lib::core::db::Database;
+    /// use spartan_lib::core::db::VecDatabase;
+    ///
+    /// let mut db = VecDatabase::default();
+    ///
+    /// db.push_raw(1);
+    /// db.push_raw(2);
+    /// db.push_raw(3);
+    ///
+    /// assert_eq!(db.drain(|msg| *msg == 1 || *msg == 2), vec![1, 2]);
+    /// assert_eq!(db.len(), 1);
+    /// ```
+    fn drain<F>(&mut self, predicate: F) -> Vec<M>
+    where
+        F: Fn(&M) -> bool;
+
     /// Get database size
     ///
     /// ```
@@ -174,11 +242,74 @@ pub trait Database<M>: Default {
 pub trait StatusAwareDatabase<M>: Database<M> {
     type RequeueKey: Copy;
 
+    /// Get database position key of the first message, that matches predicate, and is not blocked by its message group
+    ///
+    /// Message is blocked, if any previously pushed message of the same group is still available or in transit
+    ///
+    /// ```
+    /// use spartan_lib::core::clock::SystemClock;
+    /// use spartan_lib::core::db::{Database, StatusAwareDatabase};
+    /// use spartan_lib::core::db::VecDatabase;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::{Identifiable, Status};
+    ///
+    /// let mut db = VecDatabase::default();
+    /// let first = MessageBuilder::default().body("Hello").group("customer").compose().unwrap();
+    /// let second = MessageBuilder::default().body("world").group("customer").compose().unwrap();
+    ///
+    /// db.push_raw(first);
+    /// db.push_raw(second.clone());
+    ///
+    /// let position = db.reservable_position(|msg| msg.reservable()).unwrap();
+    /// db.reserve(position).unwrap().reserve(&SystemClock);
+    ///
+    /// // Second message of group is blocked until first one is deleted
+    /// assert!(db.reservable_position(|msg| msg.reservable()).is_none());
+    ///
+    /// db.delete_pos(position);
+    ///
+    /// let position = db.reservable_position(|msg| msg.reservable()).unwrap();
+    /// assert_eq!(db.get(position).unwrap().id(), second.id());
+    /// ```
+    fn reservable_position<F>(&self, predicate: F) -> Option<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool;
+
+    /// Get database position keys of up to `count` messages, that would be reserved one after another
+    ///
+    /// Reservation doesn't unblock any message, so all returned messages may be reserved at once
+    ///
+    /// ```
+    /// use spartan_lib::core::db::{Database, StatusAwareDatabase};
+    /// use spartan_lib::core::db::TreeDatabase;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::{Identifiable, Status};
+    ///
+    /// let mut db = TreeDatabase::default();
+    /// let first = MessageBuilder::default().body("Hello").group("customer").compose().unwrap();
+    /// let second = MessageBuilder::default().body("world").group("customer").compose().unwrap();
+    /// let third = MessageBuilder::default().body("!").compose().unwrap();
+    ///
+    /// db.push_raw(first.clone());
+    /// db.push_raw(second);
+    /// db.push_raw(third.clone());
+    ///
+    /// // Second message of group is blocked by the first one
+    /// assert_eq!(
+    ///     db.reservable_positions(|msg| msg.reservable(), 3),
+    ///     vec![first.id(), third.id()]
+    /// );
+    /// ```
+    fn reservable_positions<F>(&self, predicate: F, count: usize) -> Vec<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool;
+
     /// Reserve message in database
     ///
-    /// Removes message from tree in `TreeDatabase`, does nothing in `VecDatabase`
+    /// Moves message from ready index to reserved index in `TreeDatabase`, does nothing in `VecDatabase`
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::db::{Database, StatusAwareDatabase};
     /// use spartan_lib::core::db::TreeDatabase;
     /// use spartan_lib::core::message::builder::MessageBuilder;
@@ -193,15 +324,18 @@ pub trait StatusAwareDatabase<M>: Database<M> {
     ///
     /// // reserve returns mutable reference to message, so we can call reserve on message too.
     /// let message = db.reserve(position).unwrap();
-    /// message.reserve();
+    /// message.reserve(&SystemClock);
     /// ```
     fn reserve(&mut self, position: Self::PositionKey) -> Option<&mut M>;
 
     /// Requeue message back to database
     ///
-    /// Returns message back to tree in `TreeDatabase`, does nothing in `VecDatabase`
+    /// Returns message back to ready index in `TreeDatabase`, does nothing in `VecDatabase`
+    ///
+    /// Message is updated before being returned to index, so any changes of message sort key are applied
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::db::{Database, StatusAwareDatabase};
     /// use spartan_lib::core::db::TreeDatabase;
     /// use spartan_lib::core::message::builder::MessageBuilder;
@@ -215,15 +349,50 @@ pub trait StatusAwareDatabase<M>: Database<M> {
     /// let position = db.position(|msg| msg.reservable()).unwrap();
     ///
     /// let message = db.reserve(position).unwrap();
-    /// message.reserve();
+    /// message.reserve(&SystemClock);
+    ///
+    /// let id = message.id();
+    ///
+    /// // requeue tries to find a message with provided id, checks it for predicate,
+    /// // and updates message before returning it to index
+    /// db.requeue(id, |msg| msg.requeueable(), |msg| msg.requeue()).unwrap();
+    /// ```
+    fn requeue<F, U>(
+        &mut self,
+        position: Self::RequeueKey,
+        predicate: F,
+        update: U,
+    ) -> Option<&mut M>
+    where
+        F: Fn(&M) -> bool,
+        U: FnOnce(&mut M);
+
+    /// Get requeue keys of all reserved messages, that match predicate
+    ///
+    /// Only reserved index is checked in `TreeDatabase`, while `VecDatabase` checks all messages
+    ///
+    /// ```
+    /// use spartan_lib::core::clock::SystemClock;
+    /// use spartan_lib::core::db::{Database, StatusAwareDatabase};
+    /// use spartan_lib::core::db::TreeDatabase;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::{Identifiable, Status};
+    ///
+    /// let mut db = TreeDatabase::default();
+    /// let message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    ///
+    /// db.push_raw(message);
+    ///
+    /// let position = db.position(|msg| msg.reservable()).unwrap();
+    ///
+    /// let message = db.reserve(position).unwrap();
+    /// message.reserve(&SystemClock);
     ///
     /// let id = message.id();
     ///
-    /// // requeue tries to find a message with provided id, and checks it for predicate
-    /// let message = db.requeue(id, |msg| msg.requeueable()).unwrap();
-    /// message.requeue();
+    /// assert_eq!(db.reserved(|msg| msg.requeueable()), vec![id]);
     /// ```
-    fn requeue<F>(&mut self, position: Self::RequeueKey, predicate: F) -> Option<&mut M>
+    fn reserved<F>(&self, predicate: F) -> Vec<Self::RequeueKey>
     where
         F: Fn(&M) -> bool;
 }
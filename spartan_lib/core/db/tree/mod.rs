@@ -1,32 +1,132 @@
 use std::{
     collections::{hash_map::RandomState, BTreeMap, HashMap},
     hash::Hash,
+    sync::{Mutex, MutexGuard, PoisonError},
 };
 
+use chrono::{DateTime, Utc};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::core::{
+    clock::{Clock, SystemClock},
     db::{Database, StatusAwareDatabase},
-    payload::{Identifiable, Sortable, Status},
+    payload::{Groupable, Identifiable, Sortable, Status},
 };
 
 type MessageStore<M, S = RandomState> = HashMap<<M as Identifiable>::Id, (u64, M), S>;
 type Tree<M> = BTreeMap<(<M as Sortable>::Sort, u64), <M as Identifiable>::Id>;
+type DelayedTree<M> = BTreeMap<(DateTime<Utc>, u64), <M as Identifiable>::Id>;
+type Groups<M, S = RandomState> = HashMap<Box<str>, BTreeMap<u64, <M as Identifiable>::Id>, S>;
+
+/// Index of messages, that are available for reservation
+///
+/// Delayed messages are kept in separate index, ordered by delay,
+/// and are merged with ready messages by sort key once their delay expires.
+/// Expired delays are moved to ready index once, by the first lookup or mutation, that observes them.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "<M as Identifiable>::Id: Serialize + DeserializeOwned, \
+                 <M as Sortable>::Sort: Serialize + DeserializeOwned")]
+struct ReadyIndex<M>
+where
+    M: Identifiable + Sortable,
+{
+    tree: Tree<M>,
+    delayed: DelayedTree<M>,
+}
+
+impl<M> Default for ReadyIndex<M>
+where
+    M: Identifiable + Sortable,
+{
+    fn default() -> Self {
+        ReadyIndex {
+            tree: BTreeMap::new(),
+            delayed: BTreeMap::new(),
+        }
+    }
+}
+
+impl<M> ReadyIndex<M>
+where
+    M: Identifiable + Sortable,
+    <M as Identifiable>::Id: Hash,
+{
+    fn insert(&mut self, message: &M, id: u64, now: DateTime<Utc>) {
+        match message.delayed_until().filter(|delay| *delay > now) {
+            Some(delay) => self.delayed.insert((delay, id), message.id()),
+            None => self.tree.insert((message.sort(), id), message.id()),
+        };
+    }
+
+    fn remove(&mut self, message: &M, id: u64) {
+        self.tree.remove(&(message.sort(), id));
+
+        if let Some(delay) = message.delayed_until() {
+            self.delayed.remove(&(delay, id));
+        }
+    }
+
+    /// Move messages with expired delay to ready index
+    fn promote(&mut self, objects: &MessageStore<M>, now: DateTime<Utc>) {
+        while let Some((delay, id)) = self.delayed.keys().next().copied() {
+            if delay > now {
+                break;
+            }
+
+            if let Some(key) = self.delayed.remove(&(delay, id)) {
+                let (_, message) = objects.get(&key).unwrap();
+                self.tree.insert((message.sort(), id), key);
+            }
+        }
+    }
+
+    /// Iterate over keys of indexed messages in dispatch order
+    ///
+    /// Ready messages are ordered by sort key, and are followed by messages with pending delay,
+    /// ordered by delay. Expired delays must be promoted beforehand.
+    fn iter(&self) -> impl Iterator<Item = &<M as Identifiable>::Id> {
+        self.tree.values().chain(self.delayed.values())
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.tree.len() + self.delayed.len()
+    }
+
+    fn clear(&mut self) {
+        self.tree.clear();
+        self.delayed.clear();
+    }
+}
 
 /// Tree-based database
 ///
 /// Used by default server implementation.
 ///
-/// Internally, contains message storage, and index for fast lookups,
+/// Internally, contains message storage, and indexes for fast lookups,
 /// thus improving performance in comparison with [VecDatabase]
 ///
+/// Messages are split between two indexes: ready index contains messages, that are available for reservation,
+/// and reserved index contains messages, that are currently in transit.
+/// Reservation and requeue move messages between these indexes, so messages in transit never block queue head.
+///
+/// Delayed messages are kept aside in ready index until their delay expires,
+/// so messages with expired delay are ordered only by their sort key.
+///
+/// Grouped messages are also tracked in group index in push order, and only the first message of each group
+/// is present in ready index. Next message of group becomes ready only after previous one is deleted,
+/// or runs out of tries.
+///
 /// [`TreeDatabase`] heavily relies on correct `M` implementation of Sortable
-/// as only first element of index is used to check if there are any available messages in queue.
+/// as only first message in dispatch order is used to check if there are any available messages in queue.
+///
+/// Time-dependent operations use database clock, which is [`SystemClock`] by default.
+/// Clock is not serialized, so deserialized database always uses default clock of `C`.
 ///
 /// [VecDatabase]: super::VecDatabase
 #[derive(Serialize, Deserialize)]
-#[serde(bound = "M: Serialize + DeserializeOwned")]
-pub struct TreeDatabase<M>
+#[serde(bound = "M: Serialize + DeserializeOwned, C: Default")]
+pub struct TreeDatabase<M, C = SystemClock>
 where
     M: Identifiable + Sortable,
     <M as Identifiable>::Id: Hash,
@@ -34,8 +134,15 @@ where
     last_insert_id: u64,
     #[serde(bound = "<M as Identifiable>::Id: Serialize + DeserializeOwned")]
     objects: MessageStore<M>,
+    #[serde(bound = "<M as Identifiable>::Id: Serialize + DeserializeOwned, \
+                     <M as Sortable>::Sort: Serialize + DeserializeOwned")]
+    ready_tree: Mutex<ReadyIndex<M>>,
     #[serde(bound = "<M as Sortable>::Sort: Serialize + DeserializeOwned")]
-    queue_tree: Tree<M>,
+    reserved_tree: Tree<M>,
+    #[serde(bound = "<M as Identifiable>::Id: Serialize + DeserializeOwned")]
+    groups: Groups<M>,
+    #[serde(skip)]
+    clock: C,
 }
 
 impl<M> Default for TreeDatabase<M>
@@ -44,26 +151,201 @@ where
     <M as Identifiable>::Id: Hash,
 {
     fn default() -> Self {
+        TreeDatabase::with_clock(SystemClock)
+    }
+}
+
+impl<M, C> TreeDatabase<M, C>
+where
+    M: Identifiable + Sortable,
+    <M as Identifiable>::Id: Hash,
+{
+    /// Create empty database, that uses provided clock
+    ///
+    /// ```
+    /// use spartan_lib::core::clock::MockClock;
+    /// use spartan_lib::core::db::TreeDatabase;
+    /// use spartan_lib::core::dispatcher::{SimpleDispatcher, StatusAwareDispatcher};
+    /// use spartan_lib::core::message::{builder::MessageBuilder, Message};
+    /// use spartan_lib::chrono::Duration;
+    ///
+    /// let clock = MockClock::default();
+    /// let mut db = TreeDatabase::<Message, _>::with_clock(clock.clone());
+    ///
+    /// db.push(MessageBuilder::default().body("Hello, world").delay(60).compose_with_clock(&clock).unwrap());
+    ///
+    /// assert!(db.pop().is_none());
+    ///
+    /// clock.advance(Duration::seconds(60));
+    ///
+    /// assert!(db.pop().is_some());
+    /// ```
+    pub fn with_clock(clock: C) -> Self {
         TreeDatabase {
             last_insert_id: 0,
             objects: HashMap::new(),
-            queue_tree: BTreeMap::new(),
+            ready_tree: Mutex::default(),
+            reserved_tree: BTreeMap::new(),
+            groups: HashMap::new(),
+            clock,
         }
     }
 }
 
-impl<M> Database<M> for TreeDatabase<M>
+impl<M, C> TreeDatabase<M, C>
+where
+    M: Identifiable + Sortable + Status + Groupable,
+    <M as Identifiable>::Id: Hash,
+    C: Clock,
+{
+    /// Insert message key into index, that matches message status
+    ///
+    /// Messages without any tries left are not indexed, as they are not reservable anymore
+    fn index(&mut self, message: &M, id: u64) {
+        if !message.reservable() && !message.requeueable() {
+            return;
+        }
+
+        if let Some(group) = message.group() {
+            self.groups
+                .entry(group.into())
+                .or_default()
+                .insert(id, message.id());
+        }
+
+        if message.requeueable() {
+            self.reserved_tree
+                .insert((message.sort(), id), message.id());
+        } else if is_group_head(&self.groups, message, id) {
+            exclusive(&mut self.ready_tree).insert(message, id, self.clock.now());
+        }
+    }
+
+    /// Move messages with expired delay to ready index
+    fn promote(&mut self) {
+        exclusive(&mut self.ready_tree).promote(&self.objects, self.clock.now());
+    }
+
+    /// Lock ready index for lookup, promoting expired delays first
+    fn ready_index(&self) -> MutexGuard<'_, ReadyIndex<M>> {
+        let mut ready_tree = self
+            .ready_tree
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        ready_tree.promote(&self.objects, self.clock.now());
+        ready_tree
+    }
+}
+
+/// Get ready index of exclusively borrowed database without locking
+///
+/// Lookups hold the lock only to promote expired delays and to walk the index,
+/// so panic never leaves it inconsistent, and poisoning is ignored
+fn exclusive<M>(ready_tree: &mut Mutex<ReadyIndex<M>>) -> &mut ReadyIndex<M>
 where
     M: Identifiable + Sortable,
+{
+    ready_tree.get_mut().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Check if message is the first message of its group
+///
+/// Messages without group are always considered as group head
+fn is_group_head<M>(groups: &Groups<M>, message: &M, id: u64) -> bool
+where
+    M: Identifiable + Groupable,
+{
+    let head = message
+        .group()
+        .and_then(|group| groups.get(group))
+        .and_then(|members| members.keys().next());
+
+    match head {
+        Some(head) => *head == id,
+        None => true,
+    }
+}
+
+/// Remove message from its group
+///
+/// If removed message was group head, then next message of group is inserted into ready index
+fn leave_group<M>(
+    objects: &MessageStore<M>,
+    ready_tree: &mut ReadyIndex<M>,
+    groups: &mut Groups<M>,
+    message: &M,
+    id: u64,
+    now: DateTime<Utc>,
+) where
+    M: Identifiable + Sortable + Status + Groupable,
     <M as Identifiable>::Id: Hash,
+{
+    let group = match message.group() {
+        Some(group) => group,
+        None => return,
+    };
+
+    let members = match groups.get_mut(group) {
+        Some(members) => members,
+        None => return,
+    };
+
+    let was_head = members.keys().next() == Some(&id);
+
+    if members.remove(&id).is_none() {
+        return;
+    }
+
+    match members.iter().next() {
+        Some((next_id, next_key)) if was_head => {
+            if let Some((_, next)) = objects.get(next_key).filter(|(_, next)| next.reservable()) {
+                ready_tree.insert(next, *next_id, now);
+            }
+        }
+        Some(_) => (),
+        None => {
+            groups.remove(group);
+        }
+    }
+}
+
+fn unindex<M>(
+    objects: &MessageStore<M>,
+    ready_tree: &mut ReadyIndex<M>,
+    reserved_tree: &mut Tree<M>,
+    groups: &mut Groups<M>,
+    message: &M,
+    id: u64,
+    now: DateTime<Utc>,
+) where
+    M: Identifiable + Sortable + Status + Groupable,
+    <M as Identifiable>::Id: Hash,
+{
+    ready_tree.remove(message, id);
+    reserved_tree.remove(&(message.sort(), id));
+    leave_group(objects, ready_tree, groups, message, id, now);
+}
+
+impl<M, C> Database<M> for TreeDatabase<M, C>
+where
+    M: Identifiable + Sortable + Status + Groupable,
+    <M as Identifiable>::Id: Hash,
+    C: Clock,
 {
     type PositionKey = <M as Identifiable>::Id;
+    type Clock = C;
+
+    fn clock(&self) -> &C {
+        &self.clock
+    }
 
     fn push_raw(&mut self, message: M) {
+        self.promote();
+
         let id = self.last_insert_id;
         self.last_insert_id += 1;
 
-        self.queue_tree.insert((message.sort(), id), message.id());
+        self.index(&message, id);
         self.objects.insert(message.id(), (id, message));
     }
 
@@ -71,12 +353,19 @@ where
     where
         F: Fn(&M) -> bool,
     {
-        self.queue_tree
-            .values()
-            .next()
-            .map(|key| &self.objects.get(key).unwrap().1)
-            .filter(|message| predicate(message))
-            .map(|message| message.id())
+        let key = *self.ready_index().iter().next()?;
+        Some(key).filter(|key| predicate(&self.objects.get(key).unwrap().1))
+    }
+
+    fn positions<F>(&self, predicate: F) -> Vec<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool,
+    {
+        self.objects
+            .iter()
+            .filter(|(_, (_, message))| predicate(message))
+            .map(|(key, _)| *key)
+            .collect()
     }
 
     fn get(&self, position: Self::PositionKey) -> Option<&M> {
@@ -90,8 +379,18 @@ where
     }
 
     fn delete_pos(&mut self, position: Self::PositionKey) -> Option<M> {
+        self.promote();
+
         let (id, message) = self.objects.remove(&position)?;
-        self.queue_tree.remove(&(message.sort(), id));
+        unindex(
+            &self.objects,
+            exclusive(&mut self.ready_tree),
+            &mut self.reserved_tree,
+            &mut self.groups,
+            &message,
+            id,
+            self.clock.now(),
+        );
         Some(message)
     }
 
@@ -99,17 +398,19 @@ where
     where
         F: Fn(&M) -> bool,
     {
-        let tree = &mut self.queue_tree;
-
-        self.objects.retain(|_, (id, message)| {
-            let preserve = predicate(message);
-
-            if !preserve {
-                tree.remove(&(message.sort(), *id));
-            }
+        // Removal of group head may make next message of group ready,
+        // so messages are removed one by one, instead of retaining storage in place
+        self.drain(|message| !predicate(message));
+    }
 
-            preserve
-        });
+    fn drain<F>(&mut self, predicate: F) -> Vec<M>
+    where
+        F: Fn(&M) -> bool,
+    {
+        self.positions(predicate)
+            .into_iter()
+            .filter_map(|key| self.delete_pos(key))
+            .collect()
     }
 
     fn len(&self) -> usize {
@@ -123,61 +424,131 @@ where
     fn clear(&mut self) {
         self.objects.clear();
         self.objects.shrink_to_fit();
-        self.queue_tree.clear();
+        exclusive(&mut self.ready_tree).clear();
+        self.reserved_tree.clear();
+        self.groups.clear();
     }
 }
 
-impl<M> StatusAwareDatabase<M> for TreeDatabase<M>
+impl<M, C> StatusAwareDatabase<M> for TreeDatabase<M, C>
 where
-    M: Identifiable + Sortable + Status,
+    M: Identifiable + Sortable + Status + Groupable,
     <M as Identifiable>::Id: Hash,
+    C: Clock,
 {
     type RequeueKey = <M as Identifiable>::Id;
 
+    fn reservable_position<F>(&self, predicate: F) -> Option<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool,
+    {
+        // Only group heads are present in ready index
+        self.position(predicate)
+    }
+
+    fn reservable_positions<F>(&self, predicate: F, count: usize) -> Vec<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool,
+    {
+        let ready_tree = self.ready_index();
+        let positions = ready_tree
+            .iter()
+            .map(|key| &self.objects.get(key).unwrap().1)
+            .take_while(|message| predicate(message))
+            .take(count)
+            .map(|message| message.id())
+            .collect();
+        positions
+    }
+
     fn reserve(&mut self, position: Self::PositionKey) -> Option<&mut M> {
+        self.promote();
+
         let objects = &mut self.objects;
-        let queue_tree = &mut self.queue_tree;
+        let ready_tree = exclusive(&mut self.ready_tree);
+        let reserved_tree = &mut self.reserved_tree;
 
         objects
             .get_mut(&position)
             .map(|message| {
-                queue_tree.remove(&(message.1.sort(), message.0));
+                ready_tree.remove(&message.1, message.0);
+                reserved_tree.insert((message.1.sort(), message.0), position);
                 message
             })
             .map(|message| &mut message.1)
     }
 
-    fn requeue<F>(&mut self, position: Self::RequeueKey, predicate: F) -> Option<&mut M>
+    fn requeue<F, U>(
+        &mut self,
+        position: Self::RequeueKey,
+        predicate: F,
+        update: U,
+    ) -> Option<&mut M>
     where
         F: Fn(&M) -> bool,
+        U: FnOnce(&mut M),
     {
-        let objects = &mut self.objects;
-        let queue_tree = &mut self.queue_tree;
+        self.promote();
 
-        objects
+        let (id, message) = self
+            .objects
             .get_mut(&position)
-            .filter(|message| predicate(&message.1))
-            .map(|message| {
-                // Check if message can be reserved later
-                // Without this check, requeue of message where tries == max_tries can lead to
-                // broken index, as it will be stuck until GC collects it
-                if message.1.has_tries() {
-                    queue_tree.insert((message.1.sort(), message.0), position);
-                }
+            .filter(|message| predicate(&message.1))?;
+        let id = *id;
 
-                message
-            })
+        self.reserved_tree.remove(&(message.sort(), id));
+
+        // Update may change message sort key, so it must be applied while message is not indexed
+        update(message);
+
+        let message = &self.objects.get(&position)?.1;
+
+        // Check if message can be reserved later
+        // Without this check, requeue of message where tries == max_tries can lead to
+        // broken index, as it will be stuck until GC collects it
+        if message.has_tries() {
+            if is_group_head(&self.groups, message, id) {
+                exclusive(&mut self.ready_tree).insert(message, id, self.clock.now());
+            }
+        } else {
+            // Message without tries left no longer blocks its group
+            leave_group(
+                &self.objects,
+                exclusive(&mut self.ready_tree),
+                &mut self.groups,
+                message,
+                id,
+                self.clock.now(),
+            );
+        }
+
+        self.objects
+            .get_mut(&position)
             .map(|message| &mut message.1)
     }
+
+    fn reserved<F>(&self, predicate: F) -> Vec<Self::RequeueKey>
+    where
+        F: Fn(&M) -> bool,
+    {
+        self.reserved_tree
+            .values()
+            .filter(|key| predicate(&self.objects.get(key).unwrap().1))
+            .copied()
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::Duration;
+
     use super::TreeDatabase;
     use crate::core::{
-        db::Database,
+        clock::{MockClock, SystemClock},
+        db::{Database, StatusAwareDatabase},
         message::{builder::MessageBuilder, Message},
-        payload::{Identifiable, Status},
+        payload::{Dispatchable, Identifiable, Status},
     };
 
     fn create_database() -> TreeDatabase<Message> {
@@ -214,7 +585,7 @@ mod tests {
         let message = create_message!();
         database.push_raw(message);
         assert_eq!(database.objects.len(), 1);
-        assert_eq!(database.queue_tree.len(), 1);
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 1);
     }
 
     #[test]
@@ -242,7 +613,7 @@ mod tests {
         database.push_raw(message);
         let pos = database.position(|_| true).unwrap();
         let message = database.get_mut(pos).unwrap();
-        message.reserve();
+        message.reserve(&SystemClock);
     }
 
     #[test]
@@ -253,11 +624,11 @@ mod tests {
         database.push_raw(message1);
         database.push_raw(message2.clone());
         assert_eq!(database.objects.len(), 2);
-        assert_eq!(database.queue_tree.len(), 2);
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 2);
         let pos = database.position(|_| true).unwrap();
         database.delete_pos(pos).unwrap();
         assert_eq!(database.objects.len(), 1);
-        assert_eq!(database.queue_tree.len(), 1);
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 1);
         let pos = database.position(|_| true).unwrap();
         assert_eq!(database.get(pos).unwrap().id(), message2.id());
     }
@@ -271,11 +642,200 @@ mod tests {
         database.push_raw(message2.clone());
         database.retain(|message| message.id() == message2.id());
         assert_eq!(database.objects.len(), 1);
-        assert_eq!(database.queue_tree.len(), 1);
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 1);
         let pos = database.position(|_| true).unwrap();
         assert_eq!(database.get(pos).unwrap().id(), message2.id());
     }
 
+    #[test]
+    fn test_drain() {
+        let mut database = create_database();
+        let message1 = create_message!();
+        let message2 = create_message!();
+        database.push_raw(message1.clone());
+        database.push_raw(message2.clone());
+        let drained = database.drain(|message| message.id() == message1.id());
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained.first().unwrap().id(), message1.id());
+        assert_eq!(database.objects.len(), 1);
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 1);
+        let pos = database.position(|_| true).unwrap();
+        assert_eq!(database.get(pos).unwrap().id(), message2.id());
+    }
+
+    #[test]
+    fn test_reserve_requeue_index() {
+        let mut database = create_database();
+        let message = MessageBuilder::default()
+            .body("Hello world")
+            .max_tries(2)
+            .compose()
+            .unwrap();
+        database.push_raw(message.clone());
+        database
+            .reserve(message.id())
+            .unwrap()
+            .reserve(&SystemClock);
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 0);
+        assert_eq!(database.reserved_tree.len(), 1);
+        database
+            .requeue(message.id(), |_| true, |message| message.requeue())
+            .unwrap();
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 1);
+        assert_eq!(database.reserved_tree.len(), 0);
+        database.delete_pos(message.id()).unwrap();
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 0);
+        assert_eq!(database.reserved_tree.len(), 0);
+    }
+
+    #[test]
+    fn test_requeue_resort() {
+        let mut database = create_database();
+        let message1 = MessageBuilder::default()
+            .body("Hello world")
+            .max_tries(2)
+            .compose()
+            .unwrap();
+        let message2 = create_message!();
+        database.push_raw(message1.clone());
+        database.push_raw(message2.clone());
+        database
+            .reserve(message1.id())
+            .unwrap()
+            .reserve(&SystemClock);
+        database
+            .requeue(
+                message1.id(),
+                |_| true,
+                |message| {
+                    message.requeue();
+                    message.delay(10, &SystemClock);
+                },
+            )
+            .unwrap();
+        position!(database, message2);
+        assert!(database
+            .position(|message| message.obtainable(&SystemClock))
+            .is_none());
+        position!(database, message1);
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_expired_delay_priority() {
+        let clock = MockClock::default();
+        let mut database = TreeDatabase::<Message, _>::with_clock(clock.clone());
+        let delayed = MessageBuilder::default()
+            .body("Hello world")
+            .priority(10)
+            .delay(10)
+            .compose_with_clock(&clock)
+            .unwrap();
+        let message = create_message!();
+        database.push_raw(delayed.clone());
+        database.push_raw(message.clone());
+        assert_eq!(database.position(|_| true), Some(message.id()));
+        assert_eq!(database.ready_tree.lock().unwrap().delayed.len(), 1);
+        clock.advance(Duration::seconds(10));
+        assert_eq!(database.position(|_| true), Some(delayed.id()));
+        assert_eq!(
+            database.reservable_positions(|_| true, 2),
+            vec![delayed.id(), message.id()]
+        );
+        database.push_raw(create_message!());
+        assert_eq!(database.ready_tree.lock().unwrap().delayed.len(), 0);
+        position!(database, delayed);
+        position!(database, message);
+    }
+
+    #[test]
+    fn test_requeue_expired_delay() {
+        let clock = MockClock::default();
+        let mut database = TreeDatabase::<Message, _>::with_clock(clock.clone());
+        let message1 = MessageBuilder::default()
+            .body("Hello world")
+            .max_tries(3)
+            .compose_with_clock(&clock)
+            .unwrap();
+        let message2 = create_message!();
+        database.push_raw(message1.clone());
+        database.push_raw(message2.clone());
+        database.reserve(message1.id()).unwrap().reserve(&clock);
+        database
+            .requeue(
+                message1.id(),
+                |_| true,
+                |message| {
+                    message.requeue();
+                    message.delay(5, &clock);
+                },
+            )
+            .unwrap();
+        clock.advance(Duration::seconds(5));
+        database.reserve(message1.id()).unwrap().reserve(&clock);
+        // Requeue with zero delay clears expired delay
+        database
+            .requeue(
+                message1.id(),
+                |_| true,
+                |message| {
+                    message.requeue();
+                    message.delay(0, &clock);
+                },
+            )
+            .unwrap();
+        assert!(database
+            .get(message1.id())
+            .unwrap()
+            .time()
+            .delay()
+            .is_none());
+        assert!(database.ready_tree.lock().unwrap().delayed.is_empty());
+        position!(database, message1);
+        position!(database, message2);
+    }
+
+    #[test]
+    fn test_push_indexes_by_status() {
+        let mut database = create_database();
+        let mut reserved = create_message!();
+        reserved.reserve(&SystemClock);
+        let exhausted = MessageBuilder::default()
+            .body("Hello world")
+            .max_tries(0)
+            .compose()
+            .unwrap();
+        database.push_raw(reserved);
+        database.push_raw(exhausted);
+        database.push_raw(create_message!());
+        assert_eq!(database.objects.len(), 3);
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 1);
+        assert_eq!(database.reserved_tree.len(), 1);
+    }
+
+    #[test]
+    fn test_group_index() {
+        let mut database = create_database();
+        let message1 = MessageBuilder::default()
+            .body("Hello world")
+            .group("customer")
+            .compose()
+            .unwrap();
+        let message2 = MessageBuilder::default()
+            .body("Hello world")
+            .group("customer")
+            .compose()
+            .unwrap();
+        database.push_raw(message1.clone());
+        database.push_raw(message2.clone());
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 1);
+        assert_eq!(database.groups["customer"].len(), 2);
+        database.delete_pos(message1.id()).unwrap();
+        assert_eq!(database.ready_tree.lock().unwrap().len(), 1);
+        position!(database, message2);
+        assert!(database.groups.is_empty());
+    }
+
     #[test]
     fn test_len_clear() {
         let mut database = create_database();
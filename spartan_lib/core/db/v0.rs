@@ -0,0 +1,80 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::core::{
+    db::{Database, TreeDatabase as CurrentTreeDatabase},
+    message::{v0::Message, Message as CurrentMessage},
+};
+
+/// Tree-based database with single index, which is sorted by message delay
+#[derive(Deserialize)]
+pub struct TreeDatabase {
+    pub last_insert_id: u64,
+    pub objects: HashMap<Uuid, (u64, Message)>,
+    pub queue_tree: BTreeMap<(Option<i64>, u64), Uuid>,
+}
+
+impl From<TreeDatabase> for CurrentTreeDatabase<CurrentMessage> {
+    /// Messages are pushed in their insert order, so indexes are rebuilt for current message ordering.
+    fn from(database: TreeDatabase) -> Self {
+        let mut objects = database.objects.into_iter().collect::<Vec<_>>();
+        objects.sort_unstable_by_key(|(_, (id, _))| *id);
+
+        let mut converted = CurrentTreeDatabase::default();
+
+        for (_, (_, message)) in objects {
+            converted.push_raw(message.into());
+        }
+
+        converted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::deserialize;
+
+    use super::TreeDatabase;
+    use crate::core::{
+        db::{Database, TreeDatabase as CurrentTreeDatabase},
+        dispatcher::{SimpleDispatcher, StatusAwareDispatcher},
+        message::Message,
+        payload::{Dispatchable, Identifiable, Status},
+    };
+
+    /// Database with available "Hello" message and reserved "Reserved" message,
+    /// serialized by Spartan before persistence format versioning
+    const V0_TREE_DATABASE: &[u8] = include_bytes!("fixtures/v0_tree_database");
+
+    #[test]
+    fn test_convert_tree_database() {
+        let database: TreeDatabase = deserialize(V0_TREE_DATABASE).unwrap();
+        let mut database = CurrentTreeDatabase::<Message>::from(database);
+
+        let reserved = database
+            .positions(|message| message.body() == b"Reserved")
+            .pop()
+            .unwrap();
+        let reserved = database.get(reserved).unwrap();
+
+        assert!(reserved.requeueable());
+        assert_eq!(*reserved.state().tries(), 1);
+        assert!(reserved.state().receipt().is_none());
+
+        let hello = database.pop().unwrap();
+
+        assert_eq!(
+            hello.id().to_string(),
+            "52735699-7d35-40e7-828d-7b2ca06a16ff"
+        );
+        assert_eq!(hello.body(), b"Hello");
+        assert_eq!(hello.content_type(), "text/plain");
+        assert_eq!(*hello.state().max_tries(), 2);
+        assert_eq!(*hello.time().timeout().max(), 60);
+
+        assert!(database.pop().is_none());
+        assert_eq!(database.size(), 2);
+    }
+}
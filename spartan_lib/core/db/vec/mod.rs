@@ -1,8 +1,11 @@
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
 
 use crate::core::{
+    clock::{Clock, SystemClock},
     db::{Database, StatusAwareDatabase},
-    payload::Identifiable,
+    payload::{Groupable, Identifiable, Status},
 };
 
 /// [`Vec`]-based database
@@ -14,18 +17,42 @@ use crate::core::{
 ///
 /// [TreeDatabase]: super::TreeDatabase
 #[derive(Serialize, Deserialize, Debug)]
-pub struct VecDatabase<M> {
+#[serde(bound(
+    serialize = "M: Serialize",
+    deserialize = "M: Deserialize<'de>, C: Default"
+))]
+pub struct VecDatabase<M, C = SystemClock> {
     db: Vec<M>,
+    #[serde(skip)]
+    clock: C,
 }
 
 impl<M> Default for VecDatabase<M> {
     fn default() -> Self {
-        VecDatabase { db: Vec::new() }
+        VecDatabase::with_clock(SystemClock)
+    }
+}
+
+impl<M, C> VecDatabase<M, C> {
+    /// Create empty database, that uses provided clock
+    pub fn with_clock(clock: C) -> Self {
+        VecDatabase {
+            db: Vec::new(),
+            clock,
+        }
     }
 }
 
-impl<M> Database<M> for VecDatabase<M> {
+impl<M, C> Database<M> for VecDatabase<M, C>
+where
+    C: Clock,
+{
     type PositionKey = usize;
+    type Clock = C;
+
+    fn clock(&self) -> &C {
+        &self.clock
+    }
 
     fn push_raw(&mut self, message: M) {
         self.db.push(message);
@@ -38,6 +65,18 @@ impl<M> Database<M> for VecDatabase<M> {
         self.db.iter().position(predicate)
     }
 
+    fn positions<F>(&self, predicate: F) -> Vec<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool,
+    {
+        self.db
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| predicate(message))
+            .map(|(position, _)| position)
+            .collect()
+    }
+
     fn get(&self, position: Self::PositionKey) -> Option<&M> {
         self.db.get(position)
     }
@@ -61,6 +100,15 @@ impl<M> Database<M> for VecDatabase<M> {
         self.db.retain(predicate);
     }
 
+    fn drain<F>(&mut self, predicate: F) -> Vec<M>
+    where
+        F: Fn(&M) -> bool,
+    {
+        let (drained, retained) = self.db.drain(..).partition(|message| predicate(message));
+        self.db = retained;
+        drained
+    }
+
     fn len(&self) -> usize {
         self.db.len()
     }
@@ -75,28 +123,81 @@ impl<M> Database<M> for VecDatabase<M> {
     }
 }
 
-impl<M> StatusAwareDatabase<M> for VecDatabase<M>
+impl<M, C> StatusAwareDatabase<M> for VecDatabase<M, C>
 where
-    M: Identifiable,
+    M: Identifiable + Status + Groupable,
+    C: Clock,
 {
     type RequeueKey = <M as Identifiable>::Id;
 
+    fn reservable_position<F>(&self, predicate: F) -> Option<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool,
+    {
+        self.reservable_positions(predicate, 1).pop()
+    }
+
+    fn reservable_positions<F>(&self, predicate: F, count: usize) -> Vec<Self::PositionKey>
+    where
+        F: Fn(&M) -> bool,
+    {
+        let mut blocked = HashSet::new();
+
+        self.db
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| {
+                // Messages without tries left don't block their group
+                let group = message
+                    .group()
+                    .filter(|_| message.reservable() || message.requeueable());
+
+                // First message of each group blocks the rest of group
+                if matches!(group, Some(group) if !blocked.insert(group)) {
+                    return false;
+                }
+
+                predicate(message)
+            })
+            .map(|(position, _)| position)
+            .take(count)
+            .collect()
+    }
+
     fn reserve(&mut self, position: Self::PositionKey) -> Option<&mut M> {
         self.db.get_mut(position)
     }
 
-    fn requeue<F>(&mut self, position: Self::RequeueKey, predicate: F) -> Option<&mut M>
+    fn requeue<F, U>(
+        &mut self,
+        position: Self::RequeueKey,
+        predicate: F,
+        update: U,
+    ) -> Option<&mut M>
     where
         F: Fn(&M) -> bool,
+        U: FnOnce(&mut M),
     {
         let message = self.reserve(self.position(|message| message.id() == position)?)?;
 
         if predicate(message) {
+            update(message);
             Some(message)
         } else {
             None
         }
     }
+
+    fn reserved<F>(&self, predicate: F) -> Vec<Self::RequeueKey>
+    where
+        F: Fn(&M) -> bool,
+    {
+        self.db
+            .iter()
+            .filter(|message| predicate(message))
+            .map(Identifiable::id)
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -171,6 +272,19 @@ mod tests {
         assert_eq!(db.len(), 1);
     }
 
+    #[test]
+    fn test_drain() {
+        let mut db = create_database();
+        let message = create_message();
+        let message2 = create_message();
+        db.push_raw(message.clone());
+        db.push_raw(message2);
+        let drained = db.drain(|msg| msg.id() == message.id());
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained.first().unwrap().id(), message.id());
+        assert_eq!(db.len(), 1);
+    }
+
     #[test]
     fn test_clear() {
         let mut db = create_database();
@@ -9,9 +9,11 @@ mod tests {
     #[macro_export]
     macro_rules! test_dispatcher {
         ($db:tt) => {
+            use chrono::Duration;
             use uuid::Uuid;
 
             use crate::core::{
+                clock::{MockClock, SystemClock},
                 dispatcher::SimpleDispatcher,
                 message::{builder::MessageBuilder, Message},
                 payload::{Identifiable, Status},
@@ -29,6 +31,11 @@ mod tests {
                 $db::<Message>::default()
             }
 
+            fn create_mock_database() -> (MockClock, $db<Message, MockClock>) {
+                let clock = MockClock::default();
+                (clock.clone(), $db::<Message, MockClock>::with_clock(clock))
+            }
+
             #[test]
             fn push_message() {
                 let message = generate_test_message();
@@ -86,6 +93,45 @@ mod tests {
                 assert_eq!(db.size(), 2);
                 assert_eq!(db.peek().unwrap().id(), message.id());
             }
+
+            #[test]
+            fn gc_expired_message() {
+                let (clock, mut db) = create_mock_database();
+                let mut message = MessageBuilder::default()
+                    .body("Hello, world")
+                    .max_tries(3)
+                    .timeout(30)
+                    .compose_with_clock(&clock)
+                    .unwrap();
+                message.reserve(&clock);
+                db.push(message);
+
+                clock.advance(Duration::seconds(30));
+                db.gc();
+                assert_eq!(db.size(), 1);
+
+                clock.advance(Duration::seconds(1));
+                db.gc();
+                assert_eq!(db.size(), 0);
+            }
+
+            #[test]
+            fn drain_gc() {
+                let message = generate_test_message();
+                let useless_message = MessageBuilder::default()
+                    .body("Hello, world")
+                    .max_tries(0)
+                    .compose()
+                    .unwrap();
+                let mut db = create_database();
+                db.push(message.clone());
+                db.push(useless_message.clone());
+                let collected = db.drain_gc();
+                assert_eq!(collected.len(), 1);
+                assert_eq!(collected.first().unwrap().id(), useless_message.id());
+                assert_eq!(db.size(), 1);
+                assert_eq!(db.peek().unwrap().id(), message.id());
+            }
         };
     }
 
@@ -132,6 +178,26 @@ mod tests {
                 assert_eq!(db.pop().is_some(), false);
             }
 
+            #[test]
+            fn delayed_message_becomes_available() {
+                let (clock, mut db) = create_mock_database();
+                let message = MessageBuilder::default()
+                    .body("Hello, world")
+                    .delay(900)
+                    .compose_with_clock(&clock)
+                    .unwrap();
+
+                db.push(message.clone());
+
+                clock.advance(Duration::seconds(899));
+                assert!(db.peek().is_none());
+                assert!(db.pop().is_none());
+
+                clock.advance(Duration::seconds(1));
+                assert_eq!(db.peek().unwrap().id(), message.id());
+                assert_eq!(db.pop().unwrap().id(), message.id());
+            }
+
             #[test]
             fn delayed_and_ready_message() {
                 let message = generate_test_message();
@@ -150,6 +216,187 @@ mod tests {
                 assert_eq!(db.pop().unwrap().id(), delayed_message.id());
             }
 
+            #[test]
+            fn unavailable_message_at_head() {
+                let mut reserved_message = generate_test_message();
+                reserved_message.reserve(&SystemClock);
+                let useless_message = MessageBuilder::default()
+                    .body("Hello, world")
+                    .max_tries(0)
+                    .compose()
+                    .unwrap();
+                let message = generate_test_message();
+                let mut db = create_database();
+
+                db.push(reserved_message);
+                db.push(useless_message);
+                db.push(message.clone());
+
+                assert_eq!(db.pop().unwrap().id(), message.id());
+                assert!(db.pop().is_none());
+            }
+
+            #[test]
+            fn pop_after_reserved_head() {
+                let message1 = generate_test_message();
+                let message2 = generate_test_message();
+                let mut db = create_database();
+
+                db.push(message1.clone());
+                db.push(message2.clone());
+
+                assert_eq!(db.pop().unwrap().id(), message1.id());
+                assert_eq!(db.pop().unwrap().id(), message2.id());
+
+                db.requeue(message1.id()).unwrap();
+
+                assert_eq!(db.pop().unwrap().id(), message1.id());
+            }
+
+            #[test]
+            fn requeue_expired_message() {
+                let (clock, mut db) = create_mock_database();
+                let message = MessageBuilder::default()
+                    .body("Hello, world")
+                    .max_tries(2)
+                    .timeout(30)
+                    .compose_with_clock(&clock)
+                    .unwrap();
+
+                db.push(message.clone());
+
+                assert_eq!(db.pop().unwrap().id(), message.id());
+                clock.advance(Duration::seconds(30));
+                assert_eq!(db.requeue_expired(), 0);
+                clock.advance(Duration::seconds(1));
+                assert_eq!(db.requeue_expired(), 1);
+
+                assert_eq!(db.pop().unwrap().id(), message.id());
+                clock.advance(Duration::seconds(31));
+                assert_eq!(db.requeue_expired(), 1);
+
+                assert!(db.pop().is_none());
+                db.gc();
+                assert_eq!(db.size(), 0);
+            }
+
+            #[test]
+            fn requeue_delayed_message() {
+                let message1 = generate_test_message();
+                let message2 = generate_test_message();
+                let mut db = create_database();
+
+                db.push(message1.clone());
+                db.push(message2.clone());
+
+                assert_eq!(db.pop().unwrap().id(), message1.id());
+                db.requeue_delayed(message1.id(), 900).unwrap();
+
+                assert_eq!(db.pop().unwrap().id(), message2.id());
+                assert!(db.pop().is_none());
+            }
+
+            #[test]
+            fn requeue_delayed_message_becomes_available() {
+                let (clock, mut db) = create_mock_database();
+                let message = MessageBuilder::default()
+                    .body("Hello, world")
+                    .max_tries(2)
+                    .compose_with_clock(&clock)
+                    .unwrap();
+
+                db.push(message.clone());
+
+                assert_eq!(db.pop().unwrap().id(), message.id());
+                db.requeue_delayed(message.id(), 900).unwrap();
+
+                clock.advance(Duration::seconds(899));
+                assert!(db.pop().is_none());
+
+                clock.advance(Duration::seconds(1));
+                assert_eq!(db.pop().unwrap().id(), message.id());
+            }
+
+            #[test]
+            fn group_message_order() {
+                let grouped = |body: &str, group: &str| {
+                    MessageBuilder::default()
+                        .body(body)
+                        .group(group)
+                        .max_tries(2)
+                        .compose()
+                        .unwrap()
+                };
+
+                let first = grouped("first", "customer");
+                let second = grouped("second", "customer");
+                let other = grouped("other", "other_customer");
+                let mut db = create_database();
+
+                db.push(first.clone());
+                db.push(second.clone());
+                db.push(other.clone());
+
+                assert_eq!(db.pop().unwrap().id(), first.id());
+                assert_eq!(db.pop().unwrap().id(), other.id());
+                assert!(db.pop().is_none());
+
+                db.requeue(first.id()).unwrap();
+
+                assert_eq!(db.pop().unwrap().id(), first.id());
+                assert!(db.pop().is_none());
+
+                db.delete(first.id()).unwrap();
+
+                assert_eq!(db.pop().unwrap().id(), second.id());
+            }
+
+            #[test]
+            fn group_message_priority() {
+                let first = MessageBuilder::default()
+                    .body("first")
+                    .group("customer")
+                    .delay(900)
+                    .compose()
+                    .unwrap();
+                let second = MessageBuilder::default()
+                    .body("second")
+                    .group("customer")
+                    .priority(10)
+                    .compose()
+                    .unwrap();
+                let mut db = create_database();
+
+                db.push(first);
+                db.push(second);
+
+                // Group head is delayed, so the rest of group is blocked too
+                assert!(db.pop().is_none());
+            }
+
+            #[test]
+            fn group_exhausted_head() {
+                let first = MessageBuilder::default()
+                    .body("first")
+                    .group("customer")
+                    .compose()
+                    .unwrap();
+                let second = MessageBuilder::default()
+                    .body("second")
+                    .group("customer")
+                    .compose()
+                    .unwrap();
+                let mut db = create_database();
+
+                db.push(first.clone());
+                db.push(second.clone());
+
+                assert_eq!(db.pop().unwrap().id(), first.id());
+                db.requeue(first.id()).unwrap();
+
+                assert_eq!(db.pop().unwrap().id(), second.id());
+            }
+
             #[test]
             fn push_pop_requeue_push() {
                 let mut db = create_database();
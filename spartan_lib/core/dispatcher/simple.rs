@@ -1,4 +1,5 @@
 use crate::core::{
+    clock::Clock,
     db::Database,
     payload::{Dispatchable, Identifiable},
 };
@@ -42,26 +43,27 @@ where
     /// Start GC cycle on queue
     ///
     /// ```
+    /// use spartan_lib::chrono::Duration;
+    /// use spartan_lib::core::clock::MockClock;
     /// use spartan_lib::core::dispatcher::SimpleDispatcher;
     /// use spartan_lib::core::db::VecDatabase;
     /// use spartan_lib::core::message::builder::MessageBuilder;
     /// use spartan_lib::core::payload::{Identifiable, Status};
-    /// use std::thread::sleep;
-    /// use std::time::Duration;
     ///
-    /// let mut db = VecDatabase::default();
+    /// let clock = MockClock::default();
+    /// let mut db = VecDatabase::with_clock(clock.clone());
     ///
-    /// // We need two test messages here: one without timeout, and one with 1 second timeout
+    /// // We need two test messages here: one with default timeout, and one with 1 second timeout
     /// // GC condition varies between messages, but here we'll use timeout as an example
-    /// let message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    /// let message = MessageBuilder::default().body("Hello, world").compose_with_clock(&clock).unwrap();
     ///
-    /// // Setting timeout to 0 and waiting 1-2 seconds turns message into garbage
-    /// let mut garbage_message = MessageBuilder::default().body("I will be deleted").timeout(0).compose().unwrap();
+    /// // Setting timeout to 1 and advancing clock by 2 seconds turns message into garbage
+    /// let mut garbage_message = MessageBuilder::default().body("I will be deleted").timeout(1).compose_with_clock(&clock).unwrap();
     ///
     /// // We are going to reserve the message before adding it do database
-    /// garbage_message.reserve();
+    /// garbage_message.reserve(&clock);
     ///
-    /// sleep(Duration::from_secs(2));
+    /// clock.advance(Duration::seconds(2));
     ///
     /// db.push(message.clone());
     /// db.push(garbage_message);
@@ -75,6 +77,35 @@ where
     /// ```
     fn gc(&mut self);
 
+    /// Start GC cycle on queue, returning collected messages instead of dropping them
+    ///
+    /// ```
+    /// use spartan_lib::core::dispatcher::SimpleDispatcher;
+    /// use spartan_lib::core::clock::SystemClock;
+    /// use spartan_lib::core::db::VecDatabase;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::{Identifiable, Status};
+    ///
+    /// let mut db = VecDatabase::default();
+    ///
+    /// let message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    ///
+    /// // Message with max tries reached is garbage
+    /// let mut garbage_message = MessageBuilder::default().body("I will be collected").compose().unwrap();
+    /// garbage_message.reserve(&SystemClock);
+    /// garbage_message.requeue();
+    ///
+    /// db.push(message.clone());
+    /// db.push(garbage_message.clone());
+    ///
+    /// let collected = db.drain_gc();
+    ///
+    /// assert_eq!(collected.first().unwrap().id(), garbage_message.id());
+    /// assert_eq!(db.size(), 1);
+    /// assert_eq!(db.peek().unwrap().id(), message.id());
+    /// ```
+    fn drain_gc(&mut self) -> Vec<M>;
+
     /// Get queue size
     ///
     /// ```
@@ -186,11 +217,18 @@ where
     }
 
     fn peek(&self) -> Option<&M> {
-        self.get(self.position(|msg| msg.obtainable())?)
+        let now = self.clock().now();
+        self.get(self.position(|msg| msg.obtainable(&now))?)
     }
 
     fn gc(&mut self) {
-        self.retain(|msg| !msg.gc());
+        let now = self.clock().now();
+        self.retain(|msg| !msg.gc(&now));
+    }
+
+    fn drain_gc(&mut self) -> Vec<M> {
+        let now = self.clock().now();
+        self.drain(|msg| msg.gc(&now))
     }
 
     fn size(&self) -> usize {
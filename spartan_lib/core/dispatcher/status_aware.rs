@@ -1,4 +1,5 @@
 use crate::core::{
+    clock::Clock,
     db::StatusAwareDatabase,
     dispatcher::simple::SimpleDispatcher,
     payload::{Identifiable, Status},
@@ -13,6 +14,9 @@ where
     ///
     /// Behaves like "peek", but with "obtainable" message check, message and database reservation
     ///
+    /// Messages of the same group are popped one at a time, in push order:
+    /// next message of group is not available, until previous one is deleted or runs out of tries
+    ///
     /// ```
     /// use spartan_lib::core::dispatcher::{SimpleDispatcher, StatusAwareDispatcher};
     /// use spartan_lib::core::db::TreeDatabase;
@@ -47,6 +51,49 @@ where
     /// db.requeue(id).unwrap();
     /// ```
     fn requeue(&mut self, id: <M as Identifiable>::Id) -> Option<()>;
+
+    /// Requeue message in queue, delaying its dispatch for provided amount of seconds
+    ///
+    /// Returns None, if message was not found, or message cannot be requeued
+    ///
+    /// ```
+    /// use spartan_lib::core::dispatcher::{SimpleDispatcher, StatusAwareDispatcher};
+    /// use spartan_lib::core::db::TreeDatabase;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::Identifiable;
+    ///
+    /// let mut db = TreeDatabase::default();
+    /// let message = MessageBuilder::default().body("Hello, world").max_tries(2).compose().unwrap();
+    ///
+    /// db.push(message);
+    ///
+    /// let id = db.pop().unwrap().id();
+    /// db.requeue_delayed(id, 60).unwrap();
+    ///
+    /// assert!(db.pop().is_none());
+    /// ```
+    fn requeue_delayed(&mut self, id: <M as Identifiable>::Id, delay: u32) -> Option<()>;
+
+    /// Requeue all messages, which reservation has expired
+    ///
+    /// Messages without tries left are requeued too, so GC will be able to collect them
+    ///
+    /// Returns amount of requeued messages
+    ///
+    /// ```
+    /// use spartan_lib::core::dispatcher::{SimpleDispatcher, StatusAwareDispatcher};
+    /// use spartan_lib::core::db::TreeDatabase;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    ///
+    /// let mut db = TreeDatabase::default();
+    /// let message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    ///
+    /// db.push(message);
+    /// db.pop().unwrap();
+    ///
+    /// assert_eq!(db.requeue_expired(), 0);
+    /// ```
+    fn requeue_expired(&mut self) -> usize;
 }
 
 impl<T, M> StatusAwareDispatcher<M> for T
@@ -55,15 +102,51 @@ where
     M: Status,
 {
     fn pop(&mut self) -> Option<&M> {
-        let position = self.position(|msg| msg.reservable() && msg.obtainable())?;
+        let now = self.clock().now();
+        let position = self.reservable_position(|msg| msg.reservable() && msg.obtainable(&now))?;
         let message = self.reserve(position).unwrap();
-        message.reserve();
+        message.reserve(&now);
         Some(message)
     }
 
     fn requeue(&mut self, key: <M as Identifiable>::Id) -> Option<()> {
-        let message = self.requeue(key, |msg| msg.requeueable() && msg.obtainable())?;
-        message.requeue();
+        let now = self.clock().now();
+        StatusAwareDatabase::requeue(
+            self,
+            key,
+            |msg| msg.requeueable() && msg.obtainable(&now),
+            |msg| msg.requeue(),
+        )?;
+        Some(())
+    }
+
+    fn requeue_delayed(&mut self, key: <M as Identifiable>::Id, delay: u32) -> Option<()> {
+        let now = self.clock().now();
+        StatusAwareDatabase::requeue(
+            self,
+            key,
+            |msg| msg.requeueable() && msg.obtainable(&now),
+            |msg| {
+                msg.requeue();
+                msg.delay(delay, &now);
+            },
+        )?;
         Some(())
     }
+
+    fn requeue_expired(&mut self) -> usize {
+        let now = self.clock().now();
+        let keys = self.reserved(|msg| msg.requeueable() && msg.expired(&now));
+        let mut requeued = 0;
+
+        for key in keys {
+            if StatusAwareDatabase::requeue(self, key, |msg| msg.requeueable(), |msg| msg.requeue())
+                .is_some()
+            {
+                requeued += 1;
+            }
+        }
+
+        requeued
+    }
 }
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Message attributes map
+pub type Attributes = BTreeMap<Box<str>, Attribute>;
+
+/// Typed message attribute value
+///
+/// Human-readable formats (like JSON) represent attributes as plain values,
+/// while binary formats (like bincode) keep variant tag to make deserialization possible.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(remote = "Self")]
+pub enum Attribute {
+    String(Box<str>),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl Attribute {
+    /// Get attribute value size in bytes
+    ///
+    /// Only string attributes have variable size
+    pub fn size(&self) -> usize {
+        match self {
+            Attribute::String(value) => value.len(),
+            Attribute::Integer(_) | Attribute::Float(_) => 8,
+            Attribute::Boolean(_) => 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum UntaggedAttribute {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(Box<str>),
+}
+
+impl Serialize for Attribute {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            match self {
+                Attribute::String(value) => serializer.serialize_str(value),
+                Attribute::Integer(value) => serializer.serialize_i64(*value),
+                Attribute::Float(value) => serializer.serialize_f64(*value),
+                Attribute::Boolean(value) => serializer.serialize_bool(*value),
+            }
+        } else {
+            Attribute::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Attribute {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            Ok(match UntaggedAttribute::deserialize(deserializer)? {
+                UntaggedAttribute::Integer(value) => Attribute::Integer(value),
+                UntaggedAttribute::Float(value) => Attribute::Float(value),
+                UntaggedAttribute::Boolean(value) => Attribute::Boolean(value),
+                UntaggedAttribute::String(value) => Attribute::String(value),
+            })
+        } else {
+            Attribute::deserialize(deserializer)
+        }
+    }
+}
+
+impl From<&str> for Attribute {
+    fn from(value: &str) -> Self {
+        Attribute::String(value.into())
+    }
+}
+
+impl From<String> for Attribute {
+    fn from(value: String) -> Self {
+        Attribute::String(value.into_boxed_str())
+    }
+}
+
+impl From<i64> for Attribute {
+    fn from(value: i64) -> Self {
+        Attribute::Integer(value)
+    }
+}
+
+impl From<f64> for Attribute {
+    fn from(value: f64) -> Self {
+        Attribute::Float(value)
+    }
+}
+
+impl From<bool> for Attribute {
+    fn from(value: bool) -> Self {
+        Attribute::Boolean(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attribute, Attributes};
+
+    fn attributes() -> Attributes {
+        let mut attributes = Attributes::new();
+        attributes.insert("trace".into(), "abc".into());
+        attributes.insert("retries".into(), 3.into());
+        attributes.insert("ratio".into(), 0.5.into());
+        attributes.insert("urgent".into(), true.into());
+        attributes
+    }
+
+    #[test]
+    fn test_json() {
+        let json = serde_json::to_string(&attributes()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"ratio":0.5,"retries":3,"trace":"abc","urgent":true}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Attributes>(&json).unwrap(),
+            attributes()
+        );
+    }
+
+    #[test]
+    fn test_bincode() {
+        let encoded = bincode::serialize(&attributes()).unwrap();
+        assert_eq!(
+            bincode::deserialize::<Attributes>(&encoded).unwrap(),
+            attributes()
+        );
+    }
+
+    #[test]
+    fn test_size() {
+        assert_eq!(Attribute::from("Hello").size(), 5);
+        assert_eq!(Attribute::from(true).size(), 1);
+    }
+}
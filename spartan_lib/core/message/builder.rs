@@ -1,13 +1,73 @@
+use chrono::{DateTime, FixedOffset};
 use thiserror::Error;
 
-use crate::core::message::{time::Offset, Message};
+use crate::core::{
+    clock::{Clock, SystemClock},
+    message::{
+        state::State,
+        time::{Delay, Offset, Time, MAX_SCHEDULE_DAYS},
+        Attribute, Attributes, Message, Timezone,
+    },
+};
+
+/// Content type of messages, that were composed without explicit content type
+pub const DEFAULT_CONTENT_TYPE: &str = "text/plain";
+
+/// Max content type size in bytes
+pub const MAX_CONTENT_TYPE_SIZE: usize = 256;
+
+/// Max deduplication ID size in bytes
+pub const MAX_DEDUP_ID_SIZE: usize = 128;
+
+/// Max message group size in bytes
+pub const MAX_GROUP_SIZE: usize = 128;
+
+/// Max amount of attributes per message
+pub const MAX_ATTRIBUTES: usize = 16;
+
+/// Max attribute key size in bytes
+pub const MAX_ATTRIBUTE_KEY_SIZE: usize = 256;
+
+/// Max attribute value size in bytes
+pub const MAX_ATTRIBUTE_VALUE_SIZE: usize = 4096;
 
 #[derive(Debug, Error)]
 pub enum BuilderError {
     #[error("No body provided for builder")]
     BodyNotProvided,
+    #[error(
+        "Content type must be printable ASCII in range of 1 and {} bytes",
+        MAX_CONTENT_TYPE_SIZE
+    )]
+    InvalidContentType,
+    #[error(
+        "Deduplication ID size must be in range of 1 and {} bytes",
+        MAX_DEDUP_ID_SIZE
+    )]
+    DedupIdOutOfBounds,
+    #[error("Group size must be in range of 1 and {} bytes", MAX_GROUP_SIZE)]
+    GroupOutOfBounds,
     #[error("Offset must be in range of -86399 and 86399 seconds")]
     OffsetOutOfBounds,
+    #[error("Unknown timezone")]
+    UnknownTimezone,
+    #[error(
+        "Delivery time can't be in the past, or more than {} days ahead",
+        MAX_SCHEDULE_DAYS
+    )]
+    DeliverAtOutOfBounds,
+    #[error("Message can't have more than {} attributes", MAX_ATTRIBUTES)]
+    TooManyAttributes,
+    #[error(
+        "Attribute key size must be in range of 1 and {} bytes",
+        MAX_ATTRIBUTE_KEY_SIZE
+    )]
+    AttributeKeyOutOfBounds,
+    #[error(
+        "Attribute value size can't be larger than {} bytes",
+        MAX_ATTRIBUTE_VALUE_SIZE
+    )]
+    AttributeValueTooLarge,
 }
 
 /// Message builder
@@ -18,44 +78,91 @@ pub enum BuilderError {
 ///
 /// let message = MessageBuilder::default()
 ///     .body("Hello, world")
+///     .content_type("text/plain")
+///     .dedup_id("order-42")
+///     .group("customer-1")
 ///     .offset(9 * 3600)
 ///     .max_tries(5)
 ///     .timeout(60)
 ///     .delay(10)
+///     .priority(1)
+///     .attribute("trace_id", "abc")
 ///     .compose()
 ///     .unwrap();
 /// ```
 pub struct MessageBuilder {
-    body: Option<Box<str>>,
+    body: Option<Vec<u8>>,
+    content_type: Box<str>,
+    dedup_id: Option<Box<str>>,
+    group: Option<Box<str>>,
     offset: i32,
+    timezone: Option<Box<str>>,
     max_tries: u32,
     timeout: u32,
-    delay: Option<u32>,
+    delay: Option<Delay>,
+    priority: u32,
+    attributes: Attributes,
 }
 
 impl Default for MessageBuilder {
     fn default() -> Self {
         MessageBuilder {
             body: None,
+            content_type: DEFAULT_CONTENT_TYPE.into(),
+            dedup_id: None,
+            group: None,
             offset: 0,
+            timezone: None,
             max_tries: 1,
             timeout: 30,
             delay: None,
+            priority: 0,
+            attributes: Attributes::new(),
         }
     }
 }
 
 impl MessageBuilder {
-    /// Message body.
+    /// Message body. Both text and binary bodies are supported.
     #[must_use]
     pub fn body<T>(mut self, body: T) -> Self
     where
-        T: Into<Box<str>>,
+        T: Into<Vec<u8>>,
     {
         self.body = Some(body.into());
         self
     }
 
+    /// Message body content type, `text/plain` by default.
+    #[must_use]
+    pub fn content_type<T>(mut self, content_type: T) -> Self
+    where
+        T: Into<Box<str>>,
+    {
+        self.content_type = content_type.into();
+        self
+    }
+
+    /// Message deduplication ID. Queue ignores pushes with the same ID inside of deduplication window.
+    #[must_use]
+    pub fn dedup_id<T>(mut self, dedup_id: T) -> Self
+    where
+        T: Into<Box<str>>,
+    {
+        self.dedup_id = Some(dedup_id.into());
+        self
+    }
+
+    /// Message group. Messages of the same group are dispatched one at a time, in push order.
+    #[must_use]
+    pub fn group<T>(mut self, group: T) -> Self
+    where
+        T: Into<Box<str>>,
+    {
+        self.group = Some(group.into());
+        self
+    }
+
     /// Timezone offset in seconds.
     #[must_use]
     pub fn offset(mut self, offset: i32) -> Self {
@@ -63,6 +170,18 @@ impl MessageBuilder {
         self
     }
 
+    /// Message IANA timezone (like `Europe/Kyiv`). Takes precedence over offset.
+    ///
+    /// Message times are resolved with timezone offset, that is in effect at that time.
+    #[must_use]
+    pub fn timezone<T>(mut self, timezone: T) -> Self
+    where
+        T: Into<Box<str>>,
+    {
+        self.timezone = Some(timezone.into());
+        self
+    }
+
     /// Max tries for message to be reserved.
     #[must_use]
     pub fn max_tries(mut self, max_tries: u32) -> Self {
@@ -77,32 +196,147 @@ impl MessageBuilder {
         self
     }
 
-    /// Set message delay in seconds.
+    /// Set message delay in seconds. Replaces previously set delivery time.
     #[must_use]
     pub fn delay(mut self, delay: u32) -> Self {
-        self.delay = Some(delay);
+        self.delay = Some(Delay::Seconds(delay));
         self
     }
 
-    /// Compose message. Returns Err, if body was not provided.
-    pub fn compose(self) -> Result<Message, BuilderError> {
-        if let Some(body) = self.body {
-            Ok(Message::new(
-                body,
-                self.delay,
-                Offset::new(self.offset).ok_or(BuilderError::OffsetOutOfBounds)?,
-                self.max_tries,
-                self.timeout,
-            ))
+    /// Schedule message delivery for exact time. Replaces previously set delay.
+    ///
+    /// Delivery time can't be in the past, or more than [`MAX_SCHEDULE_DAYS`] days ahead.
+    #[must_use]
+    pub fn deliver_at<T>(mut self, deliver_at: T) -> Self
+    where
+        T: Into<DateTime<FixedOffset>>,
+    {
+        self.delay = Some(Delay::Until(deliver_at.into()));
+        self
+    }
+
+    /// Message priority. Messages with higher priority are dispatched first.
+    #[must_use]
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Message attribute. Attribute with the same key is replaced.
+    #[must_use]
+    pub fn attribute<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<Box<str>>,
+        V: Into<Attribute>,
+    {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    fn validate_content_type(&self) -> Result<(), BuilderError> {
+        let valid = !self.content_type.is_empty()
+            && self.content_type.len() <= MAX_CONTENT_TYPE_SIZE
+            && self
+                .content_type
+                .bytes()
+                .all(|byte| byte == b' ' || byte.is_ascii_graphic());
+
+        if valid {
+            Ok(())
         } else {
-            Err(BuilderError::BodyNotProvided)
+            Err(BuilderError::InvalidContentType)
         }
     }
+
+    fn validate_dedup_id(&self) -> Result<(), BuilderError> {
+        match self.dedup_id.as_deref() {
+            Some(dedup_id) if dedup_id.is_empty() || dedup_id.len() > MAX_DEDUP_ID_SIZE => {
+                Err(BuilderError::DedupIdOutOfBounds)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_group(&self) -> Result<(), BuilderError> {
+        match self.group.as_deref() {
+            Some(group) if group.is_empty() || group.len() > MAX_GROUP_SIZE => {
+                Err(BuilderError::GroupOutOfBounds)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_attributes(&self) -> Result<(), BuilderError> {
+        if self.attributes.len() > MAX_ATTRIBUTES {
+            return Err(BuilderError::TooManyAttributes);
+        }
+
+        for (key, value) in &self.attributes {
+            if key.is_empty() || key.len() > MAX_ATTRIBUTE_KEY_SIZE {
+                return Err(BuilderError::AttributeKeyOutOfBounds);
+            }
+
+            if value.size() > MAX_ATTRIBUTE_VALUE_SIZE {
+                return Err(BuilderError::AttributeValueTooLarge);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compose message. Returns Err, if body was not provided, or content type, deduplication ID, group,
+    /// offset, timezone, delivery time and attributes are invalid.
+    pub fn compose(self) -> Result<Message, BuilderError> {
+        self.compose_with_clock(&SystemClock)
+    }
+
+    /// Compose message, that is dispatched at current time of provided clock.
+    ///
+    /// Delivery time is validated against the same clock.
+    pub fn compose_with_clock<C>(self, clock: &C) -> Result<Message, BuilderError>
+    where
+        C: Clock,
+    {
+        self.validate_content_type()?;
+        self.validate_dedup_id()?;
+        self.validate_group()?;
+        self.validate_attributes()?;
+
+        let offset = Offset::new(self.offset).ok_or(BuilderError::OffsetOutOfBounds)?;
+        let timezone = self
+            .timezone
+            .as_deref()
+            .map(|timezone| Timezone::new(timezone).ok_or(BuilderError::UnknownTimezone))
+            .transpose()?;
+        let body = self.body.ok_or(BuilderError::BodyNotProvided)?;
+
+        Ok(Message {
+            id: Message::generate_id(),
+            body: body.into_boxed_slice(),
+            content_type: self.content_type,
+            dedup_id: self.dedup_id,
+            group: self.group,
+            priority: self.priority,
+            attributes: self.attributes,
+            state: State::new(self.max_tries),
+            time: Time::new(offset, timezone, self.delay, self.timeout, clock)
+                .ok_or(BuilderError::DeliverAtOutOfBounds)?,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::MessageBuilder;
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::{
+        BuilderError, MessageBuilder, DEFAULT_CONTENT_TYPE, MAX_ATTRIBUTES, MAX_ATTRIBUTE_KEY_SIZE,
+        MAX_ATTRIBUTE_VALUE_SIZE, MAX_DEDUP_ID_SIZE, MAX_GROUP_SIZE,
+    };
+    use crate::core::{
+        clock::{Clock, MockClock},
+        message::timezone::kyiv,
+    };
 
     #[test]
     fn creates_message() {
@@ -112,6 +346,7 @@ mod tests {
             .offset(100)
             .delay(1)
             .timeout(40)
+            .priority(2)
             .compose()
             .unwrap();
     }
@@ -127,4 +362,192 @@ mod tests {
             .compose()
             .unwrap();
     }
+
+    #[test]
+    fn creates_binary_message() {
+        let message = MessageBuilder::default()
+            .body(vec![0, 159, 146, 150])
+            .content_type("application/octet-stream")
+            .compose()
+            .unwrap();
+
+        assert_eq!(message.content_type(), "application/octet-stream");
+    }
+
+    #[test]
+    fn defaults_content_type() {
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .compose()
+            .unwrap();
+
+        assert_eq!(message.content_type(), DEFAULT_CONTENT_TYPE);
+    }
+
+    #[test]
+    fn fails_with_invalid_content_type() {
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .content_type("")
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::InvalidContentType)));
+
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .content_type("text/plain\r\n")
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::InvalidContentType)));
+    }
+
+    #[test]
+    fn fails_with_invalid_dedup_id() {
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .dedup_id("")
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::DedupIdOutOfBounds)));
+
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .dedup_id("a".repeat(MAX_DEDUP_ID_SIZE + 1))
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::DedupIdOutOfBounds)));
+    }
+
+    #[test]
+    fn fails_with_invalid_group() {
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .group("")
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::GroupOutOfBounds)));
+
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .group("a".repeat(MAX_GROUP_SIZE + 1))
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::GroupOutOfBounds)));
+    }
+
+    #[test]
+    fn creates_message_with_timezone() {
+        kyiv();
+
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .offset(3600)
+            .timezone("Europe/Kyiv")
+            .compose()
+            .unwrap();
+
+        assert_eq!(message.time().timezone().unwrap().name(), "Europe/Kyiv");
+        assert_ne!(message.time().offset().get(), 3600);
+    }
+
+    #[test]
+    fn fails_with_unknown_timezone() {
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .timezone("Europe/Atlantis")
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::UnknownTimezone)));
+    }
+
+    #[test]
+    fn creates_scheduled_message() {
+        let deliver_at = Utc::now() + Duration::hours(1);
+
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .deliver_at(deliver_at)
+            .compose()
+            .unwrap();
+
+        assert_eq!(
+            message.time().delay().unwrap().timestamp(),
+            deliver_at.timestamp()
+        );
+    }
+
+    #[test]
+    fn creates_message_with_clock() {
+        let clock = MockClock::new(Utc.ymd(2021, 3, 1).and_hms(10, 0, 0));
+
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .deliver_at(Utc.ymd(2021, 3, 1).and_hms(11, 0, 0))
+            .compose_with_clock(&clock)
+            .unwrap();
+
+        assert_eq!(message.time().dispatched_at(), &clock.now());
+    }
+
+    #[test]
+    fn fails_with_past_deliver_at() {
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .deliver_at(Utc::now() - Duration::hours(1))
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::DeliverAtOutOfBounds)));
+    }
+
+    #[test]
+    fn creates_message_with_attributes() {
+        let message = MessageBuilder::default()
+            .body("Hello, world")
+            .attribute("trace", "abc")
+            .attribute("retries", 3)
+            .compose()
+            .unwrap();
+
+        assert_eq!(message.attributes().len(), 2);
+    }
+
+    #[test]
+    fn fails_with_too_many_attributes() {
+        let builder = (0..=MAX_ATTRIBUTES).fold(
+            MessageBuilder::default().body("Hello, world"),
+            |builder, key| builder.attribute(key.to_string(), true),
+        );
+
+        assert!(matches!(
+            builder.compose(),
+            Err(BuilderError::TooManyAttributes)
+        ));
+    }
+
+    #[test]
+    fn fails_with_invalid_attribute_key() {
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .attribute("", true)
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::AttributeKeyOutOfBounds)));
+
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .attribute("a".repeat(MAX_ATTRIBUTE_KEY_SIZE + 1), true)
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::AttributeKeyOutOfBounds)));
+    }
+
+    #[test]
+    fn fails_with_large_attribute_value() {
+        let result = MessageBuilder::default()
+            .body("Hello, world")
+            .attribute("trace", "a".repeat(MAX_ATTRIBUTE_VALUE_SIZE + 1))
+            .compose();
+
+        assert!(matches!(result, Err(BuilderError::AttributeValueTooLarge)));
+    }
 }
@@ -1,53 +1,86 @@
 /// Message builder
 pub mod builder;
 
+/// Message attributes
+mod attribute;
+
 /// Message time manager
 mod time;
 
+/// IANA timezone support
+mod timezone;
+
 /// Message internal state
 mod state;
 
+/// Frozen message layout, that was persisted before format versioning
+pub mod v0;
+
+use std::cmp::Reverse;
+
+pub use attribute::{Attribute, Attributes};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 pub use state::{State, Status};
-pub use time::{Offset, Time, Timeout};
+pub use time::{Offset, Time, Timeout, MAX_SCHEDULE_DAYS};
+pub use timezone::Timezone;
 use uuid::Uuid;
 
-use crate::core::payload::{Dispatchable, Identifiable, Sortable, Status as StatusPayload};
+use crate::core::{
+    clock::Clock,
+    payload::{Dispatchable, Groupable, Identifiable, Sortable, Status as StatusPayload},
+};
 
 /// Default message implementation, with support of all [`payload`] traits
 ///
 /// [`Sortable`] implementation is compatible with [`TreeDatabase`]
 ///
+/// Messages are sorted by priority (higher priority goes first).
+/// Delayed messages are prioritized only after their delay expires,
+/// so they are never placed in front of messages, that are ready to be dispatched.
+///
+/// Messages with the same group are dispatched in push order, regardless of their sort key.
+///
 /// [`payload`]: crate::core::payload
 /// [`TreeDatabase`]: crate::core::db::TreeDatabase
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     id: Uuid,
-    body: Box<str>,
+    body: Box<[u8]>,
+    content_type: Box<str>,
+    dedup_id: Option<Box<str>>,
+    group: Option<Box<str>>,
+    priority: u32,
+    attributes: Attributes,
     state: State,
     time: Time,
 }
 
 impl Message {
-    fn new(
-        body: Box<str>,
-        delay: Option<u32>,
-        offset: Offset,
-        max_tries: u32,
-        timeout: u32,
-    ) -> Self {
-        Message {
-            id: Message::generate_id(),
-            body,
-            state: State::new(max_tries),
-            time: Time::new(offset, delay, timeout),
-        }
-    }
-
     fn generate_id() -> Uuid {
         Uuid::new_v4()
     }
 
+    /// Get message body content type
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// Get message deduplication ID
+    pub fn dedup_id(&self) -> Option<&str> {
+        self.dedup_id.as_deref()
+    }
+
+    /// Get message priority
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    /// Get message attributes
+    pub fn attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+
     /// Get current message [`State`]
     ///
     /// [`State`]: state::State
@@ -61,6 +94,64 @@ impl Message {
     pub fn time(&self) -> &Time {
         &self.time
     }
+
+    /// Reset message [`State`] and timeout, making message available for reservation again
+    ///
+    /// Message ID, body and delay are preserved.
+    ///
+    /// ```
+    /// use spartan_lib::core::clock::SystemClock;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::{Dispatchable, Status};
+    ///
+    /// let mut message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    ///
+    /// message.reserve(&SystemClock);
+    /// message.requeue();
+    ///
+    /// assert!(message.gc(&SystemClock));
+    ///
+    /// message.reset();
+    ///
+    /// assert!(message.reservable());
+    /// ```
+    ///
+    /// [`State`]: state::State
+    pub fn reset(&mut self) {
+        self.state.reset();
+        self.time.reset();
+    }
+
+    /// Reserve message with provided receipt handle, starting its timeout from current time of clock
+    ///
+    /// Unlike [`Status::reserve`], which generates new receipt handle,
+    /// this allows to repeat reservation, that was already made (for example, while replaying event log).
+    ///
+    /// ```
+    /// use spartan_lib::chrono::{TimeZone, Utc};
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::Status;
+    /// use spartan_lib::uuid::Uuid;
+    ///
+    /// let mut message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    /// let receipt = Uuid::new_v4();
+    /// let reserved_at = Utc.ymd(2021, 3, 1).and_hms(10, 0, 0);
+    ///
+    /// message.reserve_with_receipt(receipt, &reserved_at);
+    ///
+    /// assert!(message.requeueable());
+    /// assert_eq!(*message.state().receipt(), Some(receipt));
+    /// assert_eq!(message.time().timeout().obtained_at().unwrap(), reserved_at);
+    /// ```
+    ///
+    /// [`Status::reserve`]: crate::core::payload::Status::reserve
+    pub fn reserve_with_receipt<C>(&mut self, receipt: Uuid, clock: &C)
+    where
+        C: Clock,
+    {
+        self.state.reserve(receipt);
+        self.time.obtain(clock);
+    }
 }
 
 impl Identifiable for Message {
@@ -72,18 +163,31 @@ impl Identifiable for Message {
 }
 
 impl Dispatchable for Message {
-    type Body = str;
+    type Body = [u8];
 
-    fn obtainable(&self) -> bool {
-        self.time.check_delay() && !self.time.expired()
+    fn obtainable<C>(&self, clock: &C) -> bool
+    where
+        C: Clock,
+    {
+        // Timeout is only relevant for messages in transit, as requeued messages preserve obtain time
+        self.time.check_delay(clock) && !(self.state.requeueable() && self.time.expired(clock))
     }
 
     fn body(&self) -> &Self::Body {
         &self.body
     }
 
-    fn gc(&self) -> bool {
-        self.state.requires_gc() || (self.state.requeueable() && self.time.expired())
+    fn gc<C>(&self, clock: &C) -> bool
+    where
+        C: Clock,
+    {
+        self.state.requires_gc() || (self.state.requeueable() && self.time.expired(clock))
+    }
+}
+
+impl Groupable for Message {
+    fn group(&self) -> Option<&str> {
+        self.group.as_deref()
     }
 }
 
@@ -92,9 +196,25 @@ impl StatusPayload for Message {
         self.state.requeue();
     }
 
-    fn reserve(&mut self) {
-        self.state.reserve();
-        self.time.obtain();
+    fn reserve<C>(&mut self, clock: &C)
+    where
+        C: Clock,
+    {
+        self.reserve_with_receipt(Uuid::new_v4(), clock);
+    }
+
+    fn touch<C>(&mut self, timeout: u32, clock: &C)
+    where
+        C: Clock,
+    {
+        self.time.touch(timeout, clock);
+    }
+
+    fn delay<C>(&mut self, delay: u32, clock: &C)
+    where
+        C: Clock,
+    {
+        self.time.set_delay(delay, clock);
     }
 
     fn requeueable(&self) -> bool {
@@ -105,21 +225,37 @@ impl StatusPayload for Message {
         self.state.reservable()
     }
 
+    fn expired<C>(&self, clock: &C) -> bool
+    where
+        C: Clock,
+    {
+        self.time.expired(clock)
+    }
+
     fn has_tries(&self) -> bool {
         self.state.has_tries()
     }
 }
 
 impl Sortable for Message {
-    type Sort = Option<i64>;
+    type Sort = Reverse<u32>;
 
     fn sort(&self) -> Self::Sort {
-        self.time.get_raw_delay()
+        Reverse(self.priority)
+    }
+
+    fn delayed_until(&self) -> Option<DateTime<Utc>> {
+        self.time
+            .delay()
+            .as_ref()
+            .map(|delay| delay.with_timezone(&Utc))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::Utc;
+
     use super::builder::MessageBuilder;
     use crate::core::payload::Sortable;
 
@@ -133,15 +269,50 @@ mod tests {
         };
     }
 
+    macro_rules! prioritized_message {
+        ($priority:expr) => {
+            MessageBuilder::default()
+                .priority($priority)
+                .body("Hello world")
+                .compose()
+                .unwrap()
+        };
+    }
+
     #[test]
-    fn test_sort() {
-        let message1 = delayed_message!(2);
-        let message2 = delayed_message!(3);
-        let message3 = delayed_message!(1);
+    fn test_delayed_until() {
+        let message = delayed_message!(2);
+        assert_eq!(
+            message.delayed_until(),
+            message.time.delay().map(|delay| delay.with_timezone(&Utc))
+        );
+        assert!(prioritized_message!(0).delayed_until().is_none());
+    }
+
+    #[test]
+    fn test_sort_priority() {
+        let message1 = prioritized_message!(1);
+        let message2 = prioritized_message!(0);
+        let message3 = prioritized_message!(2);
         let mut vec = vec![message1.clone(), message2.clone(), message3.clone()];
         vec.sort_by_key(|msg| msg.sort());
         assert_eq!(vec.pop().unwrap().id, message2.id);
         assert_eq!(vec.pop().unwrap().id, message1.id);
         assert_eq!(vec.pop().unwrap().id, message3.id);
     }
+
+    #[test]
+    fn test_sort_ignores_delay() {
+        let message1 = MessageBuilder::default()
+            .priority(10)
+            .delay(1)
+            .body("Hello world")
+            .compose()
+            .unwrap();
+        let message2 = prioritized_message!(0);
+        let mut vec = vec![message1.clone(), message2.clone()];
+        vec.sort_by_key(|msg| msg.sort());
+        assert_eq!(vec.pop().unwrap().id, message2.id);
+        assert_eq!(vec.pop().unwrap().id, message1.id);
+    }
 }
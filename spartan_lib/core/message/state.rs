@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 /// Message status
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -16,12 +17,13 @@ impl Default for Status {
     }
 }
 
-/// Message state, containing try count and status
+/// Message state, containing try count, status and receipt handle of current reservation
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct State {
-    status: Status,
-    tries: u32,
-    max_tries: u32,
+    pub(super) status: Status,
+    pub(super) tries: u32,
+    pub(super) max_tries: u32,
+    pub(super) receipt: Option<Uuid>,
 }
 
 impl State {
@@ -30,6 +32,7 @@ impl State {
             status: Status::default(),
             tries: 0,
             max_tries,
+            receipt: None,
         }
     }
 
@@ -39,11 +42,19 @@ impl State {
 
     pub(crate) fn requeue(&mut self) {
         self.status = Status::Available;
+        self.receipt = None;
     }
 
-    pub(crate) fn reserve(&mut self) {
+    pub(crate) fn reserve(&mut self, receipt: Uuid) {
         self.status = Status::Transit;
         self.tries += 1;
+        self.receipt = Some(receipt);
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.status = Status::Available;
+        self.tries = 0;
+        self.receipt = None;
     }
 
     pub(crate) fn requeueable(&self) -> bool {
@@ -79,10 +90,20 @@ impl State {
     pub fn max_tries(&self) -> &u32 {
         &self.max_tries
     }
+
+    /// Get receipt handle of current reservation
+    ///
+    /// Each message reservation generates new receipt handle,
+    /// [`None`] if message is not reserved
+    pub fn receipt(&self) -> &Option<Uuid> {
+        &self.receipt
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use uuid::Uuid;
+
     use super::State;
 
     #[test]
@@ -103,9 +124,32 @@ mod tests {
     fn lifecycle() {
         let mut state = State::new(1);
         assert!(state.reservable());
-        state.reserve();
+        state.reserve(Uuid::new_v4());
         assert!(state.requeueable());
         state.requeue();
         assert!(state.requires_gc());
     }
+
+    #[test]
+    fn reset() {
+        let mut state = State::new(1);
+        state.reserve(Uuid::new_v4());
+        state.requeue();
+        assert!(state.requires_gc());
+        state.reset();
+        assert!(state.reservable());
+        assert_eq!(*state.tries(), 0);
+    }
+
+    #[test]
+    fn receipt() {
+        let mut state = State::new(2);
+        assert!(state.receipt().is_none());
+        state.reserve(Uuid::new_v4());
+        let receipt = state.receipt().unwrap();
+        state.requeue();
+        assert!(state.receipt().is_none());
+        state.reserve(Uuid::new_v4());
+        assert_ne!(state.receipt().unwrap(), receipt);
+    }
 }
@@ -1,14 +1,17 @@
-use chrono::{DateTime, Duration, FixedOffset, Utc};
+use chrono::{DateTime, Duration, FixedOffset, TimeZone};
 use serde::{Deserialize, Serialize};
 
+use super::Timezone;
+use crate::core::clock::Clock;
+
 /// Message timeout options
 ///
 /// Contains max timeout in seconds, and message obtain time.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Timeout {
-    max: u32,
+    pub(super) max: u32,
     #[serde(with = "serialization::tz_local_seconds_option")]
-    obtained_at: Option<DateTime<FixedOffset>>,
+    pub(super) obtained_at: Option<DateTime<FixedOffset>>,
 }
 
 impl Timeout {
@@ -23,6 +26,15 @@ impl Timeout {
         self.obtained_at = Some(current_time);
     }
 
+    pub(super) fn reset(&mut self) {
+        self.obtained_at = None;
+    }
+
+    pub(super) fn touch(&mut self, current_time: DateTime<FixedOffset>, max: u32) {
+        self.max = max;
+        self.obtained_at = Some(current_time);
+    }
+
     pub(super) fn expired(&self, current_time: DateTime<FixedOffset>) -> bool {
         self.obtained_at.map_or(false, |obtained_at| {
             (obtained_at + Duration::seconds(i64::from(self.max))) < current_time
@@ -44,11 +56,24 @@ impl Timeout {
     }
 }
 
+/// Max amount of days, that message delivery may be scheduled ahead
+pub const MAX_SCHEDULE_DAYS: i64 = 365;
+
+/// Message delay, either relative to message dispatch time, or absolute
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Delay {
+    /// Delay in seconds
+    Seconds(u32),
+
+    /// Scheduled delivery time
+    Until(DateTime<FixedOffset>),
+}
+
 /// Timezone offset type wrapper for [`i32`]
 ///
 /// Initialization requires for offset to be in range of `(-86399..86400)`
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
-pub struct Offset(i32);
+pub struct Offset(pub(super) i32);
 
 impl Offset {
     pub(crate) fn new(offset: i32) -> Option<Self> {
@@ -67,49 +92,126 @@ impl Offset {
 /// A time manager for handling message dispatch times, timeouts,
 /// delays and timezones
 ///
+/// If message has IANA timezone, then all message times are resolved with offset,
+/// that is in effect at that time, otherwise fixed offset is used.
+///
 /// Be aware, that all time handling itself is accessible to [`Message`] only
 ///
 /// [`Message`]: crate::core::message::Message
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "serialization::TimeData")]
 pub struct Time {
-    offset: Offset,
+    pub(super) offset: Offset,
+
+    pub(super) timezone: Option<Timezone>,
 
     #[serde(with = "serialization::tz_local_seconds")]
-    dispatched_at: DateTime<FixedOffset>,
+    pub(super) dispatched_at: DateTime<FixedOffset>,
 
     #[serde(with = "serialization::tz_local_seconds_option")]
-    delay: Option<DateTime<FixedOffset>>,
+    pub(super) delay: Option<DateTime<FixedOffset>>,
 
-    timeout: Timeout,
+    #[serde(with = "serialization::tz_local_seconds_option")]
+    pub(super) deliver_at: Option<DateTime<FixedOffset>>,
+
+    pub(super) timeout: Timeout,
 }
 
 impl Time {
-    pub(crate) fn new(offset: Offset, delay: Option<u32>, timeout: u32) -> Time {
-        let dispatched_at = Self::get_datetime_with_offset(offset.get());
-
-        Time {
+    /// Create new message time
+    ///
+    /// If timezone is provided, then message offset is the timezone offset at dispatch time.
+    ///
+    /// Returns [`None`], if scheduled delivery time is in the past,
+    /// or is more than [`MAX_SCHEDULE_DAYS`] days ahead
+    pub(crate) fn new<C>(
+        offset: Offset,
+        timezone: Option<Timezone>,
+        delay: Option<Delay>,
+        timeout: u32,
+        clock: &C,
+    ) -> Option<Time>
+    where
+        C: Clock,
+    {
+        let mut time = Time {
             offset,
-            dispatched_at,
-            delay: Self::convert_delay(delay.map(i64::from), dispatched_at),
+            timezone,
+            dispatched_at: clock.now().into(),
+            delay: None,
+            deliver_at: None,
             timeout: Timeout::new(timeout),
-        }
+        };
+
+        time.dispatched_at = time.get_datetime(clock);
+        time.offset = Offset(time.dispatched_at.offset().local_minus_utc());
+
+        match delay {
+            Some(Delay::Seconds(seconds)) => {
+                time.delay =
+                    Some(time.localize(time.dispatched_at + Duration::seconds(i64::from(seconds))));
+            }
+            Some(Delay::Until(deliver_at)) => {
+                if deliver_at < time.dispatched_at
+                    || deliver_at > time.dispatched_at + Duration::days(MAX_SCHEDULE_DAYS)
+                {
+                    return None;
+                }
+
+                time.delay = Some(time.localize(deliver_at));
+                time.deliver_at = Some(deliver_at);
+            }
+            None => (),
+        };
+
+        Some(time)
     }
 
-    pub(crate) fn check_delay(&self) -> bool {
+    pub(crate) fn check_delay<C>(&self, clock: &C) -> bool
+    where
+        C: Clock,
+    {
         self.delay
-            .map_or(true, |delay| delay <= self.get_datetime())
+            .map_or(true, |delay| delay <= self.get_datetime(clock))
+    }
+
+    pub(crate) fn obtain<C>(&mut self, clock: &C)
+    where
+        C: Clock,
+    {
+        self.timeout.obtain(self.get_datetime(clock));
+    }
+
+    /// Delay message by provided amount of seconds
+    ///
+    /// Zero delay clears previous delay, so message is not kept aside as delayed.
+    pub(crate) fn set_delay<C>(&mut self, delay: u32, clock: &C)
+    where
+        C: Clock,
+    {
+        self.delay = if delay > 0 {
+            Some(self.localize(clock.now() + Duration::seconds(i64::from(delay))))
+        } else {
+            None
+        };
     }
 
-    pub(crate) fn get_raw_delay(&self) -> Option<i64> {
-        self.delay.as_ref().map(DateTime::timestamp)
+    pub(crate) fn touch<C>(&mut self, timeout: u32, clock: &C)
+    where
+        C: Clock,
+    {
+        self.timeout.touch(self.get_datetime(clock), timeout);
     }
 
-    pub(crate) fn obtain(&mut self) {
-        self.timeout.obtain(self.get_datetime());
+    pub(crate) fn expired<C>(&self, clock: &C) -> bool
+    where
+        C: Clock,
+    {
+        self.timeout.expired(self.get_datetime(clock))
     }
 
-    pub(crate) fn expired(&self) -> bool {
-        self.timeout.expired(self.get_datetime())
+    pub(crate) fn reset(&mut self) {
+        self.timeout.reset();
     }
 
     /// Get message timezone offset.
@@ -117,6 +219,13 @@ impl Time {
         &self.offset
     }
 
+    /// Get message IANA timezone.
+    ///
+    /// [`None`] if message uses fixed offset.
+    pub fn timezone(&self) -> Option<&Timezone> {
+        self.timezone.as_ref()
+    }
+
     /// Get message dispatch time with offset awareness.
     pub fn dispatched_at(&self) -> &DateTime<FixedOffset> {
         &self.dispatched_at
@@ -134,24 +243,35 @@ impl Time {
         &self.delay
     }
 
+    /// Get scheduled delivery time, as it was provided on message creation.
+    ///
+    /// [`None`] if message was not scheduled for exact time.
+    /// Unlike delay, scheduled time is preserved on requeue.
+    pub fn deliver_at(&self) -> &Option<DateTime<FixedOffset>> {
+        &self.deliver_at
+    }
+
     /// Get message timeout options.
     pub fn timeout(&self) -> &Timeout {
         &self.timeout
     }
 
-    fn convert_delay(
-        seconds: Option<i64>,
-        dispatched_at: DateTime<FixedOffset>,
-    ) -> Option<DateTime<FixedOffset>> {
-        Some(dispatched_at + Duration::seconds(seconds?))
-    }
-
-    fn get_datetime(&self) -> DateTime<FixedOffset> {
-        Self::get_datetime_with_offset(self.offset.get())
+    /// Convert provided time to message local time
+    fn localize<T>(&self, datetime: DateTime<T>) -> DateTime<FixedOffset>
+    where
+        T: TimeZone,
+    {
+        match self.timezone.as_ref() {
+            Some(timezone) => timezone.localize(&datetime),
+            None => datetime.with_timezone(&FixedOffset::east(self.offset.get())),
+        }
     }
 
-    fn get_datetime_with_offset(offset: i32) -> DateTime<FixedOffset> {
-        Utc::now().with_timezone(&FixedOffset::east(offset))
+    fn get_datetime<C>(&self, clock: &C) -> DateTime<FixedOffset>
+    where
+        C: Clock,
+    {
+        self.localize(clock.now())
     }
 }
 
@@ -159,7 +279,49 @@ mod serialization {
     use std::fmt::{Formatter, Result as FmtResult};
 
     use chrono::{DateTime, FixedOffset, TimeZone, Utc};
-    use serde::de::{SeqAccess, Visitor};
+    use serde::{
+        de::{SeqAccess, Visitor},
+        Deserialize,
+    };
+
+    use super::{Offset, Time, Timeout, Timezone};
+
+    /// Stored message time, which timezone is not loaded yet
+    #[derive(Deserialize)]
+    pub(super) struct TimeData {
+        offset: Offset,
+
+        timezone: Option<Box<str>>,
+
+        #[serde(with = "tz_local_seconds")]
+        dispatched_at: DateTime<FixedOffset>,
+
+        #[serde(with = "tz_local_seconds_option")]
+        delay: Option<DateTime<FixedOffset>>,
+
+        #[serde(with = "tz_local_seconds_option")]
+        deliver_at: Option<DateTime<FixedOffset>>,
+
+        timeout: Timeout,
+    }
+
+    impl From<TimeData> for Time {
+        /// Load message timezone, falling back to stored offset if timezone database doesn't contain it
+        fn from(data: TimeData) -> Self {
+            let offset = data.offset;
+
+            Time {
+                offset,
+                timezone: data.timezone.map(|name| {
+                    Timezone::new(&name).unwrap_or_else(|| Timezone::fixed(&name, offset.get()))
+                }),
+                dispatched_at: data.dispatched_at,
+                delay: data.delay,
+                deliver_at: data.deliver_at,
+                timeout: data.timeout,
+            }
+        }
+    }
 
     struct LocalSecondsTimestampVisitor;
 
@@ -274,12 +436,23 @@ mod serialization {
 
 #[cfg(test)]
 mod tests {
-    use std::{thread::sleep, time::Duration};
-
-    use super::{DateTime, Duration as ChronoDuration, FixedOffset, Offset, Time, Timeout, Utc};
+    use chrono::{TimeZone, Utc};
+
+    use super::{
+        DateTime, Delay, Duration as ChronoDuration, FixedOffset, Offset, Time, Timeout, Timezone,
+        MAX_SCHEDULE_DAYS,
+    };
+    use crate::core::{
+        clock::{Clock, MockClock},
+        message::timezone::kyiv,
+    };
+
+    fn get_clock() -> MockClock {
+        MockClock::new(Utc.ymd(2021, 3, 1).and_hms(10, 0, 0))
+    }
 
     fn get_timestamp() -> DateTime<FixedOffset> {
-        Utc::now().into()
+        get_clock().now().into()
     }
 
     #[test]
@@ -292,21 +465,192 @@ mod tests {
         assert!(timeout.expired(timestamp + ChronoDuration::seconds(4)));
     }
 
+    #[test]
+    fn test_timeout_touch() {
+        let timestamp = get_timestamp();
+        let mut timeout = Timeout::new(3);
+        timeout.obtain(timestamp);
+        timeout.touch(timestamp + ChronoDuration::seconds(2), 5);
+        assert_eq!(timeout.max, 5);
+        assert!(!timeout.expired(timestamp + ChronoDuration::seconds(6)));
+        assert!(timeout.expired(timestamp + ChronoDuration::seconds(8)));
+    }
+
     #[test]
     fn delay_test() {
-        let time = Time::new(Offset::new(0).unwrap(), Some(2), 1);
-        assert!(!time.check_delay());
-        sleep(Duration::from_secs(3));
-        assert!(time.check_delay());
+        let clock = get_clock();
+        let time = Time::new(
+            Offset::new(0).unwrap(),
+            None,
+            Some(Delay::Seconds(2)),
+            1,
+            &clock,
+        )
+        .unwrap();
+        assert!(!time.check_delay(&clock));
+        clock.advance(ChronoDuration::seconds(1));
+        assert!(!time.check_delay(&clock));
+        clock.advance(ChronoDuration::seconds(1));
+        assert!(time.check_delay(&clock));
+    }
+
+    #[test]
+    fn test_expired() {
+        let clock = get_clock();
+        let mut time = Time::new(Offset::new(0).unwrap(), None, None, 30, &clock).unwrap();
+        assert!(!time.expired(&clock));
+        time.obtain(&clock);
+        clock.advance(ChronoDuration::seconds(30));
+        assert!(!time.expired(&clock));
+        time.touch(60, &clock);
+        clock.advance(ChronoDuration::seconds(31));
+        assert!(!time.expired(&clock));
+        clock.advance(ChronoDuration::seconds(30));
+        assert!(time.expired(&clock));
+    }
+
+    #[test]
+    fn test_set_delay() {
+        let clock = get_clock();
+        let mut time = Time::new(Offset::new(0).unwrap(), None, None, 1, &clock).unwrap();
+        assert!(time.check_delay(&clock));
+        time.set_delay(10, &clock);
+        assert!(!time.check_delay(&clock));
+        clock.advance(ChronoDuration::seconds(10));
+        assert!(time.check_delay(&clock));
+        time.set_delay(0, &clock);
+        assert!(time.check_delay(&clock));
+        assert!(time.delay().is_none());
     }
 
     // This test covers 'fast index lookup' bug, that came in version 0.6
     #[test]
     fn test_delay_compare() {
-        let time1 = Time::new(Offset::new(0).unwrap(), Some(10), 0);
-        let time2 = Time::new(Offset::new(10).unwrap(), Some(2), 0);
+        let clock = get_clock();
+        let time1 = Time::new(
+            Offset::new(0).unwrap(),
+            None,
+            Some(Delay::Seconds(10)),
+            0,
+            &clock,
+        )
+        .unwrap();
+        let time2 = Time::new(
+            Offset::new(10).unwrap(),
+            None,
+            Some(Delay::Seconds(2)),
+            0,
+            &clock,
+        )
+        .unwrap();
+
+        assert!(time1.delay() > time2.delay());
+    }
+
+    #[test]
+    fn test_deliver_at() {
+        let clock = get_clock();
+        let deliver_at =
+            (clock.now() + ChronoDuration::seconds(10)).with_timezone(&FixedOffset::east(3600));
+        let time = Time::new(
+            Offset::new(0).unwrap(),
+            None,
+            Some(Delay::Until(deliver_at)),
+            1,
+            &clock,
+        )
+        .unwrap();
+        assert!(!time.check_delay(&clock));
+        clock.advance(ChronoDuration::seconds(10));
+        assert!(time.check_delay(&clock));
+        assert_eq!(
+            time.delay().map(|delay| delay.timestamp()),
+            Some(deliver_at.timestamp())
+        );
+        assert_eq!(time.deliver_at().unwrap().offset(), deliver_at.offset());
+    }
+
+    #[test]
+    fn test_deliver_at_bounds() {
+        let clock = get_clock();
+        let bounded = |deliver_at| {
+            Time::new(
+                Offset::new(0).unwrap(),
+                None,
+                Some(Delay::Until(deliver_at)),
+                1,
+                &clock,
+            )
+            .is_some()
+        };
 
-        assert!(time1.get_raw_delay() > time2.get_raw_delay());
+        assert!(!bounded(get_timestamp() - ChronoDuration::seconds(10)));
+        assert!(bounded(
+            get_timestamp() + ChronoDuration::days(MAX_SCHEDULE_DAYS)
+        ));
+        assert!(!bounded(
+            get_timestamp() + ChronoDuration::days(MAX_SCHEDULE_DAYS + 1)
+        ));
+    }
+
+    #[test]
+    fn test_timezone() {
+        let timezone = kyiv();
+
+        // Delay, that crosses DST transition
+        let clock = get_clock();
+        let time = Time::new(
+            Offset::new(0).unwrap(),
+            Some(timezone.clone()),
+            Some(Delay::Seconds(60 * 86400)),
+            1,
+            &clock,
+        )
+        .unwrap();
+
+        assert_eq!(
+            time.dispatched_at().offset(),
+            &timezone.offset_at(time.dispatched_at())
+        );
+        assert_eq!(
+            time.offset().get(),
+            time.dispatched_at().offset().local_minus_utc()
+        );
+
+        // Delay is resolved with offset, that is in effect at delay time
+        let delay = time.delay().unwrap();
+        assert_eq!(delay.offset(), &timezone.offset_at(&delay));
+        assert_ne!(delay.offset(), time.dispatched_at().offset());
+    }
+
+    #[test]
+    fn test_timezone_serialization() {
+        let time = Time::new(Offset::new(0).unwrap(), Some(kyiv()), None, 1, &get_clock()).unwrap();
+
+        let time: Time = bincode::deserialize(&bincode::serialize(&time).unwrap()).unwrap();
+
+        assert_eq!(time.timezone().unwrap().name(), "Europe/Kyiv");
+    }
+
+    #[test]
+    fn test_unknown_timezone_deserialization() {
+        let clock = get_clock();
+        let time = Time::new(
+            Offset::new(0).unwrap(),
+            Some(Timezone::fixed("Europe/Atlantis", 7200)),
+            Some(Delay::Seconds(60)),
+            1,
+            &clock,
+        )
+        .unwrap();
+
+        let time: Time = bincode::deserialize(&bincode::serialize(&time).unwrap()).unwrap();
+
+        // Zone is missing from timezone database, so stored offset is used
+        assert_eq!(time.timezone().unwrap().name(), "Europe/Atlantis");
+        assert_eq!(time.offset().get(), 7200);
+        assert_eq!(time.get_datetime(&clock).offset().local_minus_utc(), 7200);
+        assert!(!time.check_delay(&clock));
     }
 
     #[test]
@@ -0,0 +1,331 @@
+/// POSIX TZ rule
+mod rule;
+
+/// TZif file parser
+mod tzif;
+
+use std::{
+    collections::HashMap,
+    env,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    fs::read,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc};
+use once_cell::sync::Lazy;
+use rule::Rule;
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Default location of IANA timezone database
+const DEFAULT_ZONEINFO_PATH: &str = "/usr/share/zoneinfo";
+
+/// Environment variable, that overrides location of IANA timezone database
+const ZONEINFO_PATH_ENV: &str = "TZDIR";
+
+/// Max timezone name size in bytes
+const MAX_NAME_SIZE: usize = 64;
+
+/// Max distance between local time and UTC in seconds
+const MAX_LOCAL_DISTANCE: i64 = 86_400;
+
+/// Loaded timezones, shared between all messages
+static ZONES: Lazy<Mutex<HashMap<Box<str>, Timezone>>> = Lazy::new(Default::default);
+
+/// UTC offset rules of timezone
+struct Zone {
+    name: Box<str>,
+
+    /// UTC offset before the first transition
+    initial: i32,
+
+    /// Transition UTC timestamps, and UTC offsets in effect since them
+    transitions: Box<[(i64, i32)]>,
+
+    /// Rule for timestamps after the last transition
+    rule: Option<Rule>,
+}
+
+impl Zone {
+    fn offset_at(&self, timestamp: i64) -> i32 {
+        if self.transitions.is_empty() {
+            return self
+                .rule
+                .as_ref()
+                .map_or(self.initial, |rule| rule.offset_at(timestamp));
+        }
+
+        let index = match self
+            .transitions
+            .binary_search_by_key(&timestamp, |(time, _)| *time)
+        {
+            Ok(index) => index,
+            Err(0) => return self.initial,
+            Err(index) => index - 1,
+        };
+
+        match self.rule.as_ref() {
+            Some(rule) if index == self.transitions.len() - 1 => rule.offset_at(timestamp),
+            _ => self.transitions[index].1,
+        }
+    }
+}
+
+/// Check if timezone name is safe to be used as database path
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_SIZE
+        && name.split('/').all(|part| {
+            !part.is_empty()
+                && !part.starts_with('.')
+                && part
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || b"_-+.".contains(&byte))
+        })
+}
+
+fn load(name: &str) -> Option<Zone> {
+    let path = env::var_os(ZONEINFO_PATH_ENV)
+        .map_or_else(|| PathBuf::from(DEFAULT_ZONEINFO_PATH), PathBuf::from)
+        .join(name);
+
+    tzif::parse(name, &read(path).ok()?)
+}
+
+/// IANA timezone (like `Europe/Kyiv`)
+///
+/// Rules are loaded from system timezone database (`/usr/share/zoneinfo`, or path from `TZDIR` environment variable),
+/// and are cached for the whole process lifetime.
+///
+/// Timezone is serialized as its name, so rules are loaded again on deserialization.
+/// Stored message times fall back to their fixed offset, if timezone can't be loaded anymore.
+///
+/// ```
+/// use spartan_lib::chrono::{TimeZone, Utc};
+/// use spartan_lib::core::message::Timezone;
+///
+/// assert!(Timezone::new("Unknown/Zone").is_none());
+///
+/// if let Some(timezone) = Timezone::new("Europe/Kyiv") {
+///     let summer = Utc.ymd(2021, 7, 1).and_hms(0, 0, 0);
+///     let winter = Utc.ymd(2021, 12, 1).and_hms(0, 0, 0);
+///
+///     assert_eq!(timezone.offset_at(&summer).local_minus_utc(), 3 * 3600);
+///     assert_eq!(timezone.offset_at(&winter).local_minus_utc(), 2 * 3600);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Timezone {
+    zone: Arc<Zone>,
+}
+
+impl Timezone {
+    /// Load timezone with provided name
+    ///
+    /// Returns [`None`], if timezone is not present in timezone database
+    pub fn new(name: &str) -> Option<Timezone> {
+        if !is_valid_name(name) {
+            return None;
+        }
+
+        let mut zones = ZONES.lock().ok()?;
+
+        match zones.get(name) {
+            Some(timezone) => Some(timezone.clone()),
+            None => {
+                let timezone = Timezone {
+                    zone: Arc::new(load(name)?),
+                };
+
+                zones.insert(name.into(), timezone.clone());
+                Some(timezone)
+            }
+        }
+    }
+
+    /// Make timezone with fixed UTC `offset`, that is used in place of zone missing from timezone database
+    ///
+    /// Such timezone is not cached, so zone is loaded again on the next deserialization.
+    pub(crate) fn fixed(name: &str, offset: i32) -> Timezone {
+        Timezone {
+            zone: Arc::new(Zone {
+                name: name.into(),
+                initial: offset,
+                transitions: Box::new([]),
+                rule: None,
+            }),
+        }
+    }
+
+    /// Get timezone name
+    pub fn name(&self) -> &str {
+        &self.zone.name
+    }
+
+    /// Get UTC offset, that is in effect at provided time
+    pub fn offset_at<T>(&self, datetime: &DateTime<T>) -> FixedOffset
+    where
+        T: TimeZone,
+    {
+        FixedOffset::east(self.zone.offset_at(datetime.timestamp()))
+    }
+
+    /// Convert provided time to local time of timezone
+    pub fn localize<T>(&self, datetime: &DateTime<T>) -> DateTime<FixedOffset>
+    where
+        T: TimeZone,
+    {
+        datetime.with_timezone(&self.offset_at(datetime))
+    }
+
+    /// Resolve local time of timezone
+    ///
+    /// Ambiguous local time (when clocks are turned back) resolves to its earliest occurrence,
+    /// while skipped local time (when clocks are turned forward) is shifted forward by the length of skipped interval.
+    pub fn from_local(&self, local: &NaiveDateTime) -> DateTime<FixedOffset> {
+        let timestamp = local.timestamp();
+
+        let before = self.zone.offset_at(timestamp - MAX_LOCAL_DISTANCE);
+        let after = self.zone.offset_at(timestamp + MAX_LOCAL_DISTANCE);
+
+        let offset = [before, after]
+            .iter()
+            .copied()
+            .filter(|offset| self.zone.offset_at(timestamp - i64::from(*offset)) == *offset)
+            .max()
+            .unwrap_or(before);
+
+        self.localize(&Utc.timestamp(timestamp - i64::from(offset), 0))
+    }
+}
+
+impl Debug for Timezone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_tuple("Timezone").field(&self.name()).finish()
+    }
+}
+
+impl PartialEq for Timezone {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl Serialize for Timezone {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timezone {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+
+        Timezone::new(&name).ok_or_else(|| D::Error::custom(format!("Unknown timezone {}", name)))
+    }
+}
+
+/// `Europe/Kyiv` timezone, that is built from its POSIX rule, so tests don't depend on host timezone database
+#[cfg(test)]
+pub(crate) fn kyiv() -> Timezone {
+    ZONES
+        .lock()
+        .unwrap()
+        .entry("Europe/Kyiv".into())
+        .or_insert_with(|| Timezone {
+            zone: Arc::new(Zone {
+                name: "Europe/Kyiv".into(),
+                initial: 7200,
+                transitions: Box::new([]),
+                rule: Rule::parse("EET-2EEST,M3.5.0/3,M10.5.0/4"),
+            }),
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::{is_valid_name, kyiv, Timezone};
+
+    #[test]
+    fn test_valid_name() {
+        assert!(is_valid_name("Europe/Kyiv"));
+        assert!(is_valid_name("Etc/GMT+2"));
+        assert!(is_valid_name("UTC"));
+
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("/etc/passwd"));
+        assert!(!is_valid_name("../zoneinfo/UTC"));
+        assert!(!is_valid_name("Europe//Kyiv"));
+    }
+
+    #[test]
+    fn test_unknown_timezone() {
+        assert!(Timezone::new("Europe/Atlantis").is_none());
+        assert!(Timezone::new("../../etc/passwd").is_none());
+    }
+
+    #[test]
+    fn test_offset_at() {
+        let timezone = kyiv();
+
+        let offset = |datetime| timezone.offset_at(&datetime).local_minus_utc();
+
+        assert_eq!(offset(Utc.ymd(2021, 3, 28).and_hms(0, 59, 59)), 7200);
+        assert_eq!(offset(Utc.ymd(2021, 3, 28).and_hms(1, 0, 0)), 10800);
+        assert_eq!(offset(Utc.ymd(2021, 10, 31).and_hms(0, 59, 59)), 10800);
+        assert_eq!(offset(Utc.ymd(2021, 10, 31).and_hms(1, 0, 0)), 7200);
+
+        // Far future offsets are resolved with timezone rule
+        assert_eq!(offset(Utc.ymd(2100, 7, 1).and_hms(0, 0, 0)), 10800);
+        assert_eq!(offset(Utc.ymd(2100, 12, 1).and_hms(0, 0, 0)), 7200);
+    }
+
+    #[test]
+    fn test_from_local() {
+        let timezone = kyiv();
+
+        let resolve = |hour, minute, day| {
+            timezone
+                .from_local(&NaiveDate::from_ymd(2021, 3, day).and_hms(hour, minute, 0))
+                .with_timezone(&Utc)
+        };
+
+        assert_eq!(resolve(12, 0, 1), Utc.ymd(2021, 3, 1).and_hms(10, 0, 0));
+        assert_eq!(resolve(12, 0, 29), Utc.ymd(2021, 3, 29).and_hms(9, 0, 0));
+
+        // Skipped local time is shifted forward
+        assert_eq!(resolve(3, 30, 28), Utc.ymd(2021, 3, 28).and_hms(1, 30, 0));
+
+        // Ambiguous local time resolves to its earliest occurrence
+        assert_eq!(
+            timezone
+                .from_local(&NaiveDate::from_ymd(2021, 10, 31).and_hms(3, 30, 0))
+                .with_timezone(&Utc),
+            Utc.ymd(2021, 10, 31).and_hms(0, 30, 0)
+        );
+    }
+
+    #[test]
+    fn test_serialization() {
+        let timezone = kyiv();
+
+        let serialized = bincode::serialize(&timezone).unwrap();
+        let deserialized: Timezone = bincode::deserialize(&serialized).unwrap();
+
+        assert_eq!(deserialized, timezone);
+        assert!(
+            bincode::deserialize::<Timezone>(&bincode::serialize("Europe/Atlantis").unwrap())
+                .is_err()
+        );
+    }
+}
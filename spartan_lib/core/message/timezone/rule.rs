@@ -0,0 +1,374 @@
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+
+/// Default local time of DST transitions in seconds
+const DEFAULT_TRANSITION_TIME: i64 = 2 * 3600;
+
+/// Max absolute value of UTC offset in seconds
+const MAX_OFFSET: i64 = 86_399;
+
+/// Day of year, when DST starts or ends
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Day {
+    /// `Jn`: day of year in range of `1..=365`, February 29 is never counted
+    Julian(u32),
+
+    /// `n`: zero-based day of year in range of `0..=365`
+    Ordinal(u32),
+
+    /// `Mm.w.d`: weekday `d` (0 is Sunday) of week `w` (5 is the last one) of month `m`
+    Month(u32, u32, u32),
+}
+
+impl Day {
+    fn date(self, year: i32) -> Option<NaiveDate> {
+        match self {
+            Day::Julian(day) => {
+                let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+                NaiveDate::from_yo_opt(year, if leap && day >= 60 { day + 1 } else { day })
+            }
+            Day::Ordinal(day) => NaiveDate::from_yo_opt(year, day + 1),
+            Day::Month(month, week, weekday) => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let shift = (weekday + 7 - first.weekday().num_days_from_sunday()) % 7;
+
+                // The last week may not exist in short months
+                (0..week)
+                    .rev()
+                    .find_map(|week| NaiveDate::from_ymd_opt(year, month, 1 + shift + week * 7))
+            }
+        }
+    }
+}
+
+/// DST start or end
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Transition {
+    day: Day,
+
+    /// Local time of transition in seconds (may be negative or exceed a day)
+    time: i64,
+}
+
+impl Transition {
+    /// Get UTC timestamp of transition in provided year, where `offset` is UTC offset in effect before transition
+    fn timestamp(self, year: i32, offset: i32) -> Option<i64> {
+        let date = self.day.date(year)?;
+        Some(date.and_hms(0, 0, 0).timestamp() + self.time - i64::from(offset))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Dst {
+    /// UTC offset in seconds
+    offset: i32,
+    start: Transition,
+    end: Transition,
+}
+
+/// POSIX TZ rule (like `EET-2EEST,M3.5.0/3,M10.5.0/4`)
+///
+/// Describes UTC offsets of timezone after its last listed transition.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(super) struct Rule {
+    /// Standard UTC offset in seconds
+    std: i32,
+
+    /// Daylight saving time, if timezone observes it
+    dst: Option<Dst>,
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.position).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        Some(()).filter(|_| self.eat(byte))
+    }
+
+    fn is_done(&self) -> bool {
+        self.position == self.source.len()
+    }
+
+    fn take_while<F>(&mut self, predicate: F) -> &'a [u8]
+    where
+        F: Fn(u8) -> bool,
+    {
+        let start = self.position;
+
+        while matches!(self.peek(), Some(byte) if predicate(byte)) {
+            self.position += 1;
+        }
+
+        &self.source[start..self.position]
+    }
+
+    /// Timezone abbreviation, either alphabetic (`EET`) or quoted (`<+03>`)
+    fn name(&mut self) -> Option<()> {
+        if self.eat(b'<') {
+            self.take_while(|byte| byte != b'>');
+            self.expect(b'>')
+        } else {
+            Some(()).filter(|_| self.take_while(|byte| byte.is_ascii_alphabetic()).len() >= 3)
+        }
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let digits = self.take_while(|byte| byte.is_ascii_digit());
+
+        if digits.is_empty() || digits.len() > 3 {
+            return None;
+        }
+
+        Some(
+            digits
+                .iter()
+                .fold(0, |number, digit| number * 10 + i64::from(digit - b'0')),
+        )
+    }
+
+    /// Signed time in `[+-]hh[:mm[:ss]]` format, converted to seconds
+    fn time(&mut self) -> Option<i64> {
+        let sign = if self.eat(b'-') {
+            -1
+        } else {
+            self.eat(b'+');
+            1
+        };
+
+        let mut seconds = self.number()? * 3600;
+
+        for multiplier in &[60, 1] {
+            if !self.eat(b':') {
+                break;
+            }
+
+            seconds += self.number().filter(|value| *value < 60)? * multiplier;
+        }
+
+        Some(sign * seconds)
+    }
+
+    /// POSIX offset, converted to UTC offset (POSIX offsets are positive west of Greenwich)
+    fn offset(&mut self) -> Option<i32> {
+        let offset = -self.time()?;
+
+        if offset.abs() > MAX_OFFSET {
+            None
+        } else {
+            Some(offset as i32)
+        }
+    }
+
+    fn day(&mut self) -> Option<Day> {
+        if self.eat(b'J') {
+            Some(self.number()? as u32)
+                .filter(|day| (1..=365).contains(day))
+                .map(Day::Julian)
+        } else if self.eat(b'M') {
+            let month = self.number()? as u32;
+            self.expect(b'.')?;
+            let week = self.number()? as u32;
+            self.expect(b'.')?;
+            let weekday = self.number()? as u32;
+
+            if (1..=12).contains(&month) && (1..=5).contains(&week) && weekday <= 6 {
+                Some(Day::Month(month, week, weekday))
+            } else {
+                None
+            }
+        } else {
+            Some(self.number()? as u32)
+                .filter(|day| *day <= 365)
+                .map(Day::Ordinal)
+        }
+    }
+
+    fn transition(&mut self) -> Option<Transition> {
+        let day = self.day()?;
+
+        let time = if self.eat(b'/') {
+            self.time()?
+        } else {
+            DEFAULT_TRANSITION_TIME
+        };
+
+        Some(Transition { day, time })
+    }
+}
+
+impl Rule {
+    /// Parse POSIX TZ rule
+    ///
+    /// Returns [`None`] if rule is invalid
+    pub(super) fn parse(source: &str) -> Option<Rule> {
+        let mut parser = Parser {
+            source: source.as_bytes(),
+            position: 0,
+        };
+
+        parser.name()?;
+        let std = parser.offset()?;
+
+        if parser.is_done() {
+            return Some(Rule { std, dst: None });
+        }
+
+        parser.name()?;
+
+        let offset = match parser.peek() {
+            Some(b',') | None => std + 3600,
+            _ => parser.offset()?,
+        };
+
+        let (start, end) = if parser.eat(b',') {
+            let start = parser.transition()?;
+            parser.expect(b',')?;
+            (start, parser.transition()?)
+        } else {
+            // Rules without transitions use US rules
+            (
+                Transition {
+                    day: Day::Month(3, 2, 0),
+                    time: DEFAULT_TRANSITION_TIME,
+                },
+                Transition {
+                    day: Day::Month(11, 1, 0),
+                    time: DEFAULT_TRANSITION_TIME,
+                },
+            )
+        };
+
+        if !parser.is_done() {
+            return None;
+        }
+
+        Some(Rule {
+            std,
+            dst: Some(Dst { offset, start, end }),
+        })
+    }
+
+    /// Get UTC offset in seconds at provided UTC timestamp
+    pub(super) fn offset_at(&self, timestamp: i64) -> i32 {
+        let dst = match self.dst.as_ref() {
+            Some(dst) => dst,
+            None => return self.std,
+        };
+
+        let year = match NaiveDateTime::from_timestamp_opt(timestamp + i64::from(self.std), 0) {
+            Some(datetime) => datetime.year(),
+            None => return self.std,
+        };
+
+        let bounds = (
+            dst.start.timestamp(year, self.std),
+            dst.end.timestamp(year, dst.offset),
+        );
+
+        let is_dst = match bounds {
+            (Some(start), Some(end)) if start < end => start <= timestamp && timestamp < end,
+            // Southern hemisphere DST spans over new year
+            (Some(start), Some(end)) => !(end <= timestamp && timestamp < start),
+            _ => false,
+        };
+
+        if is_dst {
+            dst.offset
+        } else {
+            self.std
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::{Day, Rule};
+
+    fn offset_at(rule: &Rule, datetime: (i32, u32, u32, u32, u32)) -> i32 {
+        let (year, month, day, hour, minute) = datetime;
+        rule.offset_at(
+            Utc.ymd(year, month, day)
+                .and_hms(hour, minute, 0)
+                .timestamp(),
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Rule::parse("UTC0"), Some(Rule { std: 0, dst: None }));
+        assert_eq!(
+            Rule::parse("<+0530>-5:30"),
+            Some(Rule {
+                std: 19800,
+                dst: None
+            })
+        );
+        assert!(Rule::parse("EET-2EEST,M3.5.0/3,M10.5.0/4").is_some());
+        assert!(Rule::parse("<-03>3<-02>,M3.5.0/-2,M10.5.0/-1").is_some());
+        assert!(Rule::parse("EST5EDT,0/0,J365/25").is_some());
+        assert!(Rule::parse("EST5EDT").is_some());
+
+        assert!(Rule::parse("").is_none());
+        assert!(Rule::parse("EE-2").is_none());
+        assert!(Rule::parse("EET-2EEST,M3.5.0/3").is_none());
+        assert!(Rule::parse("EET-2EEST,M13.5.0,M10.5.0").is_none());
+        assert!(Rule::parse("EET-25").is_none());
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(
+            Day::Month(3, 5, 0).date(2021),
+            Some(NaiveDate::from_ymd(2021, 3, 28))
+        );
+        assert_eq!(
+            Day::Month(2, 5, 1).date(2021),
+            Some(NaiveDate::from_ymd(2021, 2, 22))
+        );
+        assert_eq!(
+            Day::Julian(60).date(2024),
+            Some(NaiveDate::from_ymd(2024, 3, 1))
+        );
+        assert_eq!(
+            Day::Ordinal(59).date(2024),
+            Some(NaiveDate::from_ymd(2024, 2, 29))
+        );
+    }
+
+    #[test]
+    fn test_offset_at() {
+        let rule = Rule::parse("EET-2EEST,M3.5.0/3,M10.5.0/4").unwrap();
+
+        assert_eq!(offset_at(&rule, (2021, 1, 1, 0, 0)), 7200);
+        assert_eq!(offset_at(&rule, (2021, 3, 28, 0, 59)), 7200);
+        assert_eq!(offset_at(&rule, (2021, 3, 28, 1, 0)), 10800);
+        assert_eq!(offset_at(&rule, (2021, 10, 31, 0, 59)), 10800);
+        assert_eq!(offset_at(&rule, (2021, 10, 31, 1, 0)), 7200);
+    }
+
+    #[test]
+    fn test_southern_offset_at() {
+        let rule = Rule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+
+        assert_eq!(offset_at(&rule, (2021, 1, 1, 0, 0)), 39600);
+        assert_eq!(offset_at(&rule, (2021, 6, 1, 0, 0)), 36000);
+        assert_eq!(offset_at(&rule, (2021, 12, 31, 0, 0)), 39600);
+    }
+}
@@ -0,0 +1,149 @@
+use std::convert::TryInto;
+
+use super::{rule::Rule, Zone};
+
+/// TZif file magic bytes
+const MAGIC: &[u8] = b"TZif";
+
+/// TZif header size in bytes
+const HEADER_SIZE: usize = 44;
+
+/// Max absolute value of UTC offset in seconds
+const MAX_OFFSET: i32 = 86_399;
+
+/// Counts of TZif data block items
+struct Header {
+    version: u8,
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Option<Header> {
+        if data.get(..MAGIC.len())? != MAGIC {
+            return None;
+        }
+
+        let count = |index: usize| {
+            let start = 20 + index * 4;
+            data.get(start..start + 4)
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(|bytes| u32::from_be_bytes(bytes) as usize)
+        };
+
+        Some(Header {
+            version: *data.get(4)?,
+            isutcnt: count(0)?,
+            isstdcnt: count(1)?,
+            leapcnt: count(2)?,
+            timecnt: count(3)?,
+            typecnt: count(4)?,
+            charcnt: count(5)?,
+        })
+    }
+
+    /// Size of data block, that follows header, with provided size of timestamps
+    fn data_size(&self, time_size: usize) -> usize {
+        self.timecnt * (time_size + 1)
+            + self.typecnt * 6
+            + self.charcnt
+            + self.leapcnt * (time_size + 4)
+            + self.isstdcnt
+            + self.isutcnt
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Option<&'a [u8]> {
+        if size > self.data.len() {
+            return None;
+        }
+
+        let (taken, rest) = self.data.split_at(size);
+        self.data = rest;
+        Some(taken)
+    }
+}
+
+/// Read 32-bit (version 1) or 64-bit (version 2+) timestamp
+fn read_time(bytes: &[u8]) -> Option<i64> {
+    match bytes.len() {
+        4 => Some(i64::from(i32::from_be_bytes(bytes.try_into().ok()?))),
+        8 => Some(i64::from_be_bytes(bytes.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// Parse TZif file (RFC 8536)
+///
+/// Version 2+ files are read with 64-bit data block and footer rule, version 1 files only with 32-bit data block.
+pub(super) fn parse(name: &str, data: &[u8]) -> Option<Zone> {
+    let header = Header::parse(data)?;
+
+    let (header, data, time_size) = if header.version >= b'2' {
+        let data = data.get(HEADER_SIZE + header.data_size(4)..)?;
+        (Header::parse(data)?, data.get(HEADER_SIZE..)?, 8)
+    } else {
+        (header, data.get(HEADER_SIZE..)?, 4)
+    };
+
+    let mut reader = Reader { data };
+
+    let times = reader
+        .take(header.timecnt * time_size)?
+        .chunks(time_size)
+        .map(read_time)
+        .collect::<Option<Vec<_>>>()?;
+
+    let indices = reader.take(header.timecnt)?;
+
+    let offsets = reader
+        .take(header.typecnt * 6)?
+        .chunks(6)
+        .map(|chunk| i32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect::<Vec<_>>();
+
+    if offsets.iter().any(|offset| offset.abs() > MAX_OFFSET) {
+        return None;
+    }
+
+    let transitions = times
+        .into_iter()
+        .zip(indices)
+        .map(|(time, index)| {
+            offsets
+                .get(usize::from(*index))
+                .map(|offset| (time, *offset))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let rule = if time_size == 8 {
+        reader.take(
+            header.charcnt + header.leapcnt * (time_size + 4) + header.isstdcnt + header.isutcnt,
+        )?;
+
+        // Footer is enclosed in newlines, and is empty if rule can't be expressed in POSIX format
+        std::str::from_utf8(reader.data)
+            .ok()
+            .map(|footer| footer.trim_matches('\n'))
+            .filter(|footer| !footer.is_empty())
+            .and_then(Rule::parse)
+    } else {
+        None
+    };
+
+    Some(Zone {
+        name: name.into(),
+        initial: *offsets.first()?,
+        transitions: transitions.into_boxed_slice(),
+        rule,
+    })
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::core::message::{
+    builder::DEFAULT_CONTENT_TYPE, Attributes, Message as CurrentMessage, Offset,
+    State as CurrentState, Status as CurrentStatus, Time as CurrentTime, Timeout as CurrentTimeout,
+};
+
+/// Date and time, stored as UTC timestamp in seconds and offset
+type LocalSeconds = (i64, i32);
+
+fn from_local_seconds((timestamp, offset): LocalSeconds) -> DateTime<FixedOffset> {
+    Utc.timestamp(timestamp, 0)
+        .with_timezone(&FixedOffset::east(offset))
+}
+
+/// Message status
+#[derive(Deserialize)]
+pub enum Status {
+    Available,
+    Transit,
+}
+
+impl From<Status> for CurrentStatus {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Available => CurrentStatus::Available,
+            Status::Transit => CurrentStatus::Transit,
+        }
+    }
+}
+
+/// Message state without receipt handle
+#[derive(Deserialize)]
+pub struct State {
+    pub status: Status,
+    pub tries: u32,
+    pub max_tries: u32,
+}
+
+impl From<State> for CurrentState {
+    /// Messages in transit are converted without receipt handle,
+    /// so they are returned to queue only after their timeout expires.
+    fn from(state: State) -> Self {
+        CurrentState {
+            status: state.status.into(),
+            tries: state.tries,
+            max_tries: state.max_tries,
+            receipt: None,
+        }
+    }
+}
+
+/// Message timeout options
+#[derive(Deserialize)]
+pub struct Timeout {
+    pub max: u32,
+    pub obtained_at: Option<LocalSeconds>,
+}
+
+impl From<Timeout> for CurrentTimeout {
+    fn from(timeout: Timeout) -> Self {
+        CurrentTimeout {
+            max: timeout.max,
+            obtained_at: timeout.obtained_at.map(from_local_seconds),
+        }
+    }
+}
+
+/// Message time with fixed offset and relative delay only
+#[derive(Deserialize)]
+pub struct Time {
+    pub offset: i32,
+    pub dispatched_at: LocalSeconds,
+    pub delay: Option<LocalSeconds>,
+    pub timeout: Timeout,
+}
+
+impl From<Time> for CurrentTime {
+    fn from(time: Time) -> Self {
+        CurrentTime {
+            offset: Offset(time.offset),
+            timezone: None,
+            dispatched_at: from_local_seconds(time.dispatched_at),
+            delay: time.delay.map(from_local_seconds),
+            deliver_at: None,
+            timeout: time.timeout.into(),
+        }
+    }
+}
+
+/// Message with text body and without attributes
+#[derive(Deserialize)]
+pub struct Message {
+    pub id: Uuid,
+    pub body: Box<str>,
+    pub state: State,
+    pub time: Time,
+}
+
+impl From<Message> for CurrentMessage {
+    /// Message gets default content type and priority, without deduplication ID and group.
+    fn from(message: Message) -> Self {
+        CurrentMessage {
+            id: message.id,
+            body: message.body.into_boxed_bytes(),
+            content_type: DEFAULT_CONTENT_TYPE.into(),
+            dedup_id: None,
+            group: None,
+            priority: 0,
+            attributes: Attributes::new(),
+            state: message.state.into(),
+            time: message.time.into(),
+        }
+    }
+}
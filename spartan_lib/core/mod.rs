@@ -1,3 +1,6 @@
+/// Time sources
+pub mod clock;
+
 /// Database interface and it's implementations
 pub mod db;
 
@@ -1,16 +1,16 @@
-use crate::core::payload::Identifiable;
+use crate::core::{clock::Clock, payload::Identifiable};
 
 /// Interface for working with dispatchable messages
 pub trait Dispatchable: Identifiable {
     /// Body type of dispatchable message
     type Body: ?Sized;
 
-    /// Check if current message is obtainable
+    /// Check if current message is obtainable at current time of clock
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::message::builder::MessageBuilder;
     /// use spartan_lib::core::payload::Dispatchable;
-    /// use spartan_lib::chrono::{Utc, Duration};
     ///
     /// let message = MessageBuilder::default().body("Hello, world").compose().unwrap();
     /// let delayed_message = MessageBuilder::default()
@@ -19,10 +19,12 @@ pub trait Dispatchable: Identifiable {
     ///     .compose()
     ///     .unwrap();
     ///
-    /// assert!(message.obtainable());
-    /// assert!(!delayed_message.obtainable());
+    /// assert!(message.obtainable(&SystemClock));
+    /// assert!(!delayed_message.obtainable(&SystemClock));
     /// ```
-    fn obtainable(&self) -> bool;
+    fn obtainable<C>(&self, clock: &C) -> bool
+    where
+        C: Clock;
 
     /// Get message body
     ///
@@ -32,29 +34,34 @@ pub trait Dispatchable: Identifiable {
     ///
     /// let message = MessageBuilder::default().body("Hello, world").compose().unwrap();
     ///
-    /// assert_eq!(message.body(), "Hello, world");
+    /// assert_eq!(message.body(), b"Hello, world");
     /// ```
     fn body(&self) -> &Self::Body;
 
-    /// Check if current message is garbage
+    /// Check if current message is garbage at current time of clock
     ///
     /// ```
+    /// use spartan_lib::chrono::Duration;
+    /// use spartan_lib::core::clock::MockClock;
     /// use spartan_lib::core::message::builder::MessageBuilder;
     /// use spartan_lib::core::payload::{Dispatchable, Status};
-    /// use std::thread::sleep;
-    /// use std::time::Duration;
     ///
+    /// let clock = MockClock::default();
     /// let mut message = MessageBuilder::default()
     ///     .body("Hello, world")
-    ///     .timeout(0)
-    ///     .compose()
+    ///     .timeout(30)
+    ///     .compose_with_clock(&clock)
     ///     .unwrap();
     ///
-    /// message.reserve();
+    /// message.reserve(&clock);
+    ///
+    /// assert!(!message.gc(&clock));
     ///
-    /// sleep(Duration::from_secs(2));
+    /// clock.advance(Duration::seconds(31));
     ///
-    /// assert!(message.gc());
+    /// assert!(message.gc(&clock));
     /// ```
-    fn gc(&self) -> bool;
+    fn gc<C>(&self, clock: &C) -> bool
+    where
+        C: Clock;
 }
@@ -0,0 +1,20 @@
+/// Interface for working with messages, that may belong to message group
+///
+/// Messages of the same group are dispatched one at a time, in push order
+pub trait Groupable {
+    /// Get message group
+    ///
+    /// ```
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::Groupable;
+    ///
+    /// let message = MessageBuilder::default()
+    ///     .body("Hello, world")
+    ///     .group("customer-1")
+    ///     .compose()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(message.group(), Some("customer-1"));
+    /// ```
+    fn group(&self) -> Option<&str>;
+}
@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+
 /// Interface for working with sortable messages
 pub trait Sortable {
     type Sort: Ord;
@@ -13,4 +15,18 @@ pub trait Sortable {
     /// dbg!(message.sort());
     /// ```
     fn sort(&self) -> Self::Sort;
+
+    /// Get time, until which message is delayed.
+    ///
+    /// Delayed messages are prioritized by sort key only after their delay expires.
+    ///
+    /// ```
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::Sortable;
+    ///
+    /// let message = MessageBuilder::default().body("Hello, world").delay(10).compose().unwrap();
+    ///
+    /// assert!(message.delayed_until().is_some());
+    /// ```
+    fn delayed_until(&self) -> Option<DateTime<Utc>>;
 }
@@ -1,4 +1,4 @@
-use crate::core::payload::Dispatchable;
+use crate::core::{clock::Clock, payload::Dispatchable};
 
 /// Interface for interacting with message status
 pub trait Status: Dispatchable {
@@ -16,17 +16,57 @@ pub trait Status: Dispatchable {
 
     /// Change message status to "in transit"
     ///
-    /// Also, default message implementation increments counter of tries
+    /// Also, default message implementation increments counter of tries, and starts message timeout from current time of clock
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::message::builder::MessageBuilder;
     /// use spartan_lib::core::payload::Status;
     ///
     /// let mut message = MessageBuilder::default().body("Hello, world").compose().unwrap();
     ///
-    /// message.reserve();
+    /// message.reserve(&SystemClock);
     /// ```
-    fn reserve(&mut self);
+    fn reserve<C>(&mut self, clock: &C)
+    where
+        C: Clock;
+
+    /// Extend reservation of message, that is "in transit"
+    ///
+    /// Default message implementation restarts message timeout from current time of clock with new max timeout in seconds
+    ///
+    /// ```
+    /// use spartan_lib::core::clock::SystemClock;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::Status;
+    ///
+    /// let mut message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    ///
+    /// message.reserve(&SystemClock);
+    /// message.touch(60, &SystemClock);
+    ///
+    /// assert_eq!(*message.time().timeout().max(), 60);
+    /// ```
+    fn touch<C>(&mut self, timeout: u32, clock: &C)
+    where
+        C: Clock;
+
+    /// Delay message dispatch for provided amount of seconds, starting from current time of clock
+    ///
+    /// ```
+    /// use spartan_lib::core::clock::SystemClock;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::{Dispatchable, Status};
+    ///
+    /// let mut message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    ///
+    /// message.delay(60, &SystemClock);
+    ///
+    /// assert!(!message.obtainable(&SystemClock));
+    /// ```
+    fn delay<C>(&mut self, delay: u32, clock: &C)
+    where
+        C: Clock;
 
     /// Check if message can be requeued
     ///
@@ -52,11 +92,29 @@ pub trait Status: Dispatchable {
     /// ```
     fn reservable(&self) -> bool;
 
+    /// Check if message reservation has expired at current time of clock
+    ///
+    /// ```
+    /// use spartan_lib::core::clock::SystemClock;
+    /// use spartan_lib::core::message::builder::MessageBuilder;
+    /// use spartan_lib::core::payload::Status;
+    ///
+    /// let mut message = MessageBuilder::default().body("Hello, world").compose().unwrap();
+    ///
+    /// message.reserve(&SystemClock);
+    ///
+    /// assert!(!message.expired(&SystemClock));
+    /// ```
+    fn expired<C>(&self, clock: &C) -> bool
+    where
+        C: Clock;
+
     /// Check if message has available tries
     ///
     /// This method was added to help [`TreeDatabase`] correctly identify if message can be reserved later.
     ///
     /// ```
+    /// use spartan_lib::core::clock::SystemClock;
     /// use spartan_lib::core::message::builder::MessageBuilder;
     /// use spartan_lib::core::payload::Status;
     ///
@@ -64,7 +122,7 @@ pub trait Status: Dispatchable {
     ///
     /// assert!(message.has_tries());
     ///
-    /// message.reserve();
+    /// message.reserve(&SystemClock);
     ///
     /// assert!(!message.has_tries());
     /// ```